@@ -1,11 +1,30 @@
 // Dummy program to demonstrate the memory location of a variable
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Statics live inside of the executable's image, so their offset from the start of it stays the
+// same on every run, even though the address of the image itself changes.
+static X: AtomicU32 = AtomicU32::new(4_u32);
 
 fn main() {
-    let x: u32 = 4_u32;
-    println!("Original x-value: {}", x);
-    println!("Memory location: &x: {}", &x as *const _ as usize);
+    println!("Original x-value: {}", X.load(Ordering::Relaxed));
+    print_location(&X as *const _ as usize);
+
+    while X.load(Ordering::Relaxed) == 4_u32 {}
+
+    println!("New x-value: {}", X.load(Ordering::Relaxed));
+}
 
-    while x == 4_u32 {}
+#[cfg(target_os = "linux")]
+fn print_location(location: usize) {
+    // The first mapping of a process is the start of its executable
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+    let base = usize::from_str_radix(maps.split('-').next().unwrap(), 16).unwrap();
+    println!("Memory location: &x: {:#x}, module offset: {:#x}", location, location - base);
+    println!("Pass the module offset to the example: example_desktop_memory {:#x}", location - base);
+}
 
-    println!("New x-value: {}", x);
-}
\ No newline at end of file
+#[cfg(not(target_os = "linux"))]
+fn print_location(location: usize) {
+    println!("Memory location: &x: {:#x}", location);
+    println!("Pass the memory location to the example: example_desktop_memory {:#x}", location);
+}
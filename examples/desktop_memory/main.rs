@@ -1,20 +1,31 @@
 use titanium::desktop::memory::*;
 
-const OFFSET: usize = 140725876420396;
 const APPNAME: &str = "dummy";
 
 const SET_TO: u32 = 6;
 
 fn main() {
+    // The offset printed by the dummy program, which changes whenever the dummy is rebuilt
+    let offset = std::env::args()
+        .nth(1)
+        .and_then(|offset| usize::from_str_radix(offset.trim_start_matches("0x"), 16).ok())
+        .expect("Pass the offset printed by the dummy program, such as 0x5c02c");
+
     // Injecting ourselves into the process handle (this process, you can also inject into other processes)
     let handle = get_handle(APPNAME).expect("Failed to get process handle");
 
-    // We make a `DataMember` that has an offset referring to its location in memory
-    let member = DataMember::new_offset(handle, vec![OFFSET]);
+    // On Linux, the dummy prints the offset of the variable in its executable, so we make a
+    // `DataMember` whose offset is relative to the start of it
+    #[cfg(target_os = "linux")]
+    let member = DataMember::new_module_offset(handle, APPNAME, vec![offset])
+        .expect("Failed to find the dummy's executable");
+    // Elsewhere, it prints the address of the variable itself
+    #[cfg(not(target_os = "linux"))]
+    let member = DataMember::new_offset(handle, vec![offset]);
     // The memory refered to is now the same
     println!(
-        "Memory location: &x: {}, member: {}",
-        OFFSET,
+        "Offset: {:#x}, member: {}",
+        offset,
         member.get_offset().expect("Failed to get member's offset")
     );
     // The value of the member is the same as the variable
@@ -27,4 +38,4 @@ fn main() {
 
     // We can write to and modify the value of the variable using the member
     member.write(&SET_TO).unwrap();
-}
\ No newline at end of file
+}
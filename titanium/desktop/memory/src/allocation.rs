@@ -0,0 +1,98 @@
+use crate::{Permissions, ProcessHandle};
+
+/// How far from the `near` hint an allocation can end, so that every byte of it can be reached
+/// with a rel32 jump or RIP-relative operand.
+pub(crate) const NEAR_RANGE: usize = 0x7fff_0000;
+
+/// Allocate `size` bytes of memory with the given permissions in another process, returning the
+/// address of the allocation. The memory is zeroed and is not freed when the handle is dropped.
+///
+/// If `near` is given, the allocation is placed within ±2 GiB of that address, so that code at
+/// `near` can reach it with a rel32 jump, which is what inline hooks and trampolines need.
+///
+/// On Linux the target runs `mmap` itself, through a temporary `ptrace` attachment, so this fails
+/// if another debugger is attached. Use [`AttachSession::allocate`] instead when already attached.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{allocate, free, get_handle, Permissions};
+/// let handle = get_handle("game").unwrap();
+/// let permissions = "rwxp".parse::<Permissions>().unwrap();
+/// let cave = allocate(&handle, 0x1000, permissions, Some(0x5555_5555_4000)).unwrap();
+/// // ...
+/// free(&handle, cave, 0x1000).unwrap();
+/// ```
+///
+/// # Errors
+/// Returns an error if the allocation fails, if nothing is free within range of `near`, or if
+/// remote allocation is not supported on this platform.
+///
+/// [`AttachSession::allocate`]: struct.AttachSession.html#method.allocate
+pub fn allocate(
+    handle: &ProcessHandle,
+    size: usize,
+    permissions: Permissions,
+    near: Option<usize>,
+) -> std::io::Result<usize> {
+    crate::platform::allocate(handle, size, permissions, near)
+}
+
+/// Free memory allocated with [`allocate`]. `size` must be the size that was allocated.
+///
+/// # Errors
+/// Returns an error if the memory cannot be freed, or if remote allocation is not supported on
+/// this platform.
+///
+/// [`allocate`]: fn.allocate.html
+pub fn free(handle: &ProcessHandle, address: usize, size: usize) -> std::io::Result<()> {
+    crate::platform::free(handle, address, size)
+}
+
+/// Change the permissions of the pages covering `len` bytes at `address` in another process,
+/// for example to make code writable before patching it.
+///
+/// # Errors
+/// Returns an error if the permissions cannot be changed, such as when part of the range is not
+/// mapped, or if this is not supported on this platform.
+pub fn protect(
+    handle: &ProcessHandle,
+    address: usize,
+    len: usize,
+    permissions: Permissions,
+) -> std::io::Result<()> {
+    crate::platform::protect(handle, address, len, permissions)
+}
+
+/// Find the addresses within range of `near` where `size` bytes could be allocated, closest
+/// first. `free` is every unused range of the address space as `(start, end)`, and every
+/// candidate is a multiple of `granularity`, which must be a power of two.
+pub(crate) fn near_candidates(
+    free: impl IntoIterator<Item = (usize, usize)>,
+    near: usize,
+    size: usize,
+    granularity: usize,
+) -> Vec<usize> {
+    let mut candidates: Vec<usize> = free
+        .into_iter()
+        .filter_map(|(start, end)| {
+            let first = start.checked_add(granularity - 1)? & !(granularity - 1);
+            let last = end.checked_sub(size)? & !(granularity - 1);
+            if first > last {
+                return None;
+            }
+            let candidate = (near & !(granularity - 1)).clamp(first, last);
+            let reachable = candidate.abs_diff(near) <= NEAR_RANGE
+                && (candidate + size).abs_diff(near) <= NEAR_RANGE;
+            reachable.then_some(candidate)
+        })
+        .collect();
+    candidates.sort_by_key(|candidate| candidate.abs_diff(near));
+    candidates
+}
+
+/// The error returned when nothing is free within range of `near`.
+pub(crate) fn nothing_near(near: usize) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::OutOfMemory,
+        format!("No free memory within 2 GiB of {near:#x}"),
+    )
+}
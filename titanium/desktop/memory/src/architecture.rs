@@ -1,98 +1,254 @@
 
 
-use std::convert::TryInto;
-
-/// Enum representing the architecture of a process
-#[derive(Clone, Debug, Copy)]
-#[repr(u8)]
-pub enum Architecture {
-    /// 8-bit architecture
-    #[cfg(any(
-        target_pointer_width = "8",
-        target_pointer_width = "16",
-        target_pointer_width = "32",
-        target_pointer_width = "64",
-        target_pointer_width = "128"
-    ))]
-    Arch8Bit = 1,
-    /// 16-bit architecture
-    #[cfg(any(
-        target_pointer_width = "16",
-        target_pointer_width = "32",
-        target_pointer_width = "64",
-        target_pointer_width = "128"
-    ))]
-    Arch16Bit = 2,
-    /// 32-bit architecture
-    #[cfg(any(
-        target_pointer_width = "32",
-        target_pointer_width = "64",
-        target_pointer_width = "128"
-    ))]
-    Arch32Bit = 4,
-    /// 64-bit architecture
-    #[cfg(any(target_pointer_width = "64", target_pointer_width = "128"))]
-    Arch64Bit = 8,
-    /// 128-bit architecture
-    #[cfg(target_pointer_width = "128")]
-    Arch128Bit = 16,
+/// The order of the bytes in the integers and pointers of a process
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    /// The least significant byte comes first, as on x86 and most ARM systems
+    Little,
+    /// The most significant byte comes first
+    Big,
 }
 
+impl Endianness {
+    /// The byte order of the host process
+    pub const NATIVE: Self = if cfg!(target_endian = "big") {
+        Self::Big
+    } else {
+        Self::Little
+    };
+}
+
+/// The architecture of a process: how wide its pointers are, and the order of their bytes.
+///
+/// This describes the target, not the host, so a 64-bit tool can read a 32-bit or big-endian
+/// process. Handles detect it from the header of the executable when they are created where the
+/// platform allows, and [`ProcessHandleExt::set_arch`] overrides it. Pointers that are wider
+/// than the host's `usize` are truncated when they are read.
+///
+/// [`ProcessHandleExt::set_arch`]: trait.ProcessHandleExt.html#tymethod.set_arch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Architecture {
+    pointer_size: u8,
+    endianness: Endianness,
+}
+
+#[allow(non_upper_case_globals)]
 impl Architecture {
+    /// 8-bit architecture with the byte order of the host
+    pub const Arch8Bit: Self = Self::sized(1);
+    /// 16-bit architecture with the byte order of the host
+    pub const Arch16Bit: Self = Self::sized(2);
+    /// 32-bit architecture with the byte order of the host
+    pub const Arch32Bit: Self = Self::sized(4);
+    /// 64-bit architecture with the byte order of the host
+    pub const Arch64Bit: Self = Self::sized(8);
+    /// 128-bit architecture with the byte order of the host
+    pub const Arch128Bit: Self = Self::sized(16);
+
+    const fn sized(pointer_size: u8) -> Self {
+        Self {
+            pointer_size,
+            endianness: Endianness::NATIVE,
+        }
+    }
+
     /// Create an Architecture matching that of the host process.
     #[must_use]
     pub fn from_native() -> Architecture {
-        #[cfg(target_pointer_width = "8")]
-        return Architecture::Arch8Bit;
-        #[cfg(target_pointer_width = "16")]
-        return Architecture::Arch16Bit;
-        #[cfg(target_pointer_width = "32")]
-        return Architecture::Arch32Bit;
-        #[cfg(target_pointer_width = "64")]
-        return Architecture::Arch64Bit;
-        #[cfg(target_pointer_width = "128")]
-        return Architecture::Arch128Bit;
+        #[allow(clippy::cast_possible_truncation)]
+        Self::sized(std::mem::size_of::<usize>() as u8)
+    }
+
+    /// An architecture with pointers of `pointer_size` bytes, which has to be 1, 2, 4, 8 or 16.
+    #[must_use]
+    pub fn new(pointer_size: usize, endianness: Endianness) -> Option<Architecture> {
+        match pointer_size {
+            #[allow(clippy::cast_possible_truncation)]
+            1 | 2 | 4 | 8 | 16 => Some(Self {
+                pointer_size: pointer_size as u8,
+                endianness,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The same architecture with another byte order.
+    #[must_use]
+    pub fn with_endianness(self, endianness: Endianness) -> Architecture {
+        Self { endianness, ..self }
+    }
+
+    /// The number of bytes in a pointer.
+    #[must_use]
+    pub fn pointer_size(self) -> usize {
+        usize::from(self.pointer_size)
+    }
+
+    /// The order of the bytes in a pointer.
+    #[must_use]
+    pub fn endianness(self) -> Endianness {
+        self.endianness
+    }
+
+    /// Read the architecture from the identification bytes at the start of an ELF file.
+    #[must_use]
+    pub fn from_elf_header(header: &[u8]) -> Option<Architecture> {
+        if header.get(..4)? != b"\x7fELF" {
+            return None;
+        }
+        let pointer_size = match header.get(4)? {
+            1 => 4,
+            2 => 8,
+            _ => return None,
+        };
+        let endianness = match header.get(5)? {
+            1 => Endianness::Little,
+            2 => Endianness::Big,
+            _ => return None,
+        };
+        Self::new(pointer_size, endianness)
+    }
+
+    /// Read the architecture from the start of a PE image, such as a Windows executable run by
+    /// Wine. `image` has to reach the magic of the optional header.
+    #[must_use]
+    pub fn from_pe_header(image: &[u8]) -> Option<Architecture> {
+        if image.get(..2)? != b"MZ" {
+            return None;
+        }
+        let pe = u32::from_le_bytes(image.get(0x3c..0x40)?.try_into().ok()?) as usize;
+        if image.get(pe..pe + 4)? != b"PE\0\0" {
+            return None;
+        }
+        // The optional header follows the 4 byte signature and the 20 byte file header.
+        let magic = u16::from_le_bytes(image.get(pe + 24..pe + 26)?.try_into().ok()?);
+        let pointer_size = match magic {
+            0x10b => 4,
+            0x20b => 8,
+            _ => return None,
+        };
+        Self::new(pointer_size, Endianness::Little)
     }
 
     /// Convert bytes read from memory into a pointer in the
     /// current architecture.
     ///
+    /// Despite the name, the bytes are read in the byte order of the architecture, which is only
+    /// the host's by default.
+    ///
     /// # Panics
-    /// If there are not enough bytes in the slice to make an integer of the sized indicated by
-    /// `self`.
+    /// If the slice is not exactly the size of a pointer in `self`.
+    #[allow(clippy::cast_possible_truncation)]
     #[must_use]
     pub fn pointer_from_ne_bytes(self, bytes: &[u8]) -> usize {
-        match self {
-            #[allow(clippy::cast_possible_truncation)]
-            #[cfg(any(
-                target_pointer_width = "8",
-                target_pointer_width = "16",
-                target_pointer_width = "32",
-                target_pointer_width = "64",
-                target_pointer_width = "128"
-            ))]
-            Architecture::Arch8Bit => u8::from_ne_bytes(bytes.try_into().unwrap()) as usize,
-            #[allow(clippy::cast_possible_truncation)]
-            #[cfg(any(
-                target_pointer_width = "16",
-                target_pointer_width = "32",
-                target_pointer_width = "64",
-                target_pointer_width = "128"
-            ))]
-            Architecture::Arch16Bit => u16::from_ne_bytes(bytes.try_into().unwrap()) as usize,
-            #[allow(clippy::cast_possible_truncation)]
-            #[cfg(any(
-                target_pointer_width = "32",
-                target_pointer_width = "64",
-                target_pointer_width = "128"
-            ))]
-            Architecture::Arch32Bit => u32::from_ne_bytes(bytes.try_into().unwrap()) as usize,
-            #[allow(clippy::cast_possible_truncation)]
-            #[cfg(any(target_pointer_width = "64", target_pointer_width = "128"))]
-            Architecture::Arch64Bit => u64::from_ne_bytes(bytes.try_into().unwrap()) as usize,
-            #[allow(clippy::cast_possible_truncation)]
-            #[cfg(target_pointer_width = "128")]
-            Architecture::Arch128Bit => u128::from_ne_bytes(bytes.try_into().unwrap()) as usize,
+        assert_eq!(
+            bytes.len(),
+            self.pointer_size(),
+            "A pointer in {self:?} is {} bytes long",
+            self.pointer_size()
+        );
+        let fold = |pointer: u128, byte: &u8| pointer << 8 | u128::from(*byte);
+        let pointer = match self.endianness {
+            Endianness::Little => bytes.iter().rev().fold(0, fold),
+            Endianness::Big => bytes.iter().fold(0, fold),
+        };
+        pointer as usize
+    }
+
+    /// Write a pointer into the first [`pointer_size`] bytes of `bytes`, in the byte order of the
+    /// architecture.
+    ///
+    /// # Panics
+    /// If the slice is shorter than a pointer in `self`.
+    ///
+    /// [`pointer_size`]: #method.pointer_size
+    pub fn write_pointer(self, pointer: usize, bytes: &mut [u8]) {
+        let size = self.pointer_size();
+        let pointer = (pointer as u128).to_le_bytes();
+        let bytes = &mut bytes[..size];
+        bytes.copy_from_slice(&pointer[..size]);
+        if self.endianness == Endianness::Big {
+            bytes.reverse();
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_elf_identification() {
+        let little = Architecture::from_elf_header(b"\x7fELF\x02\x01\x01\0").unwrap();
+        assert_eq!(little.pointer_size(), 8);
+        assert_eq!(little.endianness(), Endianness::Little);
+        let big = Architecture::from_elf_header(b"\x7fELF\x01\x02").unwrap();
+        assert_eq!(big.pointer_size(), 4);
+        assert_eq!(big.endianness(), Endianness::Big);
+
+        assert_eq!(Architecture::from_elf_header(b"\x7fELF\x03\x01"), None);
+        assert_eq!(Architecture::from_elf_header(b"\x7fELF\x02\x03"), None);
+        assert_eq!(Architecture::from_elf_header(b"\x7fELF\x02"), None);
+        assert_eq!(Architecture::from_elf_header(b"MZ\x90\0\x02\x01"), None);
+    }
+
+    #[test]
+    fn reads_pe_optional_header_magic() {
+        let mut image = vec![0_u8; 0x9a];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&0x80_u32.to_le_bytes());
+        image[0x80..0x84].copy_from_slice(b"PE\0\0");
+        image[0x98..0x9a].copy_from_slice(&0x20b_u16.to_le_bytes());
+        assert_eq!(
+            Architecture::from_pe_header(&image),
+            Architecture::new(8, Endianness::Little)
+        );
+        image[0x98..0x9a].copy_from_slice(&0x10b_u16.to_le_bytes());
+        assert_eq!(
+            Architecture::from_pe_header(&image),
+            Architecture::new(4, Endianness::Little)
+        );
+
+        assert_eq!(Architecture::from_pe_header(&image[..0x99]), None);
+        image[0x98..0x9a].copy_from_slice(&0x107_u16.to_le_bytes());
+        assert_eq!(Architecture::from_pe_header(&image), None);
+        image[0x80] = b'N';
+        assert_eq!(Architecture::from_pe_header(&image), None);
+    }
+
+    #[test]
+    fn reads_pointers_in_either_byte_order() {
+        let bytes = [0x12, 0x34, 0x56, 0x78];
+        let little = Architecture::new(4, Endianness::Little).unwrap();
+        let big = little.with_endianness(Endianness::Big);
+        assert_eq!(little.pointer_from_ne_bytes(&bytes), 0x7856_3412);
+        assert_eq!(big.pointer_from_ne_bytes(&bytes), 0x1234_5678);
+        let short = Architecture::new(2, Endianness::Big).unwrap();
+        assert_eq!(short.pointer_from_ne_bytes(&bytes[..2]), 0x1234);
+
+        for arch in [little, big, Architecture::from_native()] {
+            let mut written = [0xff_u8; 9];
+            arch.write_pointer(0x0bad_cafe, &mut written);
+            assert_eq!(arch.pointer_from_ne_bytes(&written[..arch.pointer_size()]), 0x0bad_cafe);
+            assert_eq!(written[arch.pointer_size()..], [0xff; 9][arch.pointer_size()..]);
+        }
+        assert_eq!(
+            Architecture::from_native().pointer_from_ne_bytes(&0x0bad_cafe_usize.to_ne_bytes()),
+            0x0bad_cafe
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "is 4 bytes long")]
+    fn rejects_pointers_of_the_wrong_size() {
+        let arch = Architecture::new(4, Endianness::Little).unwrap();
+        let _ = arch.pointer_from_ne_bytes(&[0; 8]);
+    }
+
+    #[test]
+    fn rejects_unsupported_pointer_sizes() {
+        assert_eq!(Architecture::new(3, Endianness::Little), None);
+        assert_eq!(Architecture::new(0, Endianness::Big), None);
+        assert_eq!(Architecture::Arch64Bit.pointer_size(), 8);
+    }
+}
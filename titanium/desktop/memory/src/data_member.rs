@@ -31,7 +31,7 @@ use crate::{CopyAddress, Memory, ProcessHandle, PutAddress};
 pub struct DataMember<T> {
     offsets: Vec<usize>,
     process: ProcessHandle,
-    _phantom: std::marker::PhantomData<*mut T>,
+    _phantom: std::marker::PhantomData<fn() -> T>,
 }
 
 impl<T: Sized + Copy> DataMember<T> {
@@ -72,6 +72,53 @@ impl<T: Sized + Copy> DataMember<T> {
             _phantom: std::marker::PhantomData,
         }
     }
+
+    /// Create a new `DataMember` whose first offset is relative to the base of a loaded module,
+    /// such as `libgame.so` or the game's executable. The base is resolved once, when the
+    /// `DataMember` is created, so this needs to be called again if the process restarts.
+    ///
+    /// Static pointers are usually written as `module + offset`, and this allows them to be used
+    /// without hardcoding an address that changes every time the process is started.
+    ///
+    /// # Errors
+    /// Returns an error if the module cannot be found in the process.
+    pub fn new_module_offset(
+        handle: ProcessHandle,
+        module: &str,
+        mut offsets: Vec<usize>,
+    ) -> std::io::Result<Self> {
+        let base = crate::module_base(&handle, module)?;
+        match offsets.first_mut() {
+            Some(first) => *first = first.wrapping_add(base),
+            None => offsets.push(base),
+        }
+        Ok(Self::new_offset(handle, offsets))
+    }
+
+    /// The offsets of the member.
+    pub(crate) fn offsets(&self) -> &[usize] {
+        &self.offsets
+    }
+
+    /// Read the value through another [`CopyAddress`], such as a [`ReadCache`], instead of
+    /// straight from the process. The offsets are followed through `source` as well.
+    ///
+    /// # Safety
+    /// The same as [`Memory::read`]: the bytes read must be valid for a `T`.
+    ///
+    /// # Errors
+    /// Returns an error if copying memory fails or if a null pointer dereference would
+    /// otherwise occur.
+    ///
+    /// [`CopyAddress`]: trait.CopyAddress.html
+    /// [`ReadCache`]: struct.ReadCache.html
+    /// [`Memory::read`]: trait.Memory.html#tymethod.read
+    pub unsafe fn read_from<S: CopyAddress + ?Sized>(&self, source: &S) -> std::io::Result<T> {
+        let offset = source.get_offset(&self.offsets)?;
+        let mut buffer = vec![0_u8; std::mem::size_of::<T>()];
+        source.copy_address(offset, &mut buffer)?;
+        Ok(buffer.as_ptr().cast::<T>().read_unaligned())
+    }
 }
 
 impl<T: Sized + Copy> Memory<T> for DataMember<T> {
@@ -0,0 +1,327 @@
+use iced_x86::{
+    BlockEncoder, BlockEncoderOptions, Code, Decoder, DecoderError, DecoderOptions, FlowControl,
+    Instruction, InstructionBlock,
+};
+
+use crate::{
+    CopyAddress, LocalProcess, Permissions, ProcessHandle, ProtectAddress, PutAddress,
+};
+
+/// The size of the memory needed for a trampoline.
+pub const TRAMPOLINE_SIZE: usize = 256;
+/// The length of `jmp rel32`.
+const JMP_REL32_LEN: usize = 5;
+/// The length of `jmp [rip]` followed by the 8-byte address it jumps to.
+const JMP_ABS_LEN: usize = 14;
+/// The longest an x86-64 instruction can be.
+const MAX_INSTRUCTION_LEN: usize = 15;
+const NOP: u8 = 0x90;
+const PAGE_SIZE: usize = 0x1000;
+
+/// The error type for [`Detour`]
+#[derive(Debug, thiserror::Error)]
+pub enum DetourError {
+    /// An instruction in the prologue of the function could not be decoded
+    #[error("Invalid instruction at {address:#x}")]
+    InvalidInstruction {
+        /// The address of the instruction
+        address: usize,
+    },
+    /// The function ends before there is room for the jump to the handler
+    #[error("Function at {address:#x} is too small to hook")]
+    FunctionTooSmall {
+        /// The address of the function
+        address: usize,
+    },
+    /// The stolen instructions could not be moved to the trampoline, usually because a
+    /// RIP-relative operand is out of range of it
+    #[error("Relocating instructions failed: {0}")]
+    RelocationFailed(String),
+    /// The code at the function changed since the detour was created
+    #[error("Function at {address:#x} was modified by something else")]
+    FunctionModified {
+        /// The address of the function
+        address: usize,
+    },
+    /// Reading, writing or protecting memory failed
+    #[error("Reading or writing process memory failed")]
+    Io(#[from] std::io::Error),
+}
+
+/// Returns `true` if `to` can be reached with a rel32 displacement from an instruction ending at
+/// `from`.
+fn rel32_reachable(from: usize, to: usize) -> bool {
+    i32::try_from(to.wrapping_sub(from) as isize).is_ok()
+}
+
+/// Write code, making its pages writable for the duration of the write.
+///
+/// Each page is changed on its own, so that pages with different permissions each get their own
+/// back, and every page that was changed is restored even if another one fails.
+pub(crate) fn write_code<T: PutAddress + ProtectAddress>(
+    target: &T,
+    address: usize,
+    bytes: &[u8],
+) -> std::io::Result<()> {
+    let writable = Permissions {
+        read: true,
+        write: true,
+        execute: true,
+        shared: false,
+    };
+    let end = address + bytes.len();
+    let mut result = Ok(());
+    let mut changed = Vec::new();
+    let mut start = address;
+    while start < end {
+        let next = ((start & !(PAGE_SIZE - 1)) + PAGE_SIZE).min(end);
+        match target.protect_address(start, next - start, writable) {
+            Ok(previous) => changed.push((start, next - start, previous)),
+            Err(error) => {
+                result = Err(error);
+                break;
+            }
+        }
+        start = next;
+    }
+    if result.is_ok() {
+        result = target.put_address(address, bytes);
+    }
+    for (start, len, previous) in changed {
+        let restored = target.protect_address(start, len, previous);
+        if result.is_ok() {
+            result = restored.map(drop);
+        }
+    }
+    result
+}
+
+/// An inline hook on an x86-64 function, which makes every call to the function go to a handler
+/// instead.
+///
+/// The first instructions of the function are overwritten with a jump to the handler, and are
+/// moved to a trampoline, followed by a jump back to the rest of the function. The handler can
+/// call [`Detour::trampoline`] to run the original function. Branches and RIP-relative operands in
+/// the moved instructions are rewritten for their new address, so the trampoline should be within
+/// ±2 GiB of the function.
+///
+/// `Detour` works through [`CopyAddress`], [`PutAddress`] and [`ProtectAddress`], so the same
+/// code hooks a function in another process through a [`ProcessHandle`] or an [`AttachSession`],
+/// or in the current process through [`LocalProcess`]. The original bytes are restored when the
+/// `Detour` is dropped, but the trampoline is never freed, as a thread may still be running it.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, Detour};
+/// let handle = get_handle("game").unwrap();
+/// // `handler` is code written to a cave, or a function in an injected library
+/// let (function, handler) = (0x1234, 0x5678);
+/// let mut detour = Detour::hook(handle, function, handler).unwrap();
+/// detour.enable().unwrap();
+/// // The handler calls `detour.trampoline()` to run the original function
+/// detour.disable().unwrap();
+/// ```
+///
+/// Writing the jump is not atomic, so a thread running the first instructions of the function
+/// while it is enabled or disabled could crash. Stopping the target with an [`AttachSession`]
+/// first avoids this.
+///
+/// [`CopyAddress`]: trait.CopyAddress.html
+/// [`PutAddress`]: trait.PutAddress.html
+/// [`ProtectAddress`]: trait.ProtectAddress.html
+/// [`ProcessHandle`]: type.ProcessHandle.html
+/// [`AttachSession`]: struct.AttachSession.html
+/// [`LocalProcess`]: struct.LocalProcess.html
+#[derive(Debug)]
+pub struct Detour<T: CopyAddress + PutAddress + ProtectAddress> {
+    target: T,
+    function: usize,
+    original: Vec<u8>,
+    patch: Vec<u8>,
+    trampoline: usize,
+    enabled: bool,
+}
+
+impl<T: CopyAddress + PutAddress + ProtectAddress> Detour<T> {
+    /// Create a disabled detour from `function` to `handler`, building the trampoline in
+    /// [`TRAMPOLINE_SIZE`] bytes of executable memory at `trampoline`.
+    ///
+    /// # Errors
+    /// Returns an error if the prologue of the function cannot be decoded or relocated, or if
+    /// memory cannot be read or written.
+    ///
+    /// [`TRAMPOLINE_SIZE`]: constant.TRAMPOLINE_SIZE.html
+    pub fn new(
+        target: T,
+        function: usize,
+        handler: usize,
+        trampoline: usize,
+    ) -> Result<Self, DetourError> {
+        // The jump in the function goes to a copy of the jump to the handler at the start of
+        // the trampoline if it can, as that only needs five bytes.
+        let near = rel32_reachable(function + JMP_REL32_LEN, trampoline);
+        let patch_len = if near { JMP_REL32_LEN } else { JMP_ABS_LEN };
+
+        let mut code = vec![0_u8; JMP_ABS_LEN + MAX_INSTRUCTION_LEN];
+        if target.copy_address(function, &mut code).is_err() {
+            // The function may end close to the end of its mapping, which is on a page boundary.
+            code.truncate(PAGE_SIZE - function % PAGE_SIZE);
+            target.copy_address(function, &mut code)?;
+        }
+        let mut decoder = Decoder::with_ip(64, &code, function as u64, DecoderOptions::NONE);
+        let mut instructions = vec![Instruction::with_branch(Code::Jmp_rel32_64, handler as u64)
+            .map_err(|error| DetourError::RelocationFailed(error.to_string()))?];
+        let mut stolen = 0;
+        while stolen < patch_len {
+            let instruction = decoder.decode();
+            if decoder.last_error() == DecoderError::NoMoreBytes {
+                return Err(DetourError::FunctionTooSmall { address: function });
+            }
+            if instruction.is_invalid() {
+                return Err(DetourError::InvalidInstruction {
+                    address: function + stolen,
+                });
+            }
+            stolen += instruction.len();
+            instructions.push(instruction);
+            let ends = matches!(
+                instruction.flow_control(),
+                FlowControl::Return | FlowControl::UnconditionalBranch | FlowControl::IndirectBranch
+            );
+            if ends && stolen < patch_len {
+                return Err(DetourError::FunctionTooSmall { address: function });
+            }
+        }
+        instructions.push(
+            Instruction::with_branch(Code::Jmp_rel32_64, (function + stolen) as u64)
+                .map_err(|error| DetourError::RelocationFailed(error.to_string()))?,
+        );
+
+        let encoded = BlockEncoder::encode(
+            64,
+            InstructionBlock::new(&instructions, trampoline as u64),
+            BlockEncoderOptions::RETURN_NEW_INSTRUCTION_OFFSETS,
+        )
+        .map_err(|error| DetourError::RelocationFailed(error.to_string()))?;
+        if encoded.code_buffer.len() > TRAMPOLINE_SIZE {
+            return Err(DetourError::RelocationFailed(String::from(
+                "Trampoline is too large",
+            )));
+        }
+        write_code(&target, trampoline, &encoded.code_buffer)?;
+
+        let mut patch = Vec::with_capacity(stolen);
+        if near {
+            patch.push(0xe9);
+            #[allow(clippy::cast_possible_truncation)]
+            let displacement = trampoline.wrapping_sub(function + JMP_REL32_LEN) as u32;
+            patch.extend_from_slice(&displacement.to_le_bytes());
+        } else {
+            patch.extend_from_slice(&[0xff, 0x25, 0, 0, 0, 0]);
+            patch.extend_from_slice(&(handler as u64).to_le_bytes());
+        }
+        patch.resize(stolen, NOP);
+
+        Ok(Self {
+            target,
+            function,
+            original: code[..stolen].to_vec(),
+            patch,
+            trampoline: trampoline + encoded.new_instruction_offsets[1] as usize,
+            enabled: false,
+        })
+    }
+
+    /// Write the jump to the handler over the start of the function.
+    ///
+    /// # Errors
+    /// Returns an error if the start of the function was changed since the detour was created,
+    /// or if it cannot be written.
+    pub fn enable(&mut self) -> Result<(), DetourError> {
+        if self.enabled {
+            return Ok(());
+        }
+        let mut current = vec![0_u8; self.original.len()];
+        self.target.copy_address(self.function, &mut current)?;
+        if current != self.original {
+            return Err(DetourError::FunctionModified {
+                address: self.function,
+            });
+        }
+        write_code(&self.target, self.function, &self.patch)?;
+        self.enabled = true;
+        Ok(())
+    }
+
+    /// Restore the original start of the function.
+    ///
+    /// # Errors
+    /// Returns an error if the function cannot be written.
+    pub fn disable(&mut self) -> Result<(), DetourError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        write_code(&self.target, self.function, &self.original)?;
+        self.enabled = false;
+        Ok(())
+    }
+
+    /// Returns `true` if calls to the function go to the handler.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The address of the hooked function.
+    #[must_use]
+    pub fn function(&self) -> usize {
+        self.function
+    }
+
+    /// The address to call to run the original function, which stays valid after the detour is
+    /// disabled or dropped.
+    #[must_use]
+    pub fn trampoline(&self) -> usize {
+        self.trampoline
+    }
+}
+
+impl Detour<ProcessHandle> {
+    /// Create a disabled detour in another process, allocating the trampoline near the function
+    /// with [`allocate`].
+    ///
+    /// # Errors
+    /// Returns an error if the trampoline cannot be allocated, or if [`Detour::new`] fails.
+    ///
+    /// [`allocate`]: fn.allocate.html
+    pub fn hook(handle: ProcessHandle, function: usize, handler: usize) -> Result<Self, DetourError> {
+        let trampoline = crate::allocate(&handle, TRAMPOLINE_SIZE, executable(), Some(function))?;
+        Self::new(handle, function, handler, trampoline)
+    }
+}
+
+impl Detour<LocalProcess> {
+    /// Create a disabled detour in the current process, allocating the trampoline near the
+    /// function.
+    ///
+    /// # Errors
+    /// Returns an error if the trampoline cannot be allocated, or if [`Detour::new`] fails.
+    pub fn hook_local(function: usize, handler: usize) -> Result<Self, DetourError> {
+        let trampoline = LocalProcess.allocate(TRAMPOLINE_SIZE, executable(), Some(function))?;
+        Self::new(LocalProcess, function, handler, trampoline)
+    }
+}
+
+fn executable() -> Permissions {
+    Permissions {
+        read: true,
+        execute: true,
+        ..Permissions::default()
+    }
+}
+
+/// Restore the original start of the function.
+impl<T: CopyAddress + PutAddress + ProtectAddress> Drop for Detour<T> {
+    fn drop(&mut self) {
+        let _ = self.disable();
+    }
+}
@@ -0,0 +1,498 @@
+use crate::CopyAddress;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+pub(crate) const DT_NULL: i64 = 0;
+pub(crate) const DT_PLTRELSZ: i64 = 2;
+pub(crate) const DT_HASH: i64 = 4;
+pub(crate) const DT_STRTAB: i64 = 5;
+pub(crate) const DT_SYMTAB: i64 = 6;
+pub(crate) const DT_RELA: i64 = 7;
+pub(crate) const DT_RELASZ: i64 = 8;
+pub(crate) const DT_RELAENT: i64 = 9;
+pub(crate) const DT_STRSZ: i64 = 10;
+pub(crate) const DT_SYMENT: i64 = 11;
+pub(crate) const DT_PLTREL: i64 = 20;
+pub(crate) const DT_JMPREL: i64 = 23;
+pub(crate) const DT_GNU_HASH: i64 = 0x6fff_fef5;
+
+pub(crate) const R_X86_64_64: u32 = 1;
+pub(crate) const R_X86_64_GLOB_DAT: u32 = 6;
+pub(crate) const R_X86_64_JUMP_SLOT: u32 = 7;
+
+/// The size of an `Elf64_Sym`.
+const SYMBOL_SIZE: usize = 24;
+/// The size of an `Elf64_Rela`.
+const RELA_SIZE: usize = 24;
+/// The most dynamic entries read before giving up on finding `DT_NULL`.
+const MAX_DYNAMIC_ENTRIES: usize = 4096;
+/// The most program headers read from an object.
+const MAX_PROGRAM_HEADERS: usize = 1024;
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(value)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(value)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn read_usize(bytes: &[u8], offset: usize) -> usize {
+    read_u64(bytes, offset) as usize
+}
+
+/// A symbol from the dynamic symbol table of a loaded ELF object.
+#[derive(Clone, Debug)]
+pub(crate) struct Symbol {
+    pub(crate) name: String,
+    /// The address of the symbol in the process, or `0` if it is imported from another object.
+    pub(crate) address: usize,
+}
+
+/// A relocation with an addend from a loaded ELF object.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Relocation {
+    /// The address in the process that the relocation writes to.
+    pub(crate) address: usize,
+    /// The type of the relocation, such as `R_X86_64_JUMP_SLOT`.
+    pub(crate) kind: u32,
+    /// The index of the symbol in the dynamic symbol table.
+    pub(crate) symbol: usize,
+}
+
+/// The dynamic section of a 64-bit little-endian ELF object that has been loaded into a process,
+/// read through a [`CopyAddress`].
+#[derive(Debug)]
+pub(crate) struct DynamicObject<'a, T: CopyAddress> {
+    source: &'a T,
+    /// The difference between the addresses in the object's headers and where it was loaded.
+    pub(crate) bias: usize,
+    /// The first address of the object.
+    start: usize,
+    /// The address one past the end of the last loadable segment of the object.
+    end: usize,
+    dynamic: Vec<(i64, usize)>,
+}
+
+impl<'a, T: CopyAddress> DynamicObject<'a, T> {
+    /// Read the dynamic section of the object whose first mapping starts at `base`.
+    pub(crate) fn parse(source: &'a T, base: usize) -> std::io::Result<Self> {
+        let mut header = [0_u8; 64];
+        source.copy_address(base, &mut header)?;
+        if header[..4] != *b"\x7fELF" {
+            return Err(invalid_data("Not an ELF object"));
+        }
+        if header[4] != 2 || header[5] != 1 {
+            return Err(invalid_data("Only 64-bit little-endian ELF objects are supported"));
+        }
+        let program_offset = read_usize(&header, 32);
+        let program_size = usize::from(read_u16(&header, 54));
+        let program_count = usize::from(read_u16(&header, 56));
+        if program_size < 56 {
+            return Err(invalid_data("Invalid program header size"));
+        }
+        if program_count > MAX_PROGRAM_HEADERS {
+            return Err(invalid_data("Too many program headers"));
+        }
+        let mut programs = vec![0_u8; program_size * program_count];
+        source.copy_address(base + program_offset, &mut programs)?;
+
+        let mut first_load = None;
+        let mut last_load_end = 0_usize;
+        let mut dynamic_address = None;
+        for program in programs.chunks_exact(program_size) {
+            let address = read_usize(program, 16);
+            match read_u32(program, 0) {
+                PT_LOAD => {
+                    first_load.get_or_insert(address & !0xfff);
+                    let end = address
+                        .checked_add(read_usize(program, 40))
+                        .ok_or_else(|| invalid_data("Invalid loadable segment"))?;
+                    last_load_end = last_load_end.max(end);
+                }
+                PT_DYNAMIC => dynamic_address = Some(address),
+                _ => {}
+            }
+        }
+        let first_load = first_load.ok_or_else(|| invalid_data("No loadable segment"))?;
+        let bias = base.wrapping_sub(first_load);
+        let end = base
+            .checked_add(last_load_end.saturating_sub(first_load))
+            .ok_or_else(|| invalid_data("Invalid loadable segment"))?;
+        let dynamic_address = dynamic_address
+            .ok_or_else(|| invalid_data("No dynamic section"))?
+            .wrapping_add(bias);
+
+        let mut dynamic = Vec::new();
+        let mut entry = [0_u8; 16];
+        for index in 0..MAX_DYNAMIC_ENTRIES {
+            source.copy_address(dynamic_address + index * entry.len(), &mut entry)?;
+            #[allow(clippy::cast_possible_wrap)]
+            let tag = read_u64(&entry, 0) as i64;
+            if tag == DT_NULL {
+                return Ok(Self {
+                    source,
+                    bias,
+                    start: base,
+                    end,
+                    dynamic,
+                });
+            }
+            dynamic.push((tag, read_usize(&entry, 8)));
+        }
+        Err(invalid_data("Unterminated dynamic section"))
+    }
+
+    /// The value of the first dynamic entry with `tag`.
+    pub(crate) fn value(&self, tag: i64) -> Option<usize> {
+        self.dynamic
+            .iter()
+            .find(|(entry, _)| *entry == tag)
+            .map(|(_, value)| *value)
+    }
+
+    /// The address in the process that the dynamic entry with `tag` points to.
+    pub(crate) fn address(&self, tag: i64) -> Option<usize> {
+        // glibc relocates the pointers in the dynamic section when it loads an object, but other
+        // loaders leave them alone, so only unrelocated pointers are adjusted.
+        self.value(tag).map(|value| {
+            if value < self.bias {
+                value.wrapping_add(self.bias)
+            } else {
+                value
+            }
+        })
+    }
+
+    /// The length in bytes of a table of `count` entries of `size` bytes at `address`.
+    ///
+    /// The sizes come from the memory of the process, so a table that does not fit inside of the
+    /// loaded object is rejected rather than trusted to size an allocation.
+    fn table_len(&self, address: usize, count: usize, size: usize) -> std::io::Result<usize> {
+        count
+            .checked_mul(size)
+            .filter(|len| {
+                let end = address.checked_add(*len);
+                address >= self.start && end.is_some_and(|end| end <= self.end)
+            })
+            .ok_or_else(|| invalid_data("Table extends past the end of the object"))
+    }
+
+    /// The number of entries in the dynamic symbol table.
+    fn symbol_count(&self) -> std::io::Result<usize> {
+        if let Some(hash) = self.address(DT_HASH) {
+            let mut header = [0_u8; 8];
+            self.source.copy_address(hash, &mut header)?;
+            return Ok(read_u32(&header, 4) as usize);
+        }
+        let hash = self
+            .address(DT_GNU_HASH)
+            .ok_or_else(|| invalid_data("No symbol hash table"))?;
+        let mut header = [0_u8; 16];
+        self.source.copy_address(hash, &mut header)?;
+        let bucket_count = read_u32(&header, 0) as usize;
+        let symbol_offset = read_u32(&header, 4) as usize;
+        let bloom_size = read_u32(&header, 8) as usize;
+        let bloom = hash + header.len();
+        let buckets_address = bloom + self.table_len(bloom, bloom_size, 8)?;
+        let mut buckets = vec![0_u8; self.table_len(buckets_address, bucket_count, 4)?];
+        self.source.copy_address(buckets_address, &mut buckets)?;
+        let last_bucket = buckets
+            .chunks_exact(4)
+            .map(|bucket| read_u32(bucket, 0) as usize)
+            .max()
+            .unwrap_or(0);
+        if last_bucket < symbol_offset {
+            return Ok(symbol_offset);
+        }
+        // Walk the chain of the last bucket until the entry marking its end, which has to come
+        // before the end of the object.
+        let chains = buckets_address + buckets.len();
+        let mut index = last_bucket;
+        let mut chain = [0_u8; 4];
+        loop {
+            let address = chains + (index - symbol_offset) * 4;
+            self.table_len(address, 1, chain.len())
+                .map_err(|_| invalid_data("Unterminated symbol hash chain"))?;
+            self.source.copy_address(address, &mut chain)?;
+            if read_u32(&chain, 0) & 1 != 0 {
+                return Ok(index + 1);
+            }
+            index += 1;
+        }
+    }
+
+    /// Read every symbol in the dynamic symbol table.
+    pub(crate) fn symbols(&self) -> std::io::Result<Vec<Symbol>> {
+        let table = self
+            .address(DT_SYMTAB)
+            .ok_or_else(|| invalid_data("No symbol table"))?;
+        let strings = self
+            .address(DT_STRTAB)
+            .ok_or_else(|| invalid_data("No string table"))?;
+        let entry_size = self.value(DT_SYMENT).unwrap_or(SYMBOL_SIZE).max(SYMBOL_SIZE);
+        let count = self.symbol_count()?;
+        let mut symbols = vec![0_u8; self.table_len(table, count, entry_size)?];
+        self.source.copy_address(table, &mut symbols)?;
+        let mut names = vec![0_u8; self.table_len(strings, self.value(DT_STRSZ).unwrap_or(0), 1)?];
+        self.source.copy_address(strings, &mut names)?;
+
+        Ok(symbols
+            .chunks_exact(entry_size)
+            .map(|symbol| {
+                let name = names
+                    .get(read_u32(symbol, 0) as usize..)
+                    .map(|name| {
+                        let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+                        String::from_utf8_lossy(&name[..end]).into_owned()
+                    })
+                    .unwrap_or_default();
+                let defined = read_u16(symbol, 6) != 0;
+                let value = read_usize(symbol, 8);
+                Symbol {
+                    name,
+                    address: if defined && value != 0 {
+                        value.wrapping_add(self.bias)
+                    } else {
+                        0
+                    },
+                }
+            })
+            .collect())
+    }
+    /// Read the relocations in the table at the dynamic entry `table` that is `size` bytes long.
+    fn relocation_table(&self, table: i64, size: i64) -> std::io::Result<Vec<Relocation>> {
+        let (Some(address), Some(size)) = (self.address(table), self.value(size)) else {
+            return Ok(Vec::new());
+        };
+        let entry_size = self.value(DT_RELAENT).unwrap_or(RELA_SIZE).max(RELA_SIZE);
+        let mut entries = vec![0_u8; self.table_len(address, size / entry_size, entry_size)?];
+        self.source.copy_address(address, &mut entries)?;
+        Ok(entries
+            .chunks_exact(entry_size)
+            .map(|entry| {
+                let info = read_u64(entry, 8);
+                #[allow(clippy::cast_possible_truncation)]
+                Relocation {
+                    address: read_usize(entry, 0).wrapping_add(self.bias),
+                    kind: info as u32,
+                    symbol: (info >> 32) as usize,
+                }
+            })
+            .collect())
+    }
+
+    /// Read the relocations of the object, both the ones applied at load time and the ones for
+    /// the procedure linkage table.
+    pub(crate) fn relocations(&self) -> std::io::Result<Vec<Relocation>> {
+        let mut relocations = self.relocation_table(DT_RELA, DT_RELASZ)?;
+        if self.value(DT_PLTREL).is_none_or(|kind| kind == DT_RELA as usize) {
+            relocations.extend(self.relocation_table(DT_JMPREL, DT_PLTRELSZ)?);
+        }
+        Ok(relocations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LocalProcess;
+
+    const DT_REL: usize = 17;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Build an object with an undefined `puts`, a defined `answer` at `0x500`, two load-time
+    /// relocations against `answer` and a jump slot for `puts`, using `hash` for its hash table.
+    fn test_object(hash: i64, plt_relocation: usize) -> Vec<u8> {
+        let mut image = vec![0_u8; 0x700];
+        put(&mut image, 0, b"\x7fELF\x02\x01\x01");
+        put(&mut image, 32, &0x40_u64.to_le_bytes());
+        put(&mut image, 54, &56_u16.to_le_bytes());
+        put(&mut image, 56, &2_u16.to_le_bytes());
+        put(&mut image, 0x40, &PT_LOAD.to_le_bytes());
+        put(&mut image, 0x40 + 40, &0x700_u64.to_le_bytes());
+        put(&mut image, 0x40 + 56, &PT_DYNAMIC.to_le_bytes());
+        put(&mut image, 0x40 + 56 + 16, &0x100_u64.to_le_bytes());
+
+        let dynamic = [
+            (DT_SYMTAB, 0x200),
+            (DT_STRTAB, 0x300),
+            (DT_STRSZ, 13),
+            (DT_SYMENT, SYMBOL_SIZE),
+            (hash, 0x380),
+            (DT_RELA, 0x400),
+            (DT_RELASZ, 2 * RELA_SIZE),
+            (DT_RELAENT, RELA_SIZE),
+            (DT_JMPREL, 0x440),
+            (DT_PLTRELSZ, RELA_SIZE),
+            (DT_PLTREL, plt_relocation),
+        ];
+        for (index, (tag, value)) in dynamic.into_iter().enumerate() {
+            put(&mut image, 0x100 + index * 16, &tag.to_le_bytes());
+            put(&mut image, 0x100 + index * 16 + 8, &(value as u64).to_le_bytes());
+        }
+
+        put(&mut image, 0x200 + SYMBOL_SIZE, &1_u32.to_le_bytes());
+        put(&mut image, 0x200 + 2 * SYMBOL_SIZE, &6_u32.to_le_bytes());
+        put(&mut image, 0x200 + 2 * SYMBOL_SIZE + 6, &1_u16.to_le_bytes());
+        put(&mut image, 0x200 + 2 * SYMBOL_SIZE + 8, &0x500_u64.to_le_bytes());
+        put(&mut image, 0x300, b"\0puts\0answer\0");
+
+        if hash == DT_HASH {
+            // One bucket and a chain for each of the three symbols.
+            put(&mut image, 0x380, &1_u32.to_le_bytes());
+            put(&mut image, 0x384, &3_u32.to_le_bytes());
+        } else {
+            // One bucket starting at symbol 1, a single bloom word, and a chain ending at symbol 2.
+            put(&mut image, 0x380, &1_u32.to_le_bytes());
+            put(&mut image, 0x384, &1_u32.to_le_bytes());
+            put(&mut image, 0x388, &1_u32.to_le_bytes());
+            put(&mut image, 0x398, &1_u32.to_le_bytes());
+            put(&mut image, 0x39c, &0x1234_u32.to_le_bytes());
+            put(&mut image, 0x3a0, &0x5679_u32.to_le_bytes());
+        }
+
+        let relocations = [
+            (0x600, 2, R_X86_64_64),
+            (0x608, 2, R_X86_64_GLOB_DAT),
+            (0x610, 1, R_X86_64_JUMP_SLOT),
+        ];
+        for (index, (offset, symbol, kind)) in relocations.into_iter().enumerate() {
+            let entry = 0x400 + index * RELA_SIZE + if index == 2 { 0x10 } else { 0 };
+            put(&mut image, entry, &(offset as u64).to_le_bytes());
+            put(&mut image, entry + 8, &(symbol << 32 | u64::from(kind)).to_le_bytes());
+        }
+        image
+    }
+
+    fn symbols(image: &[u8]) -> Vec<(String, usize)> {
+        DynamicObject::parse(&LocalProcess, image.as_ptr() as usize)
+            .and_then(|object| object.symbols())
+            .unwrap()
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.address))
+            .collect()
+    }
+
+    #[test]
+    fn reads_symbols_through_either_hash_table() {
+        for hash in [DT_HASH, DT_GNU_HASH] {
+            let image = test_object(hash, DT_RELA as usize);
+            let base = image.as_ptr() as usize;
+            assert_eq!(
+                symbols(&image),
+                [
+                    (String::new(), 0),
+                    (String::from("puts"), 0),
+                    (String::from("answer"), base + 0x500),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn reads_relocations() {
+        let image = test_object(DT_GNU_HASH, DT_RELA as usize);
+        let base = image.as_ptr() as usize;
+        let object = DynamicObject::parse(&LocalProcess, base).unwrap();
+        assert_eq!(object.bias, base);
+        assert_eq!(object.address(DT_SYMTAB), Some(base + 0x200));
+        let relocations: Vec<_> = object
+            .relocations()
+            .unwrap()
+            .into_iter()
+            .map(|relocation| (relocation.address - base, relocation.symbol, relocation.kind))
+            .collect();
+        assert_eq!(
+            relocations,
+            [
+                (0x600, 2, R_X86_64_64),
+                (0x608, 2, R_X86_64_GLOB_DAT),
+                (0x610, 1, R_X86_64_JUMP_SLOT),
+            ]
+        );
+
+        // A procedure linkage table made of `Elf64_Rel` entries is not read as `Elf64_Rela`.
+        let image = test_object(DT_GNU_HASH, DT_REL);
+        let object = DynamicObject::parse(&LocalProcess, image.as_ptr() as usize).unwrap();
+        assert_eq!(object.relocations().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn rejects_tables_past_the_end_of_the_object() {
+        let error = |image: &[u8]| {
+            DynamicObject::parse(&LocalProcess, image.as_ptr() as usize)
+                .and_then(|object| object.symbols())
+                .unwrap_err()
+                .to_string()
+        };
+        let mut image = test_object(DT_GNU_HASH, DT_RELA as usize);
+        put(&mut image, 0x100 + 2 * 16 + 8, &u64::MAX.to_le_bytes());
+        assert_eq!(error(&image), "Table extends past the end of the object");
+
+        let mut image = test_object(DT_GNU_HASH, DT_RELA as usize);
+        put(&mut image, 0x380, &u32::MAX.to_le_bytes());
+        assert_eq!(error(&image), "Table extends past the end of the object");
+
+        let mut image = test_object(DT_GNU_HASH, DT_RELA as usize);
+        put(&mut image, 0x3a0, &0x5678_u32.to_le_bytes());
+        put(&mut image, 0x40 + 40, &0x3a4_u64.to_le_bytes());
+        assert_eq!(error(&image), "Unterminated symbol hash chain");
+
+        let mut image = test_object(DT_GNU_HASH, DT_RELA as usize);
+        put(&mut image, 0x100 + 6 * 16 + 8, &u64::MAX.to_le_bytes());
+        let object = DynamicObject::parse(&LocalProcess, image.as_ptr() as usize).unwrap();
+        assert!(object.relocations().is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_objects() {
+        let mut image = test_object(DT_HASH, DT_RELA as usize);
+        image[56] = 1;
+        let error = DynamicObject::parse(&LocalProcess, image.as_ptr() as usize).unwrap_err();
+        assert_eq!(error.to_string(), "No dynamic section");
+        image[4] = 1;
+        let error = DynamicObject::parse(&LocalProcess, image.as_ptr() as usize).unwrap_err();
+        assert_eq!(error.to_string(), "Only 64-bit little-endian ELF objects are supported");
+        image[0] = 0;
+        let error = DynamicObject::parse(&LocalProcess, image.as_ptr() as usize).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
+    #[test]
+    fn finds_symbols_of_the_loaded_c_library() {
+        // The loader is asked where the C library and its `malloc` are, rather than the maps of
+        // the process, which also list copies of the file mapped for reading backtraces.
+        let (base, expected) = unsafe {
+            let handle = libc::dlopen(c"libc.so.6".as_ptr(), libc::RTLD_NOW | libc::RTLD_NOLOAD);
+            assert!(!handle.is_null());
+            let malloc = libc::dlsym(handle, c"malloc".as_ptr());
+            let mut info: libc::Dl_info = std::mem::zeroed();
+            assert_ne!(libc::dladdr(malloc, &mut info), 0);
+            (info.dli_fbase as usize, malloc as usize)
+        };
+        let symbols = DynamicObject::parse(&LocalProcess, base)
+            .and_then(|object| object.symbols())
+            .unwrap();
+        let malloc = symbols.iter().find(|symbol| symbol.name == "malloc").unwrap();
+        assert_eq!(malloc.address, expected);
+    }
+}
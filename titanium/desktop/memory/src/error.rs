@@ -0,0 +1,169 @@
+use crate::Pid;
+
+/// The error type for reading and writing memory, and for finding processes.
+///
+/// The memory traits return `std::io::Error` so that they can be implemented for anything, but
+/// the errors this crate creates wrap a `MemoryError`, which can be taken back out with
+/// `MemoryError::from`. Errors that are not one of these cases become [`MemoryError::Io`].
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, CopyAddress, MemoryError};
+/// let handle = get_handle("game").unwrap();
+/// match handle.get_offset(&[0x1234, 0x30, 0x8]).map_err(MemoryError::from) {
+///     Ok(address) => println!("Found it at {address:#x}"),
+///     Err(MemoryError::NullPointer { level }) => println!("Not spawned yet (level {level})"),
+///     Err(MemoryError::ProcessGone { .. }) => println!("The game exited"),
+///     Err(error) => println!("{error}"),
+/// }
+/// ```
+///
+/// [`MemoryError::Io`]: enum.MemoryError.html#variant.Io
+#[derive(Debug, thiserror::Error)]
+pub enum MemoryError {
+    /// The process may not be accessed by this one
+    #[error("Permission denied accessing process {pid} ({hint})")]
+    PermissionDenied {
+        /// The id of the process
+        pid: Pid,
+        /// What would most likely allow the access
+        hint: String,
+    },
+    /// The process exited
+    #[error("Process {pid} has exited")]
+    ProcessGone {
+        /// The id of the process
+        pid: Pid,
+    },
+    /// The address is not mapped in the process
+    #[error("{address:#x} is not mapped{}", level.map_or_else(String::new, |level| format!(" (pointer chain level {level})")))]
+    UnmappedAddress {
+        /// The address that could not be accessed
+        address: usize,
+        /// The index of the offset that led to the address, if it was read while following a
+        /// pointer chain
+        level: Option<usize>,
+    },
+    /// Only part of a range could be read or written
+    #[error("Only {done} of {len} bytes were {} at {address:#x}", if *write { "written" } else { "read" })]
+    PartialTransfer {
+        /// The start of the range
+        address: usize,
+        /// The number of bytes transferred before it stopped
+        done: usize,
+        /// The length of the range
+        len: usize,
+        /// Whether the transfer was a write
+        write: bool,
+    },
+    /// A pointer chain reached a null pointer before its last offset
+    #[error("Null pointer in pointer chain at level {level}")]
+    NullPointer {
+        /// The index of the offset that led to the null pointer
+        level: usize,
+    },
+    /// No process matched
+    #[error("No process matches `{name}`")]
+    ProcessNotFound {
+        /// What the process was looked for by
+        name: String,
+    },
+    /// Any other error
+    #[error(transparent)]
+    Io(std::io::Error),
+}
+
+impl MemoryError {
+    /// A [`MemoryError::PermissionDenied`] for a process, with a hint based on how the system
+    /// restricts access to other processes.
+    ///
+    /// [`MemoryError::PermissionDenied`]: enum.MemoryError.html#variant.PermissionDenied
+    #[must_use]
+    pub fn permission_denied(pid: Pid) -> Self {
+        Self::PermissionDenied {
+            pid,
+            hint: permission_hint(),
+        }
+    }
+
+    /// Turn an OS error from accessing memory at `address` in a process into the matching
+    /// variant, or [`MemoryError::Io`] if there is none.
+    ///
+    /// [`MemoryError::Io`]: enum.MemoryError.html#variant.Io
+    #[must_use]
+    pub fn from_os_error(error: std::io::Error, pid: Pid, address: usize) -> Self {
+        #[cfg(unix)]
+        match error.raw_os_error() {
+            Some(libc::EPERM | libc::EACCES) => return Self::permission_denied(pid),
+            Some(libc::ESRCH) => return Self::ProcessGone { pid },
+            Some(libc::EFAULT | libc::EIO) => {
+                return Self::UnmappedAddress {
+                    address,
+                    level: None,
+                }
+            }
+            _ => {}
+        }
+        let _ = (pid, address);
+        Self::Io(error)
+    }
+
+    /// The `std::io::ErrorKind` the error has when it is turned into a `std::io::Error`.
+    #[must_use]
+    pub fn kind(&self) -> std::io::ErrorKind {
+        match self {
+            Self::PermissionDenied { .. } => std::io::ErrorKind::PermissionDenied,
+            Self::ProcessGone { .. } => std::io::ErrorKind::BrokenPipe,
+            Self::UnmappedAddress { .. } | Self::NullPointer { .. } => {
+                std::io::ErrorKind::InvalidInput
+            }
+            Self::PartialTransfer { write: false, .. } => std::io::ErrorKind::UnexpectedEof,
+            Self::PartialTransfer { write: true, .. } => std::io::ErrorKind::WriteZero,
+            Self::ProcessNotFound { .. } => std::io::ErrorKind::NotFound,
+            Self::Io(error) => error.kind(),
+        }
+    }
+}
+
+/// Take the `MemoryError` back out of a `std::io::Error` made from one, or wrap any other error
+/// in [`MemoryError::Io`].
+///
+/// [`MemoryError::Io`]: enum.MemoryError.html#variant.Io
+impl From<std::io::Error> for MemoryError {
+    fn from(error: std::io::Error) -> Self {
+        error.downcast::<Self>().unwrap_or_else(Self::Io)
+    }
+}
+
+impl From<MemoryError> for std::io::Error {
+    fn from(error: MemoryError) -> Self {
+        match error {
+            MemoryError::Io(error) => error,
+            error => std::io::Error::new(error.kind(), error),
+        }
+    }
+}
+
+/// Explain what stops this process from accessing others.
+#[cfg(target_os = "linux")]
+fn permission_hint() -> String {
+    let scope = std::fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope")
+        .ok()
+        .and_then(|scope| scope.trim().parse::<u32>().ok());
+    match scope {
+        Some(3) => "kernel.yama.ptrace_scope is 3, which forbids attaching until reboot".to_owned(),
+        Some(2) => {
+            "kernel.yama.ptrace_scope is 2, so run as root or with CAP_SYS_PTRACE".to_owned()
+        }
+        Some(1) => "kernel.yama.ptrace_scope is 1, so only parent processes may attach; run as \
+                    root, grant CAP_SYS_PTRACE, or set it to 0"
+            .to_owned(),
+        _ => "the process belongs to another user or is not dumpable; run as root or with \
+              CAP_SYS_PTRACE"
+            .to_owned(),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn permission_hint() -> String {
+    "run with elevated privileges".to_owned()
+}
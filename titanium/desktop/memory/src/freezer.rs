@@ -0,0 +1,305 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::{DataMember, Memory};
+
+/// A condition checked against the current value before a frozen value is written.
+type Condition<T> = Box<dyn Fn(&T) -> bool + Send>;
+
+/// The state of a single frozen member, shared between its writer thread and its handle.
+#[derive(Debug, Default)]
+struct EntryState {
+    enabled: bool,
+    stopped: bool,
+    last_error: Option<std::io::Error>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    state: Mutex<EntryState>,
+    wake: Condvar,
+}
+
+impl Entry {
+    fn lock(&self) -> MutexGuard<'_, EntryState> {
+        self.state.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn update(&self, f: impl FnOnce(&mut EntryState)) {
+        f(&mut self.lock());
+        self.wake.notify_all();
+    }
+}
+
+/// Keeps values locked by rewriting [`DataMember`]s on background threads, so that the target
+/// process cannot change them.
+///
+/// Each frozen member gets its own writer thread and interval, and is controlled through the
+/// [`FreezeHandle`] returned when it is frozen. Dropping the `Freezer` stops every entry.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, DataMember, Freezer};
+/// # use std::time::Duration;
+/// let handle = get_handle("game").unwrap();
+/// let health = DataMember::<f32>::new_offset(handle, vec![0x1234]);
+/// let mut freezer = Freezer::new();
+/// let frozen = freezer.freeze(health, 100.0, Duration::from_millis(10));
+/// // ...
+/// frozen.disable();
+/// ```
+///
+/// [`DataMember`]: struct.DataMember.html
+#[derive(Debug, Default)]
+pub struct Freezer {
+    entries: Vec<(Arc<Entry>, JoinHandle<()>)>,
+}
+
+impl Freezer {
+    /// Create a new `Freezer` with no frozen members.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write `value` to `member` every `interval` until the returned handle is disabled or
+    /// dropped. The first write happens immediately.
+    #[must_use = "the member is only frozen until the handle is dropped"]
+    pub fn freeze<T>(
+        &mut self,
+        member: DataMember<T>,
+        value: T,
+        interval: Duration,
+    ) -> FreezeHandle
+    where
+        T: Sized + Copy + Send + 'static,
+    {
+        self.spawn(member, value, interval, None)
+    }
+
+    /// Write `value` to `member` every `interval`, but only while `condition` returns `true` for
+    /// the value currently in memory. For example, `|health| *health < 100.0` stops a value
+    /// from going down without stopping it from going up.
+    ///
+    /// # Safety
+    /// The current value is read with [`Memory::read`] before it is passed to `condition`, so the
+    /// caller must ensure that the memory is always valid for a `T`.
+    ///
+    /// [`Memory::read`]: trait.Memory.html#tymethod.read
+    #[must_use = "the member is only frozen until the handle is dropped"]
+    pub unsafe fn freeze_if<T, F>(
+        &mut self,
+        member: DataMember<T>,
+        value: T,
+        interval: Duration,
+        condition: F,
+    ) -> FreezeHandle
+    where
+        T: Sized + Copy + Send + 'static,
+        F: Fn(&T) -> bool + Send + 'static,
+    {
+        self.spawn(member, value, interval, Some(Box::new(condition)))
+    }
+
+    fn spawn<T>(
+        &mut self,
+        member: DataMember<T>,
+        value: T,
+        interval: Duration,
+        condition: Option<Condition<T>>,
+    ) -> FreezeHandle
+    where
+        T: Sized + Copy + Send + 'static,
+    {
+        self.entries.retain(|(_, thread)| !thread.is_finished());
+        let entry = Arc::new(Entry::default());
+        entry.lock().enabled = true;
+        let thread = {
+            let entry = Arc::clone(&entry);
+            std::thread::spawn(move || {
+                let mut state = entry.lock();
+                while !state.stopped {
+                    if state.enabled {
+                        // The write happens with the lock held, so once `disable` or `drop`
+                        // returns no more writes can happen.
+                        let result = match &condition {
+                            // SAFETY: the caller of `freeze_if` guarantees that the memory is
+                            // valid for a `T`.
+                            Some(condition) => unsafe { member.read() }.and_then(|current| {
+                                if condition(&current) {
+                                    member.write(&value)
+                                } else {
+                                    Ok(())
+                                }
+                            }),
+                            None => member.write(&value),
+                        };
+                        if let Err(error) = result {
+                            state.last_error = Some(error);
+                        }
+                        state = entry
+                            .wake
+                            .wait_timeout(state, interval)
+                            .unwrap_or_else(std::sync::PoisonError::into_inner)
+                            .0;
+                    } else {
+                        state = entry
+                            .wake
+                            .wait(state)
+                            .unwrap_or_else(std::sync::PoisonError::into_inner);
+                    }
+                }
+            })
+        };
+        self.entries.push((Arc::clone(&entry), thread));
+        FreezeHandle { entry }
+    }
+
+    /// The number of entries that have not been stopped.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|(entry, _)| !entry.lock().stopped)
+            .count()
+    }
+
+    /// Returns `true` if every entry has been stopped.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stop every entry, waiting for their writer threads to exit.
+    pub fn clear(&mut self) {
+        for (entry, _) in &self.entries {
+            entry.update(|state| state.stopped = true);
+        }
+        for (_, thread) in self.entries.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Freezer {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Controls a single member frozen by a [`Freezer`]. Dropping the handle stops the entry.
+#[derive(Debug)]
+#[must_use = "the member is only frozen until the handle is dropped"]
+pub struct FreezeHandle {
+    entry: Arc<Entry>,
+}
+
+impl FreezeHandle {
+    /// Resume writing the value.
+    pub fn enable(&self) {
+        self.entry.update(|state| state.enabled = true);
+    }
+
+    /// Stop writing the value until [`FreezeHandle::enable`] is called. No writes happen after
+    /// this returns.
+    pub fn disable(&self) {
+        self.entry.update(|state| state.enabled = false);
+    }
+
+    /// Returns `true` if the value is being written.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        let state = self.entry.lock();
+        state.enabled && !state.stopped
+    }
+
+    /// Take the last error that occurred while reading or writing the member, if any.
+    pub fn take_error(&self) -> Option<std::io::Error> {
+        self.entry.lock().last_error.take()
+    }
+}
+
+impl Drop for FreezeHandle {
+    fn drop(&mut self) {
+        self.entry.update(|state| state.stopped = true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pid, TryIntoProcessHandle};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn member(value: &AtomicU32) -> DataMember<u32> {
+        let handle = (std::process::id() as Pid)
+            .try_into_process_handle()
+            .unwrap();
+        DataMember::new_offset(handle, vec![value.as_ptr() as usize])
+    }
+
+    /// Wait for up to a second for `value` to hold `expected`.
+    fn wait_for(value: &AtomicU32, expected: u32) -> bool {
+        (0..1000).any(|_| {
+            std::thread::sleep(Duration::from_millis(1));
+            value.load(Ordering::SeqCst) == expected
+        })
+    }
+
+    #[test]
+    fn keeps_writing_until_disabled() {
+        let value = AtomicU32::new(5);
+        let mut freezer = Freezer::new();
+        let frozen = freezer.freeze(member(&value), 100, Duration::from_millis(1));
+        assert!(wait_for(&value, 100));
+        value.store(5, Ordering::SeqCst);
+        assert!(wait_for(&value, 100));
+
+        frozen.disable();
+        assert!(!frozen.is_enabled());
+        value.store(5, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(value.load(Ordering::SeqCst), 5);
+
+        frozen.enable();
+        assert!(wait_for(&value, 100));
+        assert_eq!(freezer.len(), 1);
+        drop(frozen);
+        assert!(freezer.is_empty());
+        value.store(5, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(value.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn only_writes_while_the_condition_holds() {
+        let value = AtomicU32::new(200);
+        let mut freezer = Freezer::new();
+        let _frozen = unsafe {
+            freezer.freeze_if(member(&value), 100, Duration::from_millis(1), |current| {
+                *current < 100
+            })
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(value.load(Ordering::SeqCst), 200);
+        value.store(50, Ordering::SeqCst);
+        assert!(wait_for(&value, 100));
+    }
+
+    #[test]
+    fn keeps_the_last_error() {
+        let handle = (std::process::id() as Pid)
+            .try_into_process_handle()
+            .unwrap();
+        let unmapped = DataMember::<u32>::new_offset(handle, vec![0x10]);
+        let mut freezer = Freezer::new();
+        let frozen = freezer.freeze(unmapped, 1, Duration::from_millis(1));
+        let error = (0..1000).find_map(|_| {
+            std::thread::sleep(Duration::from_millis(1));
+            frozen.take_error()
+        });
+        assert!(error.is_some());
+        freezer.clear();
+        assert!(!frozen.is_enabled());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::elf::{DynamicObject, R_X86_64_64, R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT};
+use crate::{find_module, modules, CopyAddress, Module, ProcessHandle};
+
+/// The error type for [`hook_import`]
+#[derive(Debug, thiserror::Error)]
+pub enum ImportHookError {
+    /// The module does not import the symbol
+    #[error("`{module}` does not import `{symbol}`")]
+    ImportNotFound {
+        /// The name of the module
+        module: String,
+        /// The name of the symbol
+        symbol: String,
+    },
+    /// Reading the module or writing its global offset table failed
+    #[error("Reading or writing process memory failed")]
+    Io(#[from] std::io::Error),
+}
+
+/// A pointer to a function or variable from another module, which a module loaded into a process
+/// reads from its global offset table.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Import {
+    /// The name of the imported symbol, such as `SDL_GL_SwapWindow`.
+    pub name: String,
+    /// The address of the pointer in the global offset table.
+    pub slot: usize,
+    /// The address the pointer currently holds. For a function that has not been called yet with
+    /// lazy binding, this is the stub in the procedure linkage table that looks it up.
+    pub address: usize,
+}
+
+/// List the imports of a module by walking its dynamic section and relocations.
+///
+/// A symbol can be imported through several slots, such as one for calls through the procedure
+/// linkage table and one for taking its address, so it can be listed more than once.
+///
+/// # Errors
+/// Returns an error if the module is not loaded, or if it is not a 64-bit ELF object with a
+/// dynamic section.
+pub fn imports(handle: &ProcessHandle, module: &str) -> std::io::Result<Vec<Import>> {
+    let module = find_module(handle, module)?;
+    let object = DynamicObject::parse(handle, module.base)?;
+    let symbols = object.symbols()?;
+    let mut imports = Vec::new();
+    for relocation in object.relocations()? {
+        if !matches!(
+            relocation.kind,
+            R_X86_64_64 | R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT
+        ) {
+            continue;
+        }
+        let Some(symbol) = symbols.get(relocation.symbol) else {
+            continue;
+        };
+        if relocation.symbol == 0 || symbol.name.is_empty() {
+            continue;
+        }
+        let mut address = [0_u8; 8];
+        handle.copy_address(relocation.address, &mut address)?;
+        #[allow(clippy::cast_possible_truncation)]
+        imports.push(Import {
+            name: symbol.name.clone(),
+            slot: relocation.address,
+            address: u64::from_le_bytes(address) as usize,
+        });
+    }
+    Ok(imports)
+}
+
+/// Find the address a symbol is defined at in any module other than `importer`, in load order.
+fn resolve(handle: &ProcessHandle, importer: &Module, name: &str) -> std::io::Result<Option<usize>> {
+    for module in modules(handle)? {
+        if module.base == importer.base {
+            continue;
+        }
+        let Ok(symbols) = DynamicObject::parse(handle, module.base).and_then(|object| object.symbols())
+        else {
+            continue;
+        };
+        if let Some(symbol) = symbols
+            .iter()
+            .find(|symbol| symbol.address != 0 && symbol.name == name)
+        {
+            return Ok(Some(symbol.address));
+        }
+    }
+    Ok(None)
+}
+
+/// Redirect every call a module makes to an imported function to `new_address`, by overwriting
+/// its pointers in the global offset table of the module.
+///
+/// Only the module itself is affected, so calls from other modules, and calls the module makes
+/// through a pointer it got from `dlsym`, still go to the original function. This is much safer
+/// than an inline [`Detour`], because no code is changed and nothing has to be relocated. The
+/// original pointers are written back when the returned [`ImportHook`] is dropped.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, hook_import};
+/// let handle = get_handle("game").unwrap();
+/// // `handler` is code written to a cave, or a function in an injected library
+/// let handler = 0x1234;
+/// let hook = hook_import(handle, "game", "SDL_GL_SwapWindow", handler).unwrap();
+/// // The handler calls `hook.original()` to swap the window
+/// drop(hook);
+/// ```
+///
+/// If the table is read-only after relocation (full RELRO), its pages are made writable for the
+/// duration of each write in the same way as a [`PatchSet`].
+///
+/// # Errors
+/// Returns an error if the module is not loaded or cannot be parsed, if it does not import
+/// `symbol`, or if its global offset table cannot be written.
+///
+/// [`Detour`]: struct.Detour.html
+/// [`ImportHook`]: struct.ImportHook.html
+/// [`PatchSet`]: struct.PatchSet.html
+pub fn hook_import(
+    handle: ProcessHandle,
+    module: &str,
+    symbol: &str,
+    new_address: usize,
+) -> Result<ImportHook, ImportHookError> {
+    let slots: Vec<_> = imports(&handle, module)?
+        .into_iter()
+        .filter(|import| import.name == symbol)
+        .collect();
+    if slots.is_empty() {
+        return Err(ImportHookError::ImportNotFound {
+            module: module.to_string(),
+            symbol: symbol.to_string(),
+        });
+    }
+
+    // With lazy binding, the slot of a function that has not been called yet points back into
+    // the module, and calling that would run the resolver, which overwrites the hook.
+    let importer = find_module(&handle, module)?;
+    let original = match slots.iter().find(|import| !importer.contains(import.address)) {
+        Some(import) => import.address,
+        None => resolve(&handle, &importer, symbol)?.unwrap_or(slots[0].address),
+    };
+
+    let mut hook = ImportHook {
+        handle,
+        original,
+        slots: Vec::with_capacity(slots.len()),
+    };
+    for import in slots {
+        crate::patch::write(&handle, import.slot, &(new_address as u64).to_le_bytes())?;
+        hook.slots.push((import.slot, import.address));
+    }
+    Ok(hook)
+}
+
+/// A hook on an imported symbol created by [`hook_import`], which restores the original
+/// pointers when it is dropped.
+///
+/// [`hook_import`]: fn.hook_import.html
+#[derive(Debug)]
+pub struct ImportHook {
+    handle: ProcessHandle,
+    original: usize,
+    slots: Vec<(usize, usize)>,
+}
+
+impl ImportHook {
+    /// The address of the function the import resolves to, for the handler to call.
+    #[must_use]
+    pub fn original(&self) -> usize {
+        self.original
+    }
+
+    /// The addresses of the slots in the global offset table that were overwritten.
+    #[must_use]
+    pub fn slots(&self) -> Vec<usize> {
+        self.slots.iter().map(|(slot, _)| *slot).collect()
+    }
+
+    /// Write the original pointers back.
+    ///
+    /// # Errors
+    /// Returns the first error that occurred, after trying to restore every slot.
+    pub fn restore(mut self) -> std::io::Result<()> {
+        self.restore_slots()
+    }
+
+    fn restore_slots(&mut self) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for (slot, address) in std::mem::take(&mut self.slots) {
+            let written = crate::patch::write(&self.handle, slot, &(address as u64).to_le_bytes());
+            if result.is_ok() {
+                result = written;
+            }
+        }
+        result
+    }
+}
+
+/// Restore the original pointers.
+impl Drop for ImportHook {
+    fn drop(&mut self) {
+        let _ = self.restore_slots();
+    }
+}
@@ -18,14 +18,78 @@ pub(crate) mod platform;
 #[cfg(target_os = "windows")]
 #[path = "windows/util.rs"]
 pub mod winutil;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[path = "linux/util.rs"]
+pub mod linuxutil;
 
+mod allocation;
 mod architecture;
 mod data_member;
+mod detour;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod elf;
+mod error;
+mod freezer;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod import_hook;
 mod local_member;
+mod local_process;
+mod module;
+mod patch;
+mod pointer_scan;
+#[cfg(target_os = "linux")]
+mod process_query;
+#[cfg(target_os = "linux")]
+mod process_watcher;
+mod read_cache;
+mod region;
+mod remote_container;
+mod remote_string;
+mod remote_struct;
+mod signature;
+mod snapshot;
+mod value_scan;
 
-pub use architecture::Architecture;
+pub use allocation::{allocate, free, protect};
+pub use architecture::{Architecture, Endianness};
 pub use data_member::DataMember;
+pub use detour::{Detour, DetourError, TRAMPOLINE_SIZE};
+pub use error::MemoryError;
+pub use freezer::{FreezeHandle, Freezer};
 pub use local_member::LocalMember;
+pub use local_process::LocalProcess;
+pub use module::{find_module, module_base, modules, Module};
+pub use patch::{PatchError, PatchId, PatchSet};
+pub use pointer_scan::{
+    load_paths, rescan, save_paths, PointerMap, PointerPath, PointerScanOptions,
+};
+pub use read_cache::ReadCache;
+pub use region::{regions, MemoryRegion, Permissions, Regions};
+pub use remote_container::{RemoteContainerError, RemoteList, RemotePtrArray, RemoteVec, VecHeader};
+pub use remote_string::{
+    read_c_string, read_prefixed_string, read_rust_string, read_std_string, LengthPrefix,
+    RemoteStringError, RustVecLayout, StdStringLayout, StringOptions, TextEncoding,
+};
+pub use remote_struct::{
+    read_remote_pointer, write_remote_field, write_remote_pointer, write_remote_pointer_field,
+    PointerField, RemoteField, RemotePtr, RemoteStruct,
+};
+pub use signature::{scan, scan_module, scan_regions, Pattern, PatternError, ScanMode};
+pub use snapshot::Snapshot;
+pub use value_scan::{ScanCondition, ScanSession, ScanValue};
+
+#[cfg(target_os = "linux")]
+pub use platform::{
+    threads, AttachSession, BackendHandle, MemoryBackend, ThreadInfo, ThreadState,
+};
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub use platform::{InstructionHits, Registers, WatchKind, Watchpoint, WatchpointHit};
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub use import_hook::{hook_import, imports, Import, ImportHook, ImportHookError};
+#[cfg(target_os = "linux")]
+pub use process_query::{wait_for, ProcessInfo, ProcessMatches, ProcessQuery};
+#[cfg(target_os = "linux")]
+pub use process_watcher::{ProcessEvent, ProcessWatcher, WatchedMember};
 
 /// A trait that defines that it is possible to copy some memory from something represented by a
 /// type into a buffer.
@@ -36,6 +100,21 @@ pub trait CopyAddress {
     /// `std::io::Error` if an error occurs copying the address.
     fn copy_address(&self, addr: usize, buf: &mut [u8]) -> std::io::Result<()>;
 
+    /// Copy many addresses into their buffers at once, returning the result of every entry in
+    /// the same order, so that the entries that could not be read are known exactly.
+    ///
+    /// By default this calls [`copy_address`] for every entry. On Linux, a [`ProcessHandle`]
+    /// reads up to 1024 entries with each `process_vm_readv` call instead of one.
+    ///
+    /// [`copy_address`]: #tymethod.copy_address
+    /// [`ProcessHandle`]: type.ProcessHandle.html
+    fn copy_addresses(&self, entries: &mut [(usize, &mut [u8])]) -> Vec<std::io::Result<()>> {
+        entries
+            .iter_mut()
+            .map(|(addr, buf)| self.copy_address(*addr, buf))
+            .collect()
+    }
+
     /// Get the actual memory location from a set of offsets.
     ///
     /// If [`copy_address`] and [`get_pointer_width`] are already defined, then
@@ -43,19 +122,34 @@ pub trait CopyAddress {
     /// operating systems.
     ///
     /// # Errors
-    /// `std::io::Error` if an error occurs copying the address.
+    /// `std::io::Error` if an error occurs copying the address. A pointer that cannot be read is
+    /// a [`MemoryError::UnmappedAddress`] with the index of the offset that led to it, and a
+    /// pointer that is null is a [`MemoryError::NullPointer`].
     ///
     /// [`copy_address`]: #tymethod.copy_address
     /// [`get_pointer_width`]: #tymethod.get_pointer_width
+    /// [`MemoryError::UnmappedAddress`]: enum.MemoryError.html#variant.UnmappedAddress
+    /// [`MemoryError::NullPointer`]: enum.MemoryError.html#variant.NullPointer
     fn get_offset(&self, offsets: &[usize]) -> std::io::Result<usize> {
         // Look ma! No unsafes!
         let mut offset: usize = 0;
         let noffsets: usize = offsets.len();
-        let mut copy = vec![0_u8; self.get_pointer_width() as usize];
-        for next_offset in offsets.iter().take(noffsets - 1) {
+        let mut copy = vec![0_u8; self.get_pointer_width().pointer_size()];
+        for (level, next_offset) in offsets.iter().take(noffsets - 1).enumerate() {
             offset += next_offset;
-            self.copy_address(offset, &mut copy)?;
+            self.copy_address(offset, &mut copy)
+                .map_err(|error| match MemoryError::from(error) {
+                    MemoryError::UnmappedAddress { address, .. } => MemoryError::UnmappedAddress {
+                        address,
+                        level: Some(level),
+                    },
+                    MemoryError::NullPointer { .. } => MemoryError::NullPointer { level },
+                    error => error,
+                })?;
             offset = self.get_pointer_width().pointer_from_ne_bytes(&copy);
+            if offset == 0 {
+                return Err(MemoryError::NullPointer { level }.into());
+            }
         }
         offset += offsets[noffsets - 1];
         Ok(offset)
@@ -81,6 +175,38 @@ pub trait PutAddress {
     /// # Errors
     /// `std::io::Error` if an error occurs copying the address.
     fn put_address(&self, addr: usize, buf: &[u8]) -> std::io::Result<()>;
+
+    /// Put many buffers at their addresses at once, returning the result of every entry in the
+    /// same order, so that the entries that could not be written are known exactly.
+    ///
+    /// By default this calls [`put_address`] for every entry. On Linux, a [`ProcessHandle`]
+    /// writes up to 1024 entries with each `process_vm_writev` call instead of one.
+    ///
+    /// [`put_address`]: #tymethod.put_address
+    /// [`ProcessHandle`]: type.ProcessHandle.html
+    fn put_addresses(&self, entries: &[(usize, &[u8])]) -> Vec<std::io::Result<()>> {
+        entries
+            .iter()
+            .map(|(addr, buf)| self.put_address(*addr, buf))
+            .collect()
+    }
+}
+
+/// A trait that defines that it is possible to change the access permissions of memory in
+/// something represented by a type.
+pub trait ProtectAddress {
+    /// Change the permissions of the pages covering `len` bytes at `addr`, returning the
+    /// permissions the first of those pages had before.
+    ///
+    /// # Errors
+    /// `std::io::Error` if the permissions cannot be changed, such as when part of the range is
+    /// not mapped.
+    fn protect_address(
+        &self,
+        addr: usize,
+        len: usize,
+        permissions: Permissions,
+    ) -> std::io::Result<Permissions>;
 }
 
 /// A `Pid` is a "process id". Each different platform has a different method for uniquely
@@ -242,17 +368,31 @@ pub fn copy_address<T: CopyAddress>(addr: usize, length: usize, source: &T) -> s
 }
 
 /// Attempt to get a [`ProcessHandle`] from a process name.
+///
+/// This picks the first process whose name starts with `name`. On Linux, [`ProcessQuery`] can
+/// match more precisely and list every match.
+///
+/// [`ProcessQuery`]: struct.ProcessQuery.html
 pub fn get_handle<T: ToString>(name: T) -> std::io::Result<ProcessHandle> {
     let name: String = name.to_string();
-    use sysinfo::{ProcessExt, System, SystemExt};
+    #[cfg(target_os = "linux")]
+    let process = ProcessQuery::new()
+        .name_prefix(name.as_str())
+        .first()?
+        .map(|process| process.pid);
+    #[cfg(not(target_os = "linux"))]
+    let process = {
+        use sysinfo::{ProcessExt, System, SystemExt};
 
-    let mut system = System::new_all();
+        let mut system = System::new_all();
 
-    // First we update all information of our `System` struct.
-    system.refresh_all();
-    let mut ps = system.processes().iter().filter(|(_, p)| p.name().starts_with(&name));
-    match ps.nth(0) {
-        Some((pid, _)) => (*pid).try_into_process_handle(),
-        None => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Process not found"))
+        // First we update all information of our `System` struct.
+        system.refresh_all();
+        let mut ps = system.processes().iter().filter(|(_, p)| p.name().starts_with(&name));
+        ps.nth(0).map(|(pid, _)| *pid)
+    };
+    match process {
+        Some(pid) => pid.try_into_process_handle(),
+        None => Err(MemoryError::ProcessNotFound { name }.into()),
     }
 }
\ No newline at end of file
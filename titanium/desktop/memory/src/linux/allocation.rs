@@ -0,0 +1,119 @@
+use super::attach::AttachSession;
+use super::local::{page_align, prot, region_permissions, unused_ranges, PAGE_SIZE};
+use super::ProcessHandle;
+use crate::allocation::{near_candidates, nothing_near};
+use crate::{Permissions, ProtectAddress};
+
+impl AttachSession {
+    /// Allocate memory in the attached process by having it call `mmap`. See [`allocate`].
+    ///
+    /// # Errors
+    /// Returns an error if `mmap` fails, or if nothing is free within range of `near`.
+    ///
+    /// [`allocate`]: fn.allocate.html
+    pub fn allocate(
+        &self,
+        size: usize,
+        permissions: Permissions,
+        near: Option<usize>,
+    ) -> std::io::Result<usize> {
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+        let Some(near) = near else {
+            return self.mmap(0, size, permissions, flags);
+        };
+
+        let size = page_align(size).ok_or_else(|| nothing_near(near))?;
+        for candidate in near_candidates(unused_ranges(&self.handle())?, near, size, PAGE_SIZE) {
+            // Kernels older than 4.17 treat `MAP_FIXED_NOREPLACE` as a hint, so the address still
+            // has to be checked.
+            match self.mmap(candidate, size, permissions, flags | libc::MAP_FIXED_NOREPLACE) {
+                Ok(address) if address == candidate => return Ok(address),
+                Ok(address) => self.free(address, size)?,
+                Err(_) => {}
+            }
+        }
+        Err(nothing_near(near))
+    }
+
+    fn mmap(
+        &self,
+        address: usize,
+        size: usize,
+        permissions: Permissions,
+        flags: libc::c_int,
+    ) -> std::io::Result<usize> {
+        #[allow(clippy::cast_sign_loss)]
+        let args = [
+            address,
+            size,
+            prot(permissions) as usize,
+            flags as usize,
+            usize::MAX,
+            0,
+        ];
+        self.with_tracer(move |tracer| tracer.syscall(libc::SYS_mmap, args))
+    }
+
+    /// Free memory in the attached process by having it call `munmap`.
+    ///
+    /// # Errors
+    /// Returns an error if `munmap` fails.
+    pub fn free(&self, address: usize, size: usize) -> std::io::Result<()> {
+        self.with_tracer(move |tracer| {
+            tracer.syscall(libc::SYS_munmap, [address, size, 0, 0, 0, 0])
+        })?;
+        Ok(())
+    }
+
+    /// Change the permissions of memory in the attached process by having it call `mprotect`.
+    /// The range is extended to whole pages.
+    ///
+    /// # Errors
+    /// Returns an error if `mprotect` fails, such as when part of the range is not mapped.
+    pub fn protect(&self, address: usize, len: usize, permissions: Permissions) -> std::io::Result<()> {
+        let start = address & !(PAGE_SIZE - 1);
+        let len = address + len - start;
+        #[allow(clippy::cast_sign_loss)]
+        let prot = prot(permissions) as usize;
+        self.with_tracer(move |tracer| {
+            tracer.syscall(libc::SYS_mprotect, [start, len, prot, 0, 0, 0])
+        })?;
+        Ok(())
+    }
+}
+
+/// Use `mprotect` inside of the attached process to change the permissions of its memory.
+impl ProtectAddress for AttachSession {
+    fn protect_address(
+        &self,
+        addr: usize,
+        len: usize,
+        permissions: Permissions,
+    ) -> std::io::Result<Permissions> {
+        let previous = region_permissions(&self.handle(), addr)?;
+        self.protect(addr, len, permissions)?;
+        Ok(previous)
+    }
+}
+
+pub(crate) fn allocate(
+    handle: &ProcessHandle,
+    size: usize,
+    permissions: Permissions,
+    near: Option<usize>,
+) -> std::io::Result<usize> {
+    AttachSession::attach(*handle)?.allocate(size, permissions, near)
+}
+
+pub(crate) fn free(handle: &ProcessHandle, address: usize, size: usize) -> std::io::Result<()> {
+    AttachSession::attach(*handle)?.free(address, size)
+}
+
+pub(crate) fn protect(
+    handle: &ProcessHandle,
+    address: usize,
+    len: usize,
+    permissions: Permissions,
+) -> std::io::Result<()> {
+    AttachSession::attach(*handle)?.protect(address, len, permissions)
+}
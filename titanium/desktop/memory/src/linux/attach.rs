@@ -0,0 +1,550 @@
+use libc::c_int;
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::{Pid, ProcessHandle};
+use crate::{Architecture, CopyAddress, MemoryError, PutAddress};
+
+/// How often the tracer thread checks for signals to forward while the target is running.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// A unit of work run on the tracer thread, which is the only thread allowed to use `ptrace` on
+/// the target.
+type Command = Box<dyn FnOnce(&mut Tracer) + Send>;
+
+/// Call `ptrace`, turning a return value of `-1` into the last OS error.
+pub(crate) fn ptrace(
+    request: c_int,
+    tid: Pid,
+    addr: usize,
+    data: usize,
+) -> std::io::Result<libc::c_long> {
+    // `PEEK` requests can legitimately return `-1`, so errno has to be cleared first.
+    unsafe { *libc::__errno_location() = 0 };
+    #[allow(clippy::useless_conversion)]
+    let result = unsafe { libc::ptrace(request as _, tid, addr, data) };
+    if result == -1 && std::io::Error::last_os_error().raw_os_error() != Some(0) {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// Turn an error from attaching to a process, or opening one of its files in `/proc`, into a
+/// [`MemoryError`] if it is because the process cannot be accessed or has exited.
+///
+/// [`MemoryError`]: enum.MemoryError.html
+pub(crate) fn access_error(error: std::io::Error, pid: Pid) -> std::io::Error {
+    match error.raw_os_error() {
+        Some(libc::EPERM | libc::EACCES) => MemoryError::permission_denied(pid).into(),
+        Some(libc::ESRCH | libc::ENOENT) => MemoryError::ProcessGone { pid }.into(),
+        _ => error,
+    }
+}
+
+/// Send a signal to a single thread of a process.
+fn tgkill(pid: Pid, tid: Pid, signal: c_int) {
+    unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, signal) };
+}
+
+/// List the threads of a process from `/proc/<pid>/task`.
+pub(crate) fn task_ids(pid: Pid) -> std::io::Result<Vec<Pid>> {
+    let mut tids = Vec::new();
+    for entry in std::fs::read_dir(format!("/proc/{pid}/task"))? {
+        if let Some(tid) = entry?.file_name().to_str().and_then(|tid| tid.parse().ok()) {
+            tids.push(tid);
+        }
+    }
+    tids.sort_unstable();
+    Ok(tids)
+}
+
+/// A thread that is being traced.
+#[derive(Debug, Default)]
+pub(crate) struct TracedThread {
+    /// A signal that arrived while the thread was being stopped, which is delivered again when
+    /// it resumes.
+    pending_signal: Option<c_int>,
+    /// The generation of the watchpoints last written to the thread's debug registers.
+    #[cfg(target_arch = "x86_64")]
+    watchpoint_generation: u64,
+}
+
+/// The `ptrace` state of an attached process. This lives on the tracer thread.
+#[derive(Debug)]
+pub(crate) struct Tracer {
+    pub(crate) pid: Pid,
+    pub(crate) threads: BTreeMap<Pid, TracedThread>,
+    pub(crate) stopped: bool,
+    /// Leave the process stopped with `SIGSTOP` when detaching, because a thread was abandoned
+    /// in a state it cannot safely continue from.
+    pub(crate) stop_on_detach: bool,
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) watchpoints: super::watchpoint::Watchpoints,
+}
+
+impl Tracer {
+    fn new(pid: Pid) -> Self {
+        Self {
+            pid,
+            threads: BTreeMap::new(),
+            stopped: false,
+            stop_on_detach: false,
+            #[cfg(target_arch = "x86_64")]
+            watchpoints: super::watchpoint::Watchpoints::default(),
+        }
+    }
+
+    /// Seize every thread of the process, repeating until no new threads appear.
+    fn seize_all(&mut self) -> std::io::Result<()> {
+        loop {
+            let mut seized = false;
+            for tid in task_ids(self.pid)? {
+                if self.threads.contains_key(&tid) {
+                    continue;
+                }
+                match ptrace(
+                    libc::PTRACE_SEIZE as _,
+                    tid,
+                    0,
+                    libc::PTRACE_O_TRACECLONE as usize,
+                ) {
+                    Ok(_) => {
+                        self.threads.insert(tid, TracedThread::default());
+                        seized = true;
+                    }
+                    // The thread exited before it could be seized.
+                    Err(error) if error.raw_os_error() == Some(libc::ESRCH) => {}
+                    Err(error) => return Err(error),
+                }
+            }
+            if !seized {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Stop every thread, waiting until each one is in a ptrace-stop.
+    pub(crate) fn interrupt(&mut self) -> std::io::Result<()> {
+        if self.stopped {
+            return Ok(());
+        }
+        let tids: Vec<Pid> = self.threads.keys().copied().collect();
+        for tid in &tids {
+            if let Err(error) = ptrace(libc::PTRACE_INTERRUPT as _, *tid, 0, 0) {
+                if error.raw_os_error() != Some(libc::ESRCH) {
+                    return Err(error);
+                }
+            }
+        }
+        let mut waiting = tids;
+        while let Some(tid) = waiting.pop() {
+            self.wait_for_stop(tid, &mut waiting)?;
+        }
+        self.stopped = true;
+        self.ensure_alive()
+    }
+
+    /// Wait for a thread to report an interrupt stop, forwarding anything else it reports.
+    /// Threads created in the meantime are added to `waiting`.
+    pub(crate) fn wait_for_stop(&mut self, tid: Pid, waiting: &mut Vec<Pid>) -> std::io::Result<()> {
+        loop {
+            let mut status = 0;
+            if unsafe { libc::waitpid(tid, &mut status, libc::__WALL) } == -1 {
+                let error = std::io::Error::last_os_error();
+                if error.raw_os_error() == Some(libc::ECHILD) {
+                    self.threads.remove(&tid);
+                    return Ok(());
+                }
+                return Err(error);
+            }
+            if !libc::WIFSTOPPED(status) {
+                // The thread exited.
+                self.threads.remove(&tid);
+                return Ok(());
+            }
+            let signal = libc::WSTOPSIG(status);
+            match status >> 16 {
+                libc::PTRACE_EVENT_STOP => {
+                    self.sync_thread(tid);
+                    return Ok(());
+                }
+                libc::PTRACE_EVENT_CLONE => {
+                    let child = self.event_message(tid)?;
+                    self.threads.entry(child).or_default();
+                    waiting.push(child);
+                }
+                0 if signal == libc::SIGTRAP && self.on_trap(tid) => {}
+                0 => {
+                    // A signal arrived before the interrupt did. It is held until the thread
+                    // resumes so that the stop is not visible to the target.
+                    self.on_signal(tid, signal);
+                }
+                _ => {}
+            }
+            ptrace(libc::PTRACE_CONT as _, tid, 0, 0)?;
+        }
+    }
+
+    /// Record a signal that was suppressed while stopping a thread.
+    pub(crate) fn on_signal(&mut self, tid: Pid, signal: c_int) {
+        if let Some(thread) = self.threads.get_mut(&tid) {
+            thread.pending_signal = Some(signal);
+        }
+    }
+
+    /// Handle a `SIGTRAP` caused by the tracer, such as a watchpoint being hit. Returns `true` if
+    /// the signal should be hidden from the target.
+    pub(crate) fn on_trap(&mut self, tid: Pid) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        if self.watchpoints.on_trap(tid).unwrap_or(false) {
+            return true;
+        }
+        let _ = tid;
+        false
+    }
+
+    /// Bring the debug registers of a stopped thread up to date.
+    pub(crate) fn sync_thread(&mut self, tid: Pid) {
+        #[cfg(target_arch = "x86_64")]
+        if let Some(thread) = self.threads.get_mut(&tid) {
+            if thread.watchpoint_generation != self.watchpoints.generation
+                && self.watchpoints.apply(tid).is_ok()
+            {
+                thread.watchpoint_generation = self.watchpoints.generation;
+            }
+        }
+        let _ = tid;
+    }
+
+    /// Get the message of the last ptrace event, such as the id of a newly cloned thread.
+    pub(crate) fn event_message(&self, tid: Pid) -> std::io::Result<Pid> {
+        let mut message: libc::c_ulong = 0;
+        ptrace(
+            libc::PTRACE_GETEVENTMSG as _,
+            tid,
+            0,
+            std::ptr::addr_of_mut!(message) as usize,
+        )?;
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(message as Pid)
+    }
+
+    /// Run `f` on a thread in a ptrace-stop, stopping the process first and resuming it
+    /// afterwards if it was running. `tid` picks the thread, and `None` picks the main thread, or
+    /// any other if it has exited. The process is not resumed if `f` abandoned a thread in it.
+    pub(crate) fn while_stopped<R>(
+        &mut self,
+        tid: Option<Pid>,
+        f: impl FnOnce(&mut Self, Pid) -> std::io::Result<R>,
+    ) -> std::io::Result<R> {
+        let was_running = !self.stopped;
+        self.interrupt()?;
+        // Threads may have exited while the process was being stopped.
+        let tid = match tid {
+            Some(tid) if self.threads.contains_key(&tid) => Ok(tid),
+            Some(tid) => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Thread {tid} is not attached, or has exited"),
+            )),
+            None if self.threads.contains_key(&self.pid) => Ok(self.pid),
+            None => self
+                .threads
+                .keys()
+                .next()
+                .copied()
+                .ok_or_else(|| std::io::Error::from_raw_os_error(libc::ESRCH)),
+        };
+        let result = tid.and_then(|tid| f(self, tid));
+        if was_running && !self.stop_on_detach {
+            self.resume()?;
+        }
+        result
+    }
+
+    /// Resume every thread, delivering any signals that were held while it was stopped.
+    pub(crate) fn resume(&mut self) -> std::io::Result<()> {
+        if !self.stopped {
+            return Ok(());
+        }
+        self.ensure_alive()?;
+        for tid in self.threads.keys() {
+            if let Err(error) = ptrace(libc::PTRACE_CONT as _, *tid, 0, 0) {
+                if error.raw_os_error() != Some(libc::ESRCH) {
+                    return Err(error);
+                }
+            }
+        }
+        self.stopped = false;
+        self.redeliver_signals();
+        Ok(())
+    }
+
+    fn redeliver_signals(&mut self) {
+        for (tid, thread) in &mut self.threads {
+            if let Some(signal) = thread.pending_signal.take() {
+                tgkill(self.pid, *tid, signal);
+            }
+        }
+    }
+
+    /// Handle everything the running threads have reported, so that signals reach the target
+    /// without delay and new threads are kept track of.
+    fn poll(&mut self) {
+        loop {
+            let mut status = 0;
+            let tid = unsafe {
+                libc::waitpid(-1, &mut status, libc::WNOHANG | libc::__WALL | libc::__WNOTHREAD)
+            };
+            if tid <= 0 {
+                return;
+            }
+            if !libc::WIFSTOPPED(status) {
+                self.threads.remove(&tid);
+                continue;
+            }
+            let signal = libc::WSTOPSIG(status);
+            let _ = match status >> 16 {
+                libc::PTRACE_EVENT_CLONE => {
+                    if let Ok(child) = self.event_message(tid) {
+                        self.threads.entry(child).or_default();
+                    }
+                    ptrace(libc::PTRACE_CONT as _, tid, 0, 0)
+                }
+                // A new thread starting, or a stray interrupt.
+                libc::PTRACE_EVENT_STOP if signal == libc::SIGTRAP => {
+                    self.threads.entry(tid).or_default();
+                    self.sync_thread(tid);
+                    ptrace(libc::PTRACE_CONT as _, tid, 0, 0)
+                }
+                // A group-stop, such as from `SIGSTOP`, which should keep the thread stopped.
+                libc::PTRACE_EVENT_STOP => ptrace(libc::PTRACE_LISTEN as _, tid, 0, 0),
+                0 if signal == libc::SIGTRAP && self.on_trap(tid) => {
+                    ptrace(libc::PTRACE_CONT as _, tid, 0, 0)
+                }
+                0 => ptrace(libc::PTRACE_CONT as _, tid, 0, signal as usize),
+                _ => ptrace(libc::PTRACE_CONT as _, tid, 0, 0),
+            };
+        }
+    }
+
+    /// Stop every thread and detach from it.
+    fn detach(&mut self) {
+        if self.interrupt().is_err() {
+            return;
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.watchpoints = super::watchpoint::Watchpoints::default();
+            for tid in self.threads.keys() {
+                let _ = self.watchpoints.apply(*tid);
+            }
+        }
+        if self.stop_on_detach {
+            // The signal argument of `PTRACE_DETACH` is ignored unless the thread is stopped for a
+            // signal, so `SIGSTOP` is queued for the whole process instead and takes effect as soon
+            // as it is detached.
+            unsafe { libc::kill(self.pid, libc::SIGSTOP) };
+        }
+        for tid in self.threads.keys() {
+            let _ = ptrace(libc::PTRACE_DETACH as _, *tid, 0, 0);
+        }
+        self.redeliver_signals();
+        self.threads.clear();
+    }
+
+    /// Return an error if every thread of the process has exited.
+    pub(crate) fn ensure_alive(&self) -> std::io::Result<()> {
+        if self.threads.is_empty() {
+            Err(std::io::Error::from_raw_os_error(libc::ESRCH))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn run(mut self, commands: &Receiver<Command>) {
+        loop {
+            let command = if self.stopped {
+                commands.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                commands.recv_timeout(POLL_INTERVAL)
+            };
+            match command {
+                Ok(command) => command(&mut self),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if !self.stopped {
+                self.poll();
+            }
+        }
+        self.detach();
+    }
+}
+
+/// A `ptrace` attachment to every thread of a process on Linux.
+///
+/// [`ProcessHandle`]s read and write memory with `process_vm_readv` while the target keeps
+/// running, so a value can change half way through reading a structure. An `AttachSession` uses
+/// `PTRACE_SEIZE` on every thread in `/proc/<pid>/task` (and every thread created afterwards),
+/// so that all of them can be stopped with [`AttachSession::interrupt`] and started again with
+/// [`AttachSession::resume`]. The session detaches when it is dropped.
+///
+/// `AttachSession` implements [`CopyAddress`] and [`PutAddress`], so [`DataMember`]s can be used
+/// with it through [`AttachSession::handle`], or it can be used directly wherever a
+/// [`CopyAddress`] is accepted.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, AttachSession, CopyAddress};
+/// let handle = get_handle("game").unwrap();
+/// let session = AttachSession::attach(handle).unwrap();
+/// session.interrupt().unwrap();
+/// // Every read here sees the same state of the game
+/// let mut position = [0_u8; 12];
+/// session.copy_address(0x1234, &mut position).unwrap();
+/// session.resume().unwrap();
+/// ```
+///
+/// Signals sent to the target while it is attached are forwarded by a dedicated tracer thread,
+/// as `ptrace` only allows the thread that attached to control the target.
+///
+/// [`ProcessHandle`]: type.ProcessHandle.html
+/// [`CopyAddress`]: trait.CopyAddress.html
+/// [`PutAddress`]: trait.PutAddress.html
+/// [`DataMember`]: struct.DataMember.html
+#[derive(Debug)]
+pub struct AttachSession {
+    handle: ProcessHandle,
+    commands: Option<Sender<Command>>,
+    tracer: Option<JoinHandle<()>>,
+}
+
+impl AttachSession {
+    /// Seize every thread of a process. The process keeps running until
+    /// [`AttachSession::interrupt`] is called.
+    ///
+    /// # Errors
+    /// Returns an error if the threads of the process cannot be listed, or if `PTRACE_SEIZE`
+    /// fails, for example because of `/proc/sys/kernel/yama/ptrace_scope` or because another
+    /// debugger is already attached.
+    pub fn attach(handle: ProcessHandle) -> std::io::Result<Self> {
+        let (commands, receiver) = mpsc::channel::<Command>();
+        let (result_sender, result) = mpsc::channel();
+        let pid = handle.0;
+        let tracer = std::thread::Builder::new()
+            .name(format!("titanium-tracer-{pid}"))
+            .spawn(move || {
+                let mut tracer = Tracer::new(pid);
+                let seized = tracer.seize_all();
+                let failed = seized.is_err();
+                let _ = result_sender.send(seized);
+                if failed {
+                    tracer.detach();
+                } else {
+                    tracer.run(&receiver);
+                }
+            })?;
+        result
+            .recv()
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?
+            .map_err(|error| access_error(error, pid))?;
+        Ok(Self {
+            handle,
+            commands: Some(commands),
+            tracer: Some(tracer),
+        })
+    }
+
+    /// Run `f` on the tracer thread, returning its result.
+    pub(crate) fn with_tracer<R, F>(&self, f: F) -> std::io::Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut Tracer) -> std::io::Result<R> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let command: Command = Box::new(move |tracer| {
+            let _ = sender.send(f(tracer));
+        });
+        self.commands
+            .as_ref()
+            .and_then(|commands| commands.send(command).ok())
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        receiver
+            .recv()
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?
+    }
+
+    /// Stop every thread of the process, returning once all of them have stopped.
+    ///
+    /// # Errors
+    /// Returns an error if the threads cannot be interrupted, or if the process has exited.
+    pub fn interrupt(&self) -> std::io::Result<()> {
+        self.with_tracer(Tracer::interrupt)
+    }
+
+    /// Resume every thread of the process.
+    ///
+    /// # Errors
+    /// Returns an error if the threads cannot be resumed, or if the process has exited.
+    pub fn resume(&self) -> std::io::Result<()> {
+        self.with_tracer(Tracer::resume)
+    }
+
+    /// Returns `true` if the process is stopped by [`AttachSession::interrupt`].
+    ///
+    /// # Errors
+    /// Returns an error if the tracer thread has exited.
+    pub fn is_stopped(&self) -> std::io::Result<bool> {
+        self.with_tracer(|tracer| Ok(tracer.stopped))
+    }
+
+    /// The ids of every attached thread.
+    ///
+    /// # Errors
+    /// Returns an error if the tracer thread has exited.
+    pub fn threads(&self) -> std::io::Result<Vec<Pid>> {
+        self.with_tracer(|tracer| Ok(tracer.threads.keys().copied().collect()))
+    }
+
+    /// The handle of the attached process.
+    #[must_use]
+    pub fn handle(&self) -> ProcessHandle {
+        self.handle
+    }
+
+    /// The id of the attached process.
+    #[must_use]
+    pub fn pid(&self) -> Pid {
+        self.handle.0
+    }
+}
+
+/// Detach from every thread, resuming the process if it was stopped.
+impl Drop for AttachSession {
+    fn drop(&mut self) {
+        self.commands.take();
+        if let Some(tracer) = self.tracer.take() {
+            let _ = tracer.join();
+        }
+    }
+}
+
+impl CopyAddress for AttachSession {
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn get_pointer_width(&self) -> Architecture {
+        self.handle.get_pointer_width()
+    }
+
+    fn copy_address(&self, addr: usize, buf: &mut [u8]) -> std::io::Result<()> {
+        self.handle.copy_address(addr, buf)
+    }
+}
+
+impl PutAddress for AttachSession {
+    fn put_address(&self, addr: usize, buf: &[u8]) -> std::io::Result<()> {
+        self.handle.put_address(addr, buf)
+    }
+}
@@ -0,0 +1,313 @@
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+
+use super::attach::{access_error, ptrace, AttachSession};
+use super::{short_transfer, Pid, ProcessHandle};
+use crate::{Architecture, CopyAddress, MemoryError, PutAddress};
+
+const WORD_SIZE: usize = std::mem::size_of::<libc::c_long>();
+
+/// A way of reading and writing the memory of another process on Linux.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryBackend {
+    /// `process_vm_readv` and `process_vm_writev`, which copy any number of ranges in one system
+    /// call. They are the fastest, but some seccomp profiles block them, and they cannot write to
+    /// read-only pages.
+    ProcessVm,
+    /// `pread` and `pwrite` on `/proc/<pid>/mem`. Writes go through the kernel's
+    /// `FOLL_FORCE` path, which ignores the page protection, so read-only code and data can be
+    /// patched without changing it.
+    ProcMem,
+    /// `PTRACE_PEEKDATA` and `PTRACE_POKEDATA`, one word at a time, through an
+    /// [`AttachSession`]. This is by far the slowest, and stops the process for every access, but
+    /// works wherever the process can be traced.
+    ///
+    /// [`AttachSession`]: struct.AttachSession.html
+    Ptrace,
+}
+
+impl MemoryBackend {
+    /// Every backend, in the order [`BackendHandle::probe`] tries them.
+    ///
+    /// [`BackendHandle::probe`]: struct.BackendHandle.html#method.probe
+    pub const ALL: [Self; 3] = [Self::ProcessVm, Self::ProcMem, Self::Ptrace];
+}
+
+#[derive(Debug)]
+enum Access {
+    ProcessVm,
+    ProcMem(File),
+    Ptrace(AttachSession),
+}
+
+/// A [`ProcessHandle`] that reads and writes memory with a chosen [`MemoryBackend`].
+///
+/// [`ProcessHandle`]s always use `process_vm_readv`, which fails under some seccomp profiles and
+/// kernels. A `BackendHandle` implements [`CopyAddress`] and [`PutAddress`] with whichever
+/// backend works, so it can be used anywhere a `ProcessHandle` is read from or written to.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, BackendHandle, CopyAddress};
+/// let handle = get_handle("game").unwrap();
+/// let memory = BackendHandle::probe(handle).unwrap();
+/// println!("Using {:?}", memory.backend());
+/// let mut health = [0_u8; 4];
+/// memory.copy_address(0x1234, &mut health).unwrap();
+/// ```
+///
+/// [`ProcessHandle`]: type.ProcessHandle.html
+/// [`MemoryBackend`]: enum.MemoryBackend.html
+/// [`CopyAddress`]: trait.CopyAddress.html
+/// [`PutAddress`]: trait.PutAddress.html
+#[derive(Debug)]
+pub struct BackendHandle {
+    handle: ProcessHandle,
+    access: Access,
+}
+
+impl BackendHandle {
+    /// Use `backend` for the memory of a process. For [`MemoryBackend::ProcMem`] this opens
+    /// `/proc/<pid>/mem`, for writing as well if that is allowed, and for
+    /// [`MemoryBackend::Ptrace`] it attaches to the process.
+    ///
+    /// # Errors
+    /// Returns an error if the memory file cannot be opened, or if the process cannot be
+    /// attached to. Whether the backend can actually read the memory is only checked by
+    /// [`BackendHandle::probe`].
+    ///
+    /// [`MemoryBackend::ProcMem`]: enum.MemoryBackend.html#variant.ProcMem
+    /// [`MemoryBackend::Ptrace`]: enum.MemoryBackend.html#variant.Ptrace
+    /// [`BackendHandle::probe`]: struct.BackendHandle.html#method.probe
+    pub fn new(handle: ProcessHandle, backend: MemoryBackend) -> std::io::Result<Self> {
+        let access = match backend {
+            MemoryBackend::ProcessVm => Access::ProcessVm,
+            MemoryBackend::ProcMem => Access::ProcMem(open_mem(handle.0)?),
+            MemoryBackend::Ptrace => Access::Ptrace(AttachSession::attach(handle)?),
+        };
+        Ok(Self { handle, access })
+    }
+
+    /// Try every backend in the order of [`MemoryBackend::ALL`], returning the first one that
+    /// can read the first readable region of the process.
+    ///
+    /// # Errors
+    /// Returns an error if the memory map of the process cannot be read or has no readable
+    /// region, or the error of the last backend if none of them work.
+    ///
+    /// [`MemoryBackend::ALL`]: enum.MemoryBackend.html#associatedconstant.ALL
+    pub fn probe(handle: ProcessHandle) -> std::io::Result<Self> {
+        let region = super::regions(&handle)?
+            .into_iter()
+            .find(|region| region.permissions.read && region.end > region.start)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("Process {} has no readable memory", handle.0),
+                )
+            })?;
+        let mut buf = [0_u8; WORD_SIZE];
+        let mut last_error = None;
+        for backend in MemoryBackend::ALL {
+            match Self::new(handle, backend).and_then(|memory| {
+                memory.copy_address(region.start, &mut buf)?;
+                Ok(memory)
+            }) {
+                Ok(memory) => return Ok(memory),
+                Err(error) => last_error = Some(error),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| std::io::Error::other("No memory backend works")))
+    }
+
+    /// The backend used to access memory.
+    #[must_use]
+    pub fn backend(&self) -> MemoryBackend {
+        match self.access {
+            Access::ProcessVm => MemoryBackend::ProcessVm,
+            Access::ProcMem(_) => MemoryBackend::ProcMem,
+            Access::Ptrace(_) => MemoryBackend::Ptrace,
+        }
+    }
+
+    /// The handle of the process.
+    #[must_use]
+    pub fn handle(&self) -> ProcessHandle {
+        self.handle
+    }
+}
+
+impl CopyAddress for BackendHandle {
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn get_pointer_width(&self) -> Architecture {
+        self.handle.get_pointer_width()
+    }
+
+    fn copy_address(&self, addr: usize, buf: &mut [u8]) -> std::io::Result<()> {
+        let result = match &self.access {
+            Access::ProcessVm => self.handle.copy_address(addr, buf),
+            Access::ProcMem(file) => read_mem(file, addr, buf),
+            Access::Ptrace(session) => {
+                let len = buf.len();
+                session
+                    .with_tracer(move |tracer| {
+                        tracer.while_stopped(None, |_, tid| peek(tid, addr, len))
+                    })
+                    .map(|bytes| buf.copy_from_slice(&bytes))
+            }
+        };
+        result.map_err(|error| MemoryError::from_os_error(error, self.handle.0, addr).into())
+    }
+
+    fn copy_addresses(&self, entries: &mut [(usize, &mut [u8])]) -> Vec<std::io::Result<()>> {
+        match &self.access {
+            Access::ProcessVm => self.handle.copy_addresses(entries),
+            _ => entries
+                .iter_mut()
+                .map(|(addr, buf)| self.copy_address(*addr, buf))
+                .collect(),
+        }
+    }
+}
+
+impl PutAddress for BackendHandle {
+    fn put_address(&self, addr: usize, buf: &[u8]) -> std::io::Result<()> {
+        let result = match &self.access {
+            Access::ProcessVm => self.handle.put_address(addr, buf),
+            Access::ProcMem(file) => write_mem(file, addr, buf),
+            Access::Ptrace(session) => {
+                let bytes = buf.to_vec();
+                session.with_tracer(move |tracer| {
+                    tracer.while_stopped(None, |_, tid| poke(tid, addr, &bytes))
+                })
+            }
+        };
+        result.map_err(|error| MemoryError::from_os_error(error, self.handle.0, addr).into())
+    }
+
+    fn put_addresses(&self, entries: &[(usize, &[u8])]) -> Vec<std::io::Result<()>> {
+        match &self.access {
+            Access::ProcessVm => self.handle.put_addresses(entries),
+            _ => entries
+                .iter()
+                .map(|(addr, buf)| self.put_address(*addr, buf))
+                .collect(),
+        }
+    }
+}
+
+/// Open `/proc/<pid>/mem` for reading and writing, or only for reading if writing is not allowed.
+fn open_mem(pid: Pid) -> std::io::Result<File> {
+    let path = format!("/proc/{pid}/mem");
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .or_else(|_| File::open(&path))
+        .map_err(|error| access_error(error, pid))
+}
+
+/// `/proc/<pid>/mem` is addressed by file offset, which cannot go past `i64::MAX`.
+fn mem_offset(addr: usize) -> std::io::Result<u64> {
+    u64::try_from(addr)
+        .ok()
+        .filter(|offset| i64::try_from(*offset).is_ok())
+        .ok_or_else(|| std::io::Error::from_raw_os_error(libc::EFAULT))
+}
+
+fn read_mem(file: &File, addr: usize, buf: &mut [u8]) -> std::io::Result<()> {
+    let offset = mem_offset(addr)?;
+    let mut done = 0;
+    while done < buf.len() {
+        match file.read_at(&mut buf[done..], offset + done as u64) {
+            Ok(0) => return Err(short_transfer(addr, done, buf.len(), false)),
+            Ok(read) => done += read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(error) if done == 0 => return Err(error),
+            Err(_) => return Err(short_transfer(addr, done, buf.len(), false)),
+        }
+    }
+    Ok(())
+}
+
+fn write_mem(file: &File, addr: usize, buf: &[u8]) -> std::io::Result<()> {
+    let offset = mem_offset(addr)?;
+    let mut done = 0;
+    while done < buf.len() {
+        match file.write_at(&buf[done..], offset + done as u64) {
+            Ok(0) => return Err(short_transfer(addr, done, buf.len(), true)),
+            Ok(written) => done += written,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(error) if done == 0 => return Err(error),
+            Err(_) => return Err(short_transfer(addr, done, buf.len(), true)),
+        }
+    }
+    Ok(())
+}
+
+/// Write `bytes` to another process through `/proc/<pid>/mem`, which works even if the pages are
+/// not writable.
+pub(crate) fn force_write(pid: Pid, addr: usize, bytes: &[u8]) -> std::io::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .open(format!("/proc/{pid}/mem"))?;
+    write_mem(&file, addr, bytes)
+}
+
+fn peek_word(tid: Pid, addr: usize) -> std::io::Result<[u8; WORD_SIZE]> {
+    let word = ptrace(libc::PTRACE_PEEKDATA as _, tid, addr, 0)?;
+    Ok(word.to_ne_bytes())
+}
+
+fn poke_word(tid: Pid, addr: usize, word: [u8; WORD_SIZE]) -> std::io::Result<()> {
+    #[allow(clippy::cast_sign_loss)]
+    let data = libc::c_long::from_ne_bytes(word) as usize;
+    ptrace(libc::PTRACE_POKEDATA as _, tid, addr, data).map(drop)
+}
+
+/// Read `len` bytes at `addr` a word at a time.
+fn peek(tid: Pid, addr: usize, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(len);
+    let mut word_addr = addr & !(WORD_SIZE - 1);
+    while bytes.len() < len {
+        let word = match peek_word(tid, word_addr) {
+            Ok(word) => word,
+            Err(error) if bytes.is_empty() => return Err(error),
+            Err(_) => return Err(short_transfer(addr, bytes.len(), len, false)),
+        };
+        let skip = addr.saturating_sub(word_addr);
+        let take = (WORD_SIZE - skip).min(len - bytes.len());
+        bytes.extend_from_slice(&word[skip..skip + take]);
+        word_addr += WORD_SIZE;
+    }
+    Ok(bytes)
+}
+
+/// Write `bytes` at `addr` a word at a time, keeping the bytes around them in the first and last
+/// word.
+fn poke(tid: Pid, addr: usize, bytes: &[u8]) -> std::io::Result<()> {
+    let mut done = 0;
+    let mut word_addr = addr & !(WORD_SIZE - 1);
+    while done < bytes.len() {
+        let skip = addr.saturating_sub(word_addr);
+        let take = (WORD_SIZE - skip).min(bytes.len() - done);
+        let result = if take == WORD_SIZE {
+            let mut word = [0_u8; WORD_SIZE];
+            word.copy_from_slice(&bytes[done..done + WORD_SIZE]);
+            poke_word(tid, word_addr, word)
+        } else {
+            peek_word(tid, word_addr).and_then(|mut word| {
+                word[skip..skip + take].copy_from_slice(&bytes[done..done + take]);
+                poke_word(tid, word_addr, word)
+            })
+        };
+        match result {
+            Ok(()) => {}
+            Err(error) if done == 0 => return Err(error),
+            Err(_) => return Err(short_transfer(addr, done, bytes.len(), true)),
+        }
+        done += take;
+        word_addr += WORD_SIZE;
+    }
+    Ok(())
+}
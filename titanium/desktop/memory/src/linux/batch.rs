@@ -0,0 +1,213 @@
+use libc::{c_void, iovec, pid_t, process_vm_readv, process_vm_writev};
+
+use super::short_transfer;
+use crate::MemoryError;
+
+/// The most `iovec`s `process_vm_readv` and `process_vm_writev` accept in one call (`UIO_MAXIOV`
+/// in the kernel).
+const IOV_MAX: usize = 1024;
+
+/// Transfer every `(remote address, local buffer, length)` entry with as few system calls as
+/// possible, returning the result of every entry in order.
+///
+/// The kernel stops at the first remote range it cannot access and returns the number of bytes it
+/// transferred until then, so the entries before it are complete and the next call picks up after
+/// it. Errors that are not about an address, such as the process exiting, fail every entry that is
+/// left.
+fn transfer(pid: pid_t, entries: &[(usize, *mut c_void, usize)], write: bool) -> Vec<std::io::Result<()>> {
+    let mut results = Vec::with_capacity(entries.len());
+    let mut start = 0;
+    while start < entries.len() {
+        let chunk = &entries[start..entries.len().min(start + IOV_MAX)];
+        let local: Vec<_> = chunk
+            .iter()
+            .map(|&(_, buf, len)| iovec {
+                iov_base: buf,
+                iov_len: len,
+            })
+            .collect();
+        let remote: Vec<_> = chunk
+            .iter()
+            .map(|&(addr, _, len)| iovec {
+                iov_base: addr as *mut c_void,
+                iov_len: len,
+            })
+            .collect();
+        #[allow(clippy::cast_possible_truncation)]
+        let count = chunk.len() as libc::c_ulong;
+        let result = unsafe {
+            if write {
+                process_vm_writev(pid, local.as_ptr(), count, remote.as_ptr(), count, 0)
+            } else {
+                process_vm_readv(pid, local.as_ptr(), count, remote.as_ptr(), count, 0)
+            }
+        };
+
+        let Ok(mut transferred) = usize::try_from(result) else {
+            let error = std::io::Error::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EFAULT) => {
+                    results.push(Err(MemoryError::from_os_error(error, pid, chunk[0].0).into()));
+                    start += 1;
+                }
+                code => {
+                    for &(addr, _, _) in &entries[start..] {
+                        results.push(Err(code.map_or_else(
+                            || std::io::Error::other("Transferring process memory failed"),
+                            |code| {
+                                let error = std::io::Error::from_raw_os_error(code);
+                                MemoryError::from_os_error(error, pid, addr).into()
+                            },
+                        )));
+                    }
+                    break;
+                }
+            }
+            continue;
+        };
+        let mut done = 0;
+        for &(_, _, len) in chunk {
+            if transferred < len {
+                break;
+            }
+            transferred -= len;
+            results.push(Ok(()));
+            done += 1;
+        }
+        start += done;
+        if done < chunk.len() && (transferred > 0 || done == 0) {
+            // The entry was only partly transferred, so retrying it would stop at the same place.
+            let (addr, _, len) = chunk[done];
+            results.push(Err(short_transfer(addr, transferred, len, write)));
+            start += 1;
+        }
+    }
+    results
+}
+
+/// Read many ranges of another process into buffers with `process_vm_readv`.
+pub(crate) fn copy_addresses(pid: pid_t, entries: &mut [(usize, &mut [u8])]) -> Vec<std::io::Result<()>> {
+    let entries: Vec<_> = entries
+        .iter_mut()
+        .map(|(addr, buf)| (*addr, buf.as_mut_ptr().cast::<c_void>(), buf.len()))
+        .collect();
+    transfer(pid, &entries, false)
+}
+
+/// Write many buffers to ranges of another process with `process_vm_writev`.
+pub(crate) fn put_addresses(pid: pid_t, entries: &[(usize, &[u8])]) -> Vec<std::io::Result<()>> {
+    // `process_vm_writev` only reads from the local buffers, even though `iovec` is mutable.
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|(addr, buf)| (*addr, buf.as_ptr() as *mut c_void, buf.len()))
+        .collect();
+    transfer(pid, &entries, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_SIZE: usize = 0x1000;
+
+    fn pid() -> pid_t {
+        std::process::id() as pid_t
+    }
+
+    fn errors(results: Vec<std::io::Result<()>>) -> Vec<Option<MemoryError>> {
+        results
+            .into_iter()
+            .map(|result| result.err().map(MemoryError::from))
+            .collect()
+    }
+
+    #[test]
+    fn reads_around_unmapped_entries() {
+        let source = [1_u8, 2, 3, 4, 5, 6, 7, 8];
+        let (mut first, mut missing, mut last) = ([0_u8; 4], [0_u8; 4], [0_u8; 4]);
+        let mut entries = [
+            (source.as_ptr() as usize, &mut first[..]),
+            (0x10, &mut missing[..]),
+            (source.as_ptr() as usize + 4, &mut last[..]),
+        ];
+        let errors = errors(copy_addresses(pid(), &mut entries));
+        assert!(errors[0].is_none() && errors[2].is_none());
+        assert!(matches!(
+            errors[1],
+            Some(MemoryError::UnmappedAddress {
+                address: 0x10,
+                level: None
+            })
+        ));
+        assert_eq!((first, last), ([1, 2, 3, 4], [5, 6, 7, 8]));
+    }
+
+    #[test]
+    fn reports_partly_read_entries() {
+        // Map two pages and unmap the second, so a range can run off the end of the first.
+        let pages = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                2 * PAGE_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(pages, libc::MAP_FAILED);
+        let end = pages as usize + PAGE_SIZE;
+        assert_eq!(unsafe { libc::munmap(end as *mut c_void, PAGE_SIZE) }, 0);
+
+        let (mut straddling, mut after) = ([0_u8; 0x20], [0_u8; 4]);
+        let source = [9_u8; 4];
+        let mut entries = [
+            (end - 0x10, &mut straddling[..]),
+            (source.as_ptr() as usize, &mut after[..]),
+        ];
+        let errors = errors(copy_addresses(pid(), &mut entries));
+        unsafe { libc::munmap(pages, PAGE_SIZE) };
+        assert!(matches!(
+            errors[0],
+            Some(MemoryError::PartialTransfer {
+                done: 0x10,
+                len: 0x20,
+                write: false,
+                ..
+            })
+        ));
+        assert!(errors[1].is_none());
+        assert_eq!(after, source);
+    }
+
+    #[test]
+    fn writes_more_entries_than_one_call_takes() {
+        let mut targets = vec![0_u8; IOV_MAX + 10];
+        let values: Vec<u8> = (0..targets.len()).map(|index| index as u8).collect();
+        let base = targets.as_mut_ptr() as usize;
+        let entries: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (base + index, std::slice::from_ref(value)))
+            .collect();
+        let results = put_addresses(pid(), &entries);
+        assert_eq!(results.len(), entries.len());
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(*std::hint::black_box(&targets), values);
+    }
+
+    #[test]
+    fn fails_every_entry_of_a_missing_process() {
+        let mut buffers = [[0_u8; 4]; 3];
+        let mut entries: Vec<_> = buffers
+            .iter_mut()
+            .enumerate()
+            .map(|(index, buffer)| (0x1000 * (index + 1), &mut buffer[..]))
+            .collect();
+        let errors = errors(copy_addresses(pid_t::MAX, &mut entries));
+        assert_eq!(errors.len(), 3);
+        assert!(errors
+            .iter()
+            .all(|error| matches!(error, Some(MemoryError::ProcessGone { pid: pid_t::MAX }))));
+    }
+}
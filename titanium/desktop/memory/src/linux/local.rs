@@ -0,0 +1,116 @@
+use super::{Pid, ProcessHandle};
+use crate::allocation::{near_candidates, nothing_near};
+use crate::{Architecture, Permissions};
+
+/// The lowest address `mmap` will place a mapping at by default.
+const MIN_ADDRESS: usize = 0x1_0000;
+pub(crate) const PAGE_SIZE: usize = 0x1000;
+
+/// The `PROT_*` flags for a set of permissions.
+pub(crate) fn prot(permissions: Permissions) -> libc::c_int {
+    let mut prot = libc::PROT_NONE;
+    if permissions.read {
+        prot |= libc::PROT_READ;
+    }
+    if permissions.write {
+        prot |= libc::PROT_WRITE;
+    }
+    if permissions.execute {
+        prot |= libc::PROT_EXEC;
+    }
+    prot
+}
+
+/// Round `size` up to a whole number of pages.
+pub(crate) fn page_align(size: usize) -> Option<usize> {
+    Some(size.checked_add(PAGE_SIZE - 1)? & !(PAGE_SIZE - 1))
+}
+
+/// Every range of the address space of a process that nothing is mapped in, as `(start, end)`.
+pub(crate) fn unused_ranges(handle: &ProcessHandle) -> std::io::Result<Vec<(usize, usize)>> {
+    let mut previous_end = MIN_ADDRESS;
+    let mut unused = Vec::new();
+    for region in crate::regions(handle)? {
+        if region.start > previous_end {
+            unused.push((previous_end, region.start));
+        }
+        previous_end = previous_end.max(region.end);
+    }
+    Ok(unused)
+}
+
+/// The permissions of the region that contains `addr`.
+pub(crate) fn region_permissions(handle: &ProcessHandle, addr: usize) -> std::io::Result<Permissions> {
+    crate::regions(handle)?
+        .find(|region| region.contains(addr))
+        .map(|region| region.permissions)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{addr:#x} is not mapped"),
+            )
+        })
+}
+
+/// A handle to the current process, for reading its own memory map.
+fn own_handle() -> ProcessHandle {
+    #[allow(clippy::cast_possible_wrap)]
+    (std::process::id() as Pid, Architecture::from_native())
+}
+
+/// Allocate memory in the current process with `mmap`.
+pub(crate) fn allocate_local(
+    size: usize,
+    permissions: Permissions,
+    near: Option<usize>,
+) -> std::io::Result<usize> {
+    let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+    let mmap = |address: usize, size: usize, flags: libc::c_int| {
+        let result = unsafe {
+            libc::mmap(address as *mut libc::c_void, size, prot(permissions), flags, -1, 0)
+        };
+        if result == libc::MAP_FAILED {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(result as usize)
+        }
+    };
+    let Some(near) = near else {
+        return mmap(0, size, flags);
+    };
+
+    let size = page_align(size).ok_or_else(|| nothing_near(near))?;
+    for candidate in near_candidates(unused_ranges(&own_handle())?, near, size, PAGE_SIZE) {
+        match mmap(candidate, size, flags | libc::MAP_FIXED_NOREPLACE) {
+            Ok(address) if address == candidate => return Ok(address),
+            Ok(address) => free_local(address, size)?,
+            Err(_) => {}
+        }
+    }
+    Err(nothing_near(near))
+}
+
+/// Free memory in the current process with `munmap`.
+pub(crate) fn free_local(address: usize, size: usize) -> std::io::Result<()> {
+    if unsafe { libc::munmap(address as *mut libc::c_void, size) } == -1 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Change the permissions of memory in the current process with `mprotect`.
+pub(crate) fn protect_local(
+    address: usize,
+    len: usize,
+    permissions: Permissions,
+) -> std::io::Result<Permissions> {
+    let previous = region_permissions(&own_handle(), address)?;
+    let start = address & !(PAGE_SIZE - 1);
+    let len = address + len - start;
+    if unsafe { libc::mprotect(start as *mut libc::c_void, len, prot(permissions)) } == -1 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(previous)
+    }
+}
@@ -1,7 +1,37 @@
 use libc::{c_void, iovec, pid_t, process_vm_readv, process_vm_writev};
+use std::io::Read;
 use std::process::Child;
 
-use super::{Architecture, CopyAddress, ProcessHandleExt, PutAddress, TryIntoProcessHandle};
+use super::{
+    Architecture, CopyAddress, MemoryError, MemoryRegion, Permissions, ProcessHandleExt,
+    ProtectAddress, PutAddress, TryIntoProcessHandle,
+};
+
+#[cfg(target_arch = "x86_64")]
+mod allocation;
+mod attach;
+mod backend;
+mod batch;
+mod local;
+#[cfg(target_arch = "x86_64")]
+mod registers;
+#[cfg(target_arch = "x86_64")]
+mod remote;
+mod threads;
+#[cfg(target_arch = "x86_64")]
+mod watchpoint;
+
+#[cfg(target_arch = "x86_64")]
+pub(crate) use allocation::{allocate, free, protect};
+pub use attach::AttachSession;
+pub(crate) use backend::force_write;
+pub use backend::{BackendHandle, MemoryBackend};
+pub(crate) use local::{allocate_local, free_local, protect_local};
+#[cfg(target_arch = "x86_64")]
+pub use registers::Registers;
+pub use threads::{threads, ThreadInfo, ThreadState};
+#[cfg(target_arch = "x86_64")]
+pub use watchpoint::{InstructionHits, WatchKind, Watchpoint, WatchpointHit};
 
 /// On Linux a `Pid` is just a `libc::pid_t`.
 pub type Pid = pid_t;
@@ -27,14 +57,74 @@ impl ProcessHandleExt for ProcessHandle {
 impl TryIntoProcessHandle for Child {
     fn try_into_process_handle(&self) -> std::io::Result<ProcessHandle> {
         #[allow(clippy::cast_possible_wrap)]
-        Ok((self.id() as Pid, Architecture::from_native()))
+        let pid = self.id() as Pid;
+        Ok((pid, detect_architecture(pid)))
     }
 }
 
 impl TryIntoProcessHandle for Pid {
     fn try_into_process_handle(&self) -> std::io::Result<ProcessHandle> {
-        Ok((*self, Architecture::from_native()))
+        Ok((*self, detect_architecture(*self)))
+    }
+}
+
+/// Work out the architecture of a process from the ELF header of its executable, or from the PE
+/// header of the Windows executable it runs if it is Wine, falling back to the host's.
+fn detect_architecture(pid: Pid) -> Architecture {
+    let exe = format!("/proc/{pid}/exe");
+    let mut header = [0_u8; 64];
+    let Some(arch) = std::fs::File::open(&exe)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .ok()
+        .and_then(|()| Architecture::from_elf_header(&header))
+    else {
+        return Architecture::from_native();
+    };
+    let is_wine = std::fs::read_link(&exe).is_ok_and(|path| {
+        path.file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("wine"))
+    });
+    if is_wine {
+        if let Some(arch) = wine_image_architecture(pid) {
+            return arch;
+        }
+    }
+    arch
+}
+
+/// Read the PE header of the first `.exe` mapped into a Wine process.
+fn wine_image_architecture(pid: Pid) -> Option<Architecture> {
+    let handle = (pid, Architecture::from_native());
+    let region = regions(&handle).ok()?.into_iter().find(|region| {
+        region.offset == 0
+            && region
+                .path
+                .as_ref()
+                .and_then(|path| path.extension())
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("exe"))
+    })?;
+    let mut image = vec![0_u8; region.size().min(0x1000)];
+    handle.copy_address(region.start, &mut image).ok()?;
+    Architecture::from_pe_header(&image)
+}
+
+/// The last OS error from accessing memory at `addr` in a process, as a [`MemoryError`] where
+/// there is a matching variant.
+///
+/// [`MemoryError`]: enum.MemoryError.html
+fn os_error(pid: Pid, addr: usize) -> std::io::Error {
+    MemoryError::from_os_error(std::io::Error::last_os_error(), pid, addr).into()
+}
+
+/// The error for a transfer that stopped part of the way through `len` bytes at `addr`.
+pub(crate) fn short_transfer(addr: usize, transferred: usize, len: usize, write: bool) -> std::io::Error {
+    MemoryError::PartialTransfer {
+        address: addr,
+        done: transferred,
+        len,
+        write,
     }
+    .into()
 }
 
 impl CopyAddress for ProcessHandle {
@@ -54,12 +144,16 @@ impl CopyAddress for ProcessHandle {
             iov_len: buf.len(),
         };
         let result = unsafe { process_vm_readv(self.0, &local_iov, 1, &remote_iov, 1, 0) };
-        if result == -1 {
-            Err(std::io::Error::last_os_error())
-        } else {
-            Ok(())
+        match usize::try_from(result) {
+            Err(_) => Err(os_error(self.0, addr)),
+            Ok(read) if read < buf.len() => Err(short_transfer(addr, read, buf.len(), false)),
+            Ok(_) => Ok(()),
         }
     }
+
+    fn copy_addresses(&self, entries: &mut [(usize, &mut [u8])]) -> Vec<std::io::Result<()>> {
+        batch::copy_addresses(self.0, entries)
+    }
 }
 
 impl PutAddress for ProcessHandle {
@@ -73,10 +167,72 @@ impl PutAddress for ProcessHandle {
             iov_len: buf.len(),
         };
         let result = unsafe { process_vm_writev(self.0, &local_iov, 1, &remote_iov, 1, 0) };
-        if result == -1 {
-            Err(std::io::Error::last_os_error())
-        } else {
-            Ok(())
+        match usize::try_from(result) {
+            Err(_) => Err(os_error(self.0, addr)),
+            Ok(written) if written < buf.len() => {
+                Err(short_transfer(addr, written, buf.len(), true))
+            }
+            Ok(_) => Ok(()),
         }
     }
-}
\ No newline at end of file
+
+    fn put_addresses(&self, entries: &[(usize, &[u8])]) -> Vec<std::io::Result<()>> {
+        batch::put_addresses(self.0, entries)
+    }
+}
+
+/// Use `mprotect` inside of another process, through a temporary `ptrace` attachment, to change
+/// the permissions of its memory on Linux.
+impl ProtectAddress for ProcessHandle {
+    fn protect_address(
+        &self,
+        addr: usize,
+        len: usize,
+        permissions: Permissions,
+    ) -> std::io::Result<Permissions> {
+        let previous = local::region_permissions(self, addr)?;
+        protect(self, addr, len, permissions)?;
+        Ok(previous)
+    }
+}
+
+/// Read the memory map of a process from `/proc/<pid>/maps`.
+pub(crate) fn regions(handle: &ProcessHandle) -> std::io::Result<Vec<MemoryRegion>> {
+    let maps = std::fs::read_to_string(format!("/proc/{}/maps", handle.0))?;
+    maps.lines().map(str::parse).collect()
+}
+
+/// Remote allocation needs to run code in the target, which has only been implemented for
+/// x86-64 Linux.
+#[cfg(not(target_arch = "x86_64"))]
+fn unsupported_allocation() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Remote allocation is only supported on x86-64 Linux",
+    )
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) fn allocate(
+    _handle: &ProcessHandle,
+    _size: usize,
+    _permissions: Permissions,
+    _near: Option<usize>,
+) -> std::io::Result<usize> {
+    Err(unsupported_allocation())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) fn free(_handle: &ProcessHandle, _address: usize, _size: usize) -> std::io::Result<()> {
+    Err(unsupported_allocation())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) fn protect(
+    _handle: &ProcessHandle,
+    _address: usize,
+    _len: usize,
+    _permissions: Permissions,
+) -> std::io::Result<()> {
+    Err(unsupported_allocation())
+}
@@ -0,0 +1,119 @@
+use super::attach::{ptrace, AttachSession};
+use super::Pid;
+
+/// The general purpose registers of an x86-64 thread, laid out the same as the kernel's
+/// `struct user_regs_struct` so that it can be passed straight to `PTRACE_GETREGS`.
+#[repr(C)]
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    /// The syscall number, if the thread stopped inside of a syscall.
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub eflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    /// The base address of the `fs` segment, which holds thread-local storage on Linux.
+    pub fs_base: u64,
+    /// The base address of the `gs` segment.
+    pub gs_base: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+/// Read the registers of a stopped thread.
+pub(crate) fn get_registers(tid: Pid) -> std::io::Result<Registers> {
+    let mut registers = Registers::default();
+    ptrace(
+        libc::PTRACE_GETREGS as _,
+        tid,
+        0,
+        std::ptr::addr_of_mut!(registers) as usize,
+    )?;
+    Ok(registers)
+}
+
+/// Overwrite the registers of a stopped thread.
+pub(crate) fn set_registers(tid: Pid, registers: &Registers) -> std::io::Result<()> {
+    ptrace(
+        libc::PTRACE_SETREGS as _,
+        tid,
+        0,
+        std::ptr::from_ref(registers) as usize,
+    )?;
+    Ok(())
+}
+
+impl AttachSession {
+    /// Read the general purpose registers of an attached thread, including `fs_base` and
+    /// `gs_base`. The process is stopped for the duration of the read if it is running.
+    ///
+    /// ```rust,no_run
+    /// # use titanium_desktop_memory::{get_handle, AttachSession};
+    /// let session = AttachSession::attach(get_handle("game").unwrap()).unwrap();
+    /// for tid in session.threads().unwrap() {
+    ///     let registers = session.registers(tid).unwrap();
+    ///     println!("{tid}: rip {:#x} fs_base {:#x}", registers.rip, registers.fs_base);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if the thread is not attached, or if it exits before it can be read.
+    pub fn registers(&self, tid: Pid) -> std::io::Result<Registers> {
+        self.with_tracer(move |tracer| tracer.while_stopped(Some(tid), |_, tid| get_registers(tid)))
+    }
+
+    /// Overwrite the general purpose registers of an attached thread, which it continues with
+    /// once the process is resumed. The process is stopped for the duration of the write if it is
+    /// running.
+    ///
+    /// # Errors
+    /// Returns an error if the thread is not attached, if it exits before it can be written, or
+    /// if the kernel rejects the registers, such as a non-canonical `fs_base`.
+    pub fn set_registers(&self, tid: Pid, registers: &Registers) -> std::io::Result<()> {
+        let registers = *registers;
+        self.with_tracer(move |tracer| {
+            tracer.while_stopped(Some(tid), |_, tid| set_registers(tid, &registers))
+        })
+    }
+
+    /// The thread pointer of an attached thread, which is its `fs_base` on x86-64 Linux.
+    ///
+    /// The thread pointer points at the thread control block, and the static thread-local
+    /// storage of the executable and of the libraries loaded with it sits just below it, so a
+    /// `thread_local` variable the game reads with `mov rax, fs:[-0x40]` is at
+    /// `tls_base - 0x40` in that thread.
+    ///
+    /// ```rust,no_run
+    /// # use titanium_desktop_memory::{get_handle, AttachSession, DataMember, Memory};
+    /// let handle = get_handle("game").unwrap();
+    /// let session = AttachSession::attach(handle).unwrap();
+    /// let tls = session.tls_base(session.pid()).unwrap();
+    /// let world = DataMember::<u64>::new_offset(handle, vec![tls - 0x40, 0x10]);
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if the registers of the thread cannot be read.
+    pub fn tls_base(&self, tid: Pid) -> std::io::Result<usize> {
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(self.registers(tid)?.fs_base as usize)
+    }
+}
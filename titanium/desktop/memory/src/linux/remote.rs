@@ -0,0 +1,245 @@
+use std::time::{Duration, Instant};
+
+use super::attach::{ptrace, AttachSession, Tracer};
+use super::registers::{get_registers, set_registers};
+use super::Pid;
+
+/// The encoding of the x86-64 `syscall` instruction.
+const SYSCALL: [u8; 2] = [0x0f, 0x05];
+/// How many times a single step is retried when it is interrupted by a signal.
+const MAX_STEPS: usize = 16;
+/// How long a function called in the target can run before it is abandoned.
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+/// Space left below the stack pointer when calling a function, which covers the red zone.
+const STACK_RESERVE: u64 = 256;
+/// The registers the first integer arguments are passed in, in the System V calling convention.
+const MAX_ARGS: usize = 6;
+
+impl Tracer {
+    /// Once a call has been abandoned, nothing more is run in the process, as the abandoned
+    /// thread may hold locks that any other code could block on.
+    fn ensure_not_abandoned(&self) -> std::io::Result<()> {
+        if self.stop_on_detach {
+            Err(std::io::Error::other(
+                "A remote call was abandoned in the process, so no more code can be run in it",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Make a system call inside the target, returning its result.
+    ///
+    /// The process is stopped while the call runs. A `syscall` instruction is written over the
+    /// current instruction of one of its threads, which is single-stepped with the arguments in
+    /// its registers before the original bytes and registers are put back. The main thread is
+    /// preferred, as it is the least likely to exit.
+    pub(crate) fn syscall(&mut self, number: libc::c_long, args: [usize; 6]) -> std::io::Result<usize> {
+        self.ensure_not_abandoned()?;
+        self.while_stopped(None, |tracer, tid| tracer.syscall_on(tid, number, args))
+    }
+
+    fn syscall_on(&mut self, tid: Pid, number: libc::c_long, args: [usize; 6]) -> std::io::Result<usize> {
+        let saved = get_registers(tid)?;
+        #[allow(clippy::cast_possible_truncation)]
+        let rip = saved.rip as usize;
+        #[allow(clippy::cast_sign_loss)]
+        let original = ptrace(libc::PTRACE_PEEKTEXT as _, tid, rip, 0)? as usize;
+        let mut patched = original.to_ne_bytes();
+        patched[..SYSCALL.len()].copy_from_slice(&SYSCALL);
+        ptrace(
+            libc::PTRACE_POKETEXT as _,
+            tid,
+            rip,
+            usize::from_ne_bytes(patched),
+        )?;
+
+        let mut registers = saved;
+        #[allow(clippy::cast_sign_loss)]
+        {
+            registers.rax = number as u64;
+        }
+        registers.rdi = args[0] as u64;
+        registers.rsi = args[1] as u64;
+        registers.rdx = args[2] as u64;
+        registers.r10 = args[3] as u64;
+        registers.r8 = args[4] as u64;
+        registers.r9 = args[5] as u64;
+        // Stop the kernel from restarting a system call the thread was interrupted in, which
+        // would move the instruction pointer back before the call runs.
+        registers.orig_rax = u64::MAX;
+
+        let result = set_registers(tid, &registers)
+            .and_then(|()| self.step(tid, rip + SYSCALL.len()))
+            .and_then(|()| get_registers(tid));
+        // Put everything back even if the call failed, as long as the thread still exists.
+        let restored = ptrace(libc::PTRACE_POKETEXT as _, tid, rip, original)
+            .and_then(|_| set_registers(tid, &saved));
+        let registers = result?;
+        restored?;
+
+        #[allow(clippy::cast_possible_wrap)]
+        let value = registers.rax as i64;
+        if (-4095..0).contains(&value) {
+            #[allow(clippy::cast_possible_truncation)]
+            Err(std::io::Error::from_raw_os_error(-value as i32))
+        } else {
+            #[allow(clippy::cast_possible_truncation)]
+            Ok(registers.rax as usize)
+        }
+    }
+
+    /// Single-step a stopped thread until it reaches `end`, holding any signals that arrive in
+    /// the meantime.
+    fn step(&mut self, tid: Pid, end: usize) -> std::io::Result<()> {
+        for _ in 0..MAX_STEPS {
+            ptrace(libc::PTRACE_SINGLESTEP as _, tid, 0, 0)?;
+            let mut status = 0;
+            if unsafe { libc::waitpid(tid, &mut status, libc::__WALL) } == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            if !libc::WIFSTOPPED(status) {
+                self.threads.remove(&tid);
+                return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+            }
+            let signal = libc::WSTOPSIG(status);
+            if status >> 16 == 0 && signal != libc::SIGTRAP {
+                self.on_signal(tid, signal);
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            if get_registers(tid)?.rip as usize == end {
+                return Ok(());
+            }
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            "The injected instruction did not finish",
+        ))
+    }
+    /// Call a function inside the target with up to six integer arguments, returning the value
+    /// it returns.
+    ///
+    /// The function runs on one thread while the others stay stopped. Its return address is set
+    /// to `0`, so the thread faults as soon as it returns, which is caught before the target
+    /// sees it and the thread's registers are put back.
+    ///
+    /// A function that does not return within [`CALL_TIMEOUT`] may be holding a lock, such as
+    /// the loader lock in `dlopen`, so its thread is not put back, as that would leave the lock
+    /// held forever. The process is kept stopped instead, and stays stopped with `SIGSTOP` after
+    /// detaching. Every later call or system call through the same tracer fails.
+    pub(crate) fn call(&mut self, function: usize, args: &[usize]) -> std::io::Result<usize> {
+        if args.len() > MAX_ARGS {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Only six arguments can be passed to a remote function",
+            ));
+        }
+        self.ensure_not_abandoned()?;
+        self.while_stopped(None, |tracer, tid| {
+            let saved = get_registers(tid)?;
+            let result = tracer.call_on(tid, &saved, function, args);
+            if let Err(error) = &result {
+                if error.kind() == std::io::ErrorKind::TimedOut {
+                    tracer.stop_on_detach = true;
+                    return result;
+                }
+            }
+            // Put the registers back even if the call failed, as long as the thread still exists.
+            let restored = if tracer.threads.contains_key(&tid) {
+                set_registers(tid, &saved)
+            } else {
+                Ok(())
+            };
+            let value = result?;
+            restored?;
+            Ok(value)
+        })
+    }
+
+    fn call_on(
+        &mut self,
+        tid: Pid,
+        saved: &crate::Registers,
+        function: usize,
+        args: &[usize],
+    ) -> std::io::Result<usize> {
+        let mut registers = *saved;
+        // The stack has to be 16-byte aligned before the return address is pushed.
+        let stack = (saved.rsp - STACK_RESERVE) & !0xf;
+        registers.rsp = stack - 8;
+        #[allow(clippy::cast_possible_truncation)]
+        ptrace(libc::PTRACE_POKEDATA as _, tid, registers.rsp as usize, 0)?;
+        let mut values = [0_u64; MAX_ARGS];
+        for (value, arg) in values.iter_mut().zip(args) {
+            *value = *arg as u64;
+        }
+        let [rdi, rsi, rdx, rcx, r8, r9] = values;
+        registers.rdi = rdi;
+        registers.rsi = rsi;
+        registers.rdx = rdx;
+        registers.rcx = rcx;
+        registers.r8 = r8;
+        registers.r9 = r9;
+        // The number of vector registers used by a variadic function.
+        registers.rax = 0;
+        registers.rip = function as u64;
+        registers.orig_rax = u64::MAX;
+        set_registers(tid, &registers)?;
+        ptrace(libc::PTRACE_CONT as _, tid, 0, 0)?;
+
+        let deadline = Instant::now() + CALL_TIMEOUT;
+        loop {
+            let mut status = 0;
+            match unsafe { libc::waitpid(tid, &mut status, libc::__WALL | libc::WNOHANG) } {
+                -1 => return Err(std::io::Error::last_os_error()),
+                0 if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(1));
+                    continue;
+                }
+                0 => {
+                    ptrace(libc::PTRACE_INTERRUPT as _, tid, 0, 0)?;
+                    self.wait_for_stop(tid, &mut Vec::new())?;
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "The remote function did not return",
+                    ));
+                }
+                _ => {}
+            }
+            if !libc::WIFSTOPPED(status) {
+                self.threads.remove(&tid);
+                return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+            }
+            let signal = libc::WSTOPSIG(status);
+            match status >> 16 {
+                libc::PTRACE_EVENT_CLONE => {
+                    let child = self.event_message(tid)?;
+                    self.threads.entry(child).or_default();
+                }
+                0 if signal == libc::SIGSEGV => {
+                    let registers = get_registers(tid)?;
+                    if registers.rip == 0 {
+                        #[allow(clippy::cast_possible_truncation)]
+                        return Ok(registers.rax as usize);
+                    }
+                    return Err(std::io::Error::other(format!(
+                        "The remote function crashed at {:#x}",
+                        registers.rip
+                    )));
+                }
+                0 if signal == libc::SIGTRAP && self.on_trap(tid) => {}
+                0 => self.on_signal(tid, signal),
+                _ => {}
+            }
+            ptrace(libc::PTRACE_CONT as _, tid, 0, 0)?;
+        }
+    }
+}
+
+impl AttachSession {
+    /// Call a function inside the attached process. See [`Tracer::call`].
+    pub(crate) fn call(&self, function: usize, args: &[usize]) -> std::io::Result<usize> {
+        let args = args.to_vec();
+        self.with_tracer(move |tracer| tracer.call(function, &args))
+    }
+}
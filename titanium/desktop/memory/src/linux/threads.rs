@@ -0,0 +1,172 @@
+use super::attach::{access_error, task_ids};
+use super::{Pid, ProcessHandle};
+
+/// What a thread is doing, from the state field of `/proc/<pid>/task/<tid>/stat`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ThreadState {
+    /// Running or ready to run (`R`).
+    Running,
+    /// Waiting for something, such as a lock or a timer (`S`).
+    Sleeping,
+    /// Waiting for I/O, which cannot be interrupted (`D`).
+    DiskSleep,
+    /// Stopped by a signal such as `SIGSTOP` (`T`).
+    Stopped,
+    /// Stopped by a debugger, such as an [`AttachSession`] (`t`).
+    ///
+    /// [`AttachSession`]: struct.AttachSession.html
+    TracingStop,
+    /// Exited, but not reaped yet (`Z`).
+    Zombie,
+    /// Exiting (`X`).
+    Dead,
+    /// A kernel thread with nothing to do (`I`).
+    Idle,
+    /// Any other state, by its letter.
+    Other(char),
+}
+
+impl From<char> for ThreadState {
+    fn from(state: char) -> Self {
+        match state {
+            'R' => Self::Running,
+            'S' => Self::Sleeping,
+            'D' => Self::DiskSleep,
+            'T' => Self::Stopped,
+            't' => Self::TracingStop,
+            'Z' => Self::Zombie,
+            'X' | 'x' => Self::Dead,
+            'I' => Self::Idle,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// A thread of a process.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ThreadInfo {
+    /// The id of the thread. The main thread has the same id as the process.
+    pub tid: Pid,
+    /// The name of the thread, as set with `pthread_setname_np`. Only the first 15 bytes are
+    /// kept.
+    pub name: String,
+    /// What the thread was doing when it was listed.
+    pub state: ThreadState,
+}
+
+/// List the threads of a process from `/proc/<pid>/task`, in order of their ids.
+///
+/// Threads that exit while they are being listed are left out. Their registers, including the
+/// `fs_base` that thread-local storage is found through, can be read with
+/// [`AttachSession::registers`] on x86-64.
+///
+/// # Errors
+/// Returns an error if the threads of the process cannot be listed, which is a
+/// [`MemoryError::ProcessGone`] once it has exited.
+///
+/// [`AttachSession::registers`]: struct.AttachSession.html#method.registers
+/// [`MemoryError::ProcessGone`]: enum.MemoryError.html#variant.ProcessGone
+pub fn threads(handle: &ProcessHandle) -> std::io::Result<Vec<ThreadInfo>> {
+    let pid = handle.0;
+    Ok(task_ids(pid)
+        .map_err(|error| access_error(error, pid))?
+        .into_iter()
+        .filter_map(|tid| thread(pid, tid))
+        .collect())
+}
+
+fn thread(pid: Pid, tid: Pid) -> Option<ThreadInfo> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/task/{tid}/stat")).ok()?;
+    // The name is in brackets and can contain spaces and brackets itself, so it runs up to the
+    // last closing bracket, and the state follows it.
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let name = stat.get(open + 1..close)?.to_owned();
+    let state = stat.get(close + 1..)?.trim_start().chars().next()?;
+    Some(ThreadInfo {
+        tid,
+        name,
+        state: state.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryError, TryIntoProcessHandle};
+
+    fn own_handle() -> ProcessHandle {
+        (std::process::id() as Pid)
+            .try_into_process_handle()
+            .unwrap()
+    }
+
+    fn gettid() -> Pid {
+        unsafe { libc::gettid() }
+    }
+
+    #[test]
+    fn lists_own_threads_with_names_and_states() {
+        let (tid_sender, tid_receiver) = std::sync::mpsc::channel();
+        let (stop_sender, stop_receiver) = std::sync::mpsc::channel::<()>();
+        let sleeper = std::thread::Builder::new()
+            .name(String::from("odd) (name"))
+            .spawn(move || {
+                tid_sender.send(gettid()).unwrap();
+                let _ = stop_receiver.recv();
+            })
+            .unwrap();
+        let sleeper_tid = tid_receiver.recv().unwrap();
+
+        // The thread may not have started waiting yet.
+        let listed = (0..1000)
+            .find_map(|_| {
+                let threads = threads(&own_handle()).unwrap();
+                let sleeper = threads.iter().find(|thread| thread.tid == sleeper_tid)?;
+                (sleeper.state == ThreadState::Sleeping).then_some(threads)
+            })
+            .unwrap();
+        stop_sender.send(()).unwrap();
+        sleeper.join().unwrap();
+
+        assert!(listed.windows(2).all(|pair| pair[0].tid < pair[1].tid));
+        assert!(listed
+            .iter()
+            .any(|thread| thread.tid == std::process::id() as Pid));
+        let sleeper = listed
+            .iter()
+            .find(|thread| thread.tid == sleeper_tid)
+            .unwrap();
+        assert_eq!(sleeper.name, "odd) (name");
+        let current = listed.iter().find(|thread| thread.tid == gettid()).unwrap();
+        assert_eq!(current.state, ThreadState::Running);
+    }
+
+    #[test]
+    fn reports_exited_processes_as_gone() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let handle = (child.id() as Pid).try_into_process_handle().unwrap();
+        child.wait().unwrap();
+        let error = threads(&handle).map_err(MemoryError::from).unwrap_err();
+        assert!(matches!(error, MemoryError::ProcessGone { .. }));
+    }
+
+    #[test]
+    fn parses_states() {
+        let states: Vec<ThreadState> = "RSDTtZXI?".chars().map(ThreadState::from).collect();
+        assert_eq!(
+            states,
+            [
+                ThreadState::Running,
+                ThreadState::Sleeping,
+                ThreadState::DiskSleep,
+                ThreadState::Stopped,
+                ThreadState::TracingStop,
+                ThreadState::Zombie,
+                ThreadState::Dead,
+                ThreadState::Idle,
+                ThreadState::Other('?'),
+            ]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! Utility functions for Linux
+
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::elf::DynamicObject;
+use crate::{modules, AttachSession, CopyAddress, Permissions, PutAddress, TryIntoProcessHandle};
+
+/// Resolve every symbol of the shared object before `dlopen` returns.
+const RTLD_NOW: usize = 0x2;
+/// The flag `__libc_dlopen_mode` needs to behave like a call to `dlopen`.
+const RTLD_DLOPEN: usize = 0x8000_0000;
+/// The longest message read back from `dlerror`.
+const MAX_ERROR_LEN: usize = 512;
+
+/// The error type for [`inject_shared_object`] and [`eject`]
+#[derive(Debug, thiserror::Error)]
+pub enum InjectSharedObjectError {
+    /// Failed to canonicalize shared object path
+    #[error("Failed to canonicalize shared object path")]
+    SharedObjectPathCanonicalizationFailed(std::io::Error),
+    /// The shared object path contains a nul byte
+    #[error("Shared object path contains a nul byte")]
+    SharedObjectPathContainsNul,
+    /// Failed to attach to the process with `ptrace`
+    #[error("Attaching to process failed")]
+    AttachFailed(std::io::Error),
+    /// Failed to read the modules loaded by the process
+    #[error("Reading process modules failed")]
+    ReadingModulesFailed(std::io::Error),
+    /// Neither `dlopen` nor `__libc_dlopen_mode` was found in the process
+    #[error("dlopen not found")]
+    DlopenNotFound,
+    /// `dlclose` was not found in the process
+    #[error("dlclose not found")]
+    DlcloseNotFound,
+    /// Failed to allocate memory in process
+    #[error("Shared object path buffer allocation failed")]
+    SharedObjectPathBufferAllocationFailed(std::io::Error),
+    /// Failed to write shared object path to process memory
+    #[error("Writing process memory failed")]
+    WritingProcessMemoryFailed(std::io::Error),
+    /// Failed to call a function inside of the process
+    #[error("Calling function in process failed")]
+    RemoteCallFailed(std::io::Error),
+    /// `dlopen` returned `NULL`
+    #[error("dlopen failed: {0}")]
+    DlopenFailed(String),
+    /// `dlclose` returned an error
+    #[error("dlclose failed: {0}")]
+    DlcloseFailed(String),
+}
+
+/// Find the first of `names` exported by the C library or the dynamic loader of the process.
+fn find_loader_symbol<'a>(
+    session: &AttachSession,
+    names: &[&'a str],
+) -> Result<Option<(usize, &'a str)>, InjectSharedObjectError> {
+    let handle = session.handle();
+    let loaders: Vec<_> = modules(&handle)
+        .map_err(InjectSharedObjectError::ReadingModulesFailed)?
+        .into_iter()
+        .filter(|module| {
+            ["libc.so", "libc-", "libdl", "ld-musl"]
+                .iter()
+                .any(|prefix| module.name.starts_with(prefix))
+        })
+        .filter_map(|module| {
+            DynamicObject::parse(&handle, module.base)
+                .and_then(|object| object.symbols())
+                .ok()
+        })
+        .collect();
+    for name in names {
+        for symbols in &loaders {
+            if let Some(symbol) = symbols
+                .iter()
+                .find(|symbol| symbol.address != 0 && symbol.name == *name)
+            {
+                return Ok(Some((symbol.address, *name)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Get the message of the last `dlopen` or `dlclose` error in the process.
+fn last_error(session: &AttachSession) -> String {
+    let message = find_loader_symbol(session, &["dlerror"])
+        .ok()
+        .flatten()
+        .and_then(|(dlerror, _)| session.call(dlerror, &[]).ok())
+        .filter(|message| *message != 0)
+        .and_then(|message| {
+            let mut buffer = vec![0_u8; MAX_ERROR_LEN];
+            session.copy_address(message, &mut buffer).ok()?;
+            let end = buffer.iter().position(|&byte| byte == 0).unwrap_or(buffer.len());
+            Some(String::from_utf8_lossy(&buffer[..end]).into_owned())
+        });
+    message.unwrap_or_else(|| String::from("unknown error"))
+}
+
+/// Injects a shared object into a process, returning the handle returned by `dlopen`
+/// `process_id` The process id
+/// `path` The path of the shared object that should be injected
+///
+/// The process is attached to with `ptrace` and one of its threads is made to call `dlopen`, or
+/// `__libc_dlopen_mode` if the C library does not export `dlopen`. The call can deadlock if
+/// another thread of the process was stopped while holding the loader lock, in which case it is
+/// abandoned after ten seconds and [`InjectSharedObjectError::RemoteCallFailed`] is returned with
+/// an error of kind `TimedOut`.
+///
+/// An abandoned call may itself hold the loader lock, so the thread's registers are not put back
+/// and the process is left stopped with `SIGSTOP` instead. Sending it `SIGCONT` lets `dlopen`
+/// finish, but the thread then returns to a null address and crashes the process, so it usually
+/// has to be killed. The page holding the path is leaked rather than freed, as `dlopen` may still
+/// be reading it.
+///
+/// [`InjectSharedObjectError::RemoteCallFailed`]: enum.InjectSharedObjectError.html#variant.RemoteCallFailed
+///
+/// # Safety
+/// The constructors of the shared object run inside of the process, and the thread that calls
+/// `dlopen` is interrupted wherever it happened to be.
+pub unsafe fn inject_shared_object(
+    process_id: crate::Pid,
+    path: &Path,
+) -> Result<usize, InjectSharedObjectError> {
+    let canonicalized_path = path
+        .canonicalize()
+        .map_err(InjectSharedObjectError::SharedObjectPathCanonicalizationFailed)?;
+    let path_cstr = CString::new(canonicalized_path.as_os_str().as_bytes())
+        .map_err(|_| InjectSharedObjectError::SharedObjectPathContainsNul)?;
+    let path_bytes = path_cstr.as_bytes_with_nul();
+
+    let handle = process_id
+        .try_into_process_handle()
+        .map_err(InjectSharedObjectError::AttachFailed)?;
+    let session = AttachSession::attach(handle).map_err(InjectSharedObjectError::AttachFailed)?;
+    let (dlopen, name) = find_loader_symbol(&session, &["dlopen", "__libc_dlopen_mode"])?
+        .ok_or(InjectSharedObjectError::DlopenNotFound)?;
+
+    let permissions = Permissions {
+        read: true,
+        write: true,
+        ..Permissions::default()
+    };
+    let path_buffer = session
+        .allocate(path_bytes.len(), permissions, None)
+        .map_err(InjectSharedObjectError::SharedObjectPathBufferAllocationFailed)?;
+    let result = session
+        .put_address(path_buffer, path_bytes)
+        .map_err(InjectSharedObjectError::WritingProcessMemoryFailed)
+        .and_then(|()| {
+            let mode = if name == "dlopen" {
+                RTLD_NOW
+            } else {
+                RTLD_NOW | RTLD_DLOPEN
+            };
+            session
+                .call(dlopen, &[path_buffer, mode])
+                .map_err(InjectSharedObjectError::RemoteCallFailed)
+        });
+    let abandoned = matches!(
+        &result,
+        Err(InjectSharedObjectError::RemoteCallFailed(error))
+            if error.kind() == std::io::ErrorKind::TimedOut
+    );
+    if !abandoned {
+        let _ = session.free(path_buffer, path_bytes.len());
+    }
+
+    match result? {
+        0 => Err(InjectSharedObjectError::DlopenFailed(last_error(&session))),
+        handle => Ok(handle),
+    }
+}
+
+/// Ejects a shared object from a process by calling `dlclose` on it
+/// `process_id` The process id
+/// `handle` The handle returned by [`inject_shared_object`]
+///
+/// The shared object is only unloaded once every handle to it has been closed.
+///
+/// # Safety
+/// `handle` must be a handle returned by `dlopen` in the process that has not been closed yet,
+/// and nothing in the process may still use code or data from the shared object.
+pub unsafe fn eject(process_id: crate::Pid, handle: usize) -> Result<(), InjectSharedObjectError> {
+    let process = process_id
+        .try_into_process_handle()
+        .map_err(InjectSharedObjectError::AttachFailed)?;
+    let session = AttachSession::attach(process).map_err(InjectSharedObjectError::AttachFailed)?;
+    let (dlclose, _) = find_loader_symbol(&session, &["dlclose", "__libc_dlclose"])?
+        .ok_or(InjectSharedObjectError::DlcloseNotFound)?;
+    let result = session
+        .call(dlclose, &[handle])
+        .map_err(InjectSharedObjectError::RemoteCallFailed)?;
+    // `dlclose` returns an `int`, so only the low half of the register is meaningful.
+    if result as u32 == 0 {
+        Ok(())
+    } else {
+        Err(InjectSharedObjectError::DlcloseFailed(last_error(&session)))
+    }
+}
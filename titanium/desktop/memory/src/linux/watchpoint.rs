@@ -0,0 +1,297 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::attach::{ptrace, AttachSession, Tracer};
+use super::registers::{get_registers, Registers};
+use super::Pid;
+
+/// The number of debug address registers on x86-64.
+const SLOTS: usize = 4;
+/// The most raw hits kept for each watchpoint between calls to [`Watchpoint::take_hits`].
+const MAX_PENDING_HITS: usize = 1 << 16;
+
+/// The offset of debug register `index` in the kernel's `struct user`.
+fn debug_register(index: usize) -> usize {
+    std::mem::offset_of!(libc::user, u_debugreg) + index * std::mem::size_of::<u64>()
+}
+
+/// The kind of access that triggers a [`Watchpoint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    /// Trigger when the address is executed. The length must be `1`.
+    Execute,
+    /// Trigger when the address is written to.
+    Write,
+    /// Trigger when the address is read from or written to.
+    Access,
+}
+
+impl WatchKind {
+    /// The `R/W` bits of the kind in `DR7`.
+    fn bits(self) -> u64 {
+        match self {
+            WatchKind::Execute => 0b00,
+            WatchKind::Write => 0b01,
+            WatchKind::Access => 0b11,
+        }
+    }
+}
+
+/// A single time a [`Watchpoint`] was triggered.
+#[derive(Clone, Debug)]
+pub struct WatchpointHit {
+    /// The thread that triggered the watchpoint.
+    pub tid: Pid,
+    /// The instruction pointer when the watchpoint was reported. For write and access
+    /// watchpoints this is the instruction *after* the one that touched the address, as the
+    /// processor reports them once the instruction has finished.
+    pub rip: usize,
+    /// The registers of the thread when the watchpoint was reported.
+    pub registers: Registers,
+}
+
+/// Every hit of a [`Watchpoint`] from a single instruction.
+#[derive(Clone, Debug)]
+pub struct InstructionHits {
+    /// The instruction pointer the hits were reported at. See [`WatchpointHit::rip`].
+    pub rip: usize,
+    /// The number of times the instruction triggered the watchpoint.
+    pub count: usize,
+    /// Every thread that triggered the watchpoint at this instruction.
+    pub threads: BTreeSet<Pid>,
+    /// The most recent hit from this instruction.
+    pub last: WatchpointHit,
+}
+
+#[derive(Debug)]
+struct Slot {
+    address: usize,
+    len: usize,
+    kind: WatchKind,
+    hits: Vec<WatchpointHit>,
+    summary: BTreeMap<usize, InstructionHits>,
+}
+
+/// The hardware watchpoints of a traced process, which are applied to every thread.
+#[derive(Debug, Default)]
+pub(crate) struct Watchpoints {
+    slots: [Option<Slot>; SLOTS],
+    /// Incremented whenever the slots change, so threads know when their debug registers are out
+    /// of date.
+    pub(crate) generation: u64,
+}
+
+impl Watchpoints {
+    /// The value of `DR7` that enables every used slot.
+    fn control(&self) -> u64 {
+        let mut control = 0;
+        for (index, slot) in self.slots.iter().enumerate() {
+            if let Some(slot) = slot {
+                let len = match slot.len {
+                    1 => 0b00,
+                    2 => 0b01,
+                    8 => 0b10,
+                    _ => 0b11,
+                };
+                control |= 1 << (index * 2);
+                control |= (slot.kind.bits() | len << 2) << (16 + index * 4);
+            }
+        }
+        control
+    }
+
+    /// Write the debug registers of a stopped thread.
+    pub(crate) fn apply(&self, tid: Pid) -> std::io::Result<()> {
+        // Disable everything first, as the processor rejects a control value that enables a
+        // slot whose address is not suitably aligned.
+        ptrace(libc::PTRACE_POKEUSER as _, tid, debug_register(7), 0)?;
+        for (index, slot) in self.slots.iter().enumerate() {
+            let address = slot.as_ref().map_or(0, |slot| slot.address);
+            ptrace(libc::PTRACE_POKEUSER as _, tid, debug_register(index), address)?;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        ptrace(
+            libc::PTRACE_POKEUSER as _,
+            tid,
+            debug_register(7),
+            self.control() as usize,
+        )?;
+        Ok(())
+    }
+
+    /// Check whether a `SIGTRAP` was caused by a watchpoint, recording the hit if so. Returns
+    /// `true` if the trap should be hidden from the target.
+    pub(crate) fn on_trap(&mut self, tid: Pid) -> std::io::Result<bool> {
+        #[allow(clippy::cast_sign_loss)]
+        let status = ptrace(libc::PTRACE_PEEKUSER as _, tid, debug_register(6), 0)? as u64;
+        let triggered: Vec<usize> = (0..SLOTS)
+            .filter(|index| status & (1 << index) != 0 && self.slots[*index].is_some())
+            .collect();
+        if triggered.is_empty() {
+            return Ok(false);
+        }
+        ptrace(libc::PTRACE_POKEUSER as _, tid, debug_register(6), 0)?;
+        let registers = get_registers(tid)?;
+        #[allow(clippy::cast_possible_truncation)]
+        let hit = WatchpointHit {
+            tid,
+            rip: registers.rip as usize,
+            registers,
+        };
+        for index in triggered {
+            if let Some(slot) = &mut self.slots[index] {
+                let summary = slot
+                    .summary
+                    .entry(hit.rip)
+                    .or_insert_with(|| InstructionHits {
+                        rip: hit.rip,
+                        count: 0,
+                        threads: BTreeSet::new(),
+                        last: hit.clone(),
+                    });
+                summary.count += 1;
+                summary.threads.insert(tid);
+                summary.last = hit.clone();
+                if slot.hits.len() < MAX_PENDING_HITS {
+                    slot.hits.push(hit.clone());
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Tracer {
+    /// Change the watchpoints, stopping the process while every thread is updated.
+    fn update_watchpoints<R>(
+        &mut self,
+        f: impl FnOnce(&mut Watchpoints) -> std::io::Result<R>,
+    ) -> std::io::Result<R> {
+        self.while_stopped(None, |tracer, _| {
+            let result = f(&mut tracer.watchpoints);
+            if result.is_ok() {
+                tracer.watchpoints.generation += 1;
+                let tids: Vec<Pid> = tracer.threads.keys().copied().collect();
+                for tid in tids {
+                    tracer.sync_thread(tid);
+                }
+            }
+            result
+        })
+    }
+}
+
+/// A hardware watchpoint on every thread of an attached process, created with
+/// [`AttachSession::watch`]. The watchpoint is removed when this is dropped.
+///
+/// Watchpoints use the x86-64 debug registers, so at most four can exist at once, and each one
+/// covers 1, 2, 4 or 8 bytes at an address aligned to its length.
+#[derive(Debug)]
+pub struct Watchpoint<'a> {
+    session: &'a AttachSession,
+    slot: usize,
+    address: usize,
+}
+
+impl AttachSession {
+    /// Watch `len` bytes at `address`, finding out what reads, writes or executes it.
+    ///
+    /// ```rust,no_run
+    /// # use titanium_desktop_memory::{get_handle, AttachSession, WatchKind};
+    /// let session = AttachSession::attach(get_handle("game").unwrap()).unwrap();
+    /// let watchpoint = session.watch(0x1234, 4, WatchKind::Write).unwrap();
+    /// std::thread::sleep(std::time::Duration::from_secs(5));
+    /// for hits in watchpoint.summary().unwrap() {
+    ///     println!("{:#x} wrote {} times", hits.rip, hits.count);
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Returns a `std::io::Error` with a `std::io::ErrorKind` of `InvalidInput` if the length or
+    /// alignment is not supported, one of `OutOfMemory` if all four debug registers are in use,
+    /// or an error if the debug registers of a thread cannot be written.
+    pub fn watch(&self, address: usize, len: usize, kind: WatchKind) -> std::io::Result<Watchpoint<'_>> {
+        let len = if kind == WatchKind::Execute { 1 } else { len };
+        if !matches!(len, 1 | 2 | 4 | 8) || address & (len - 1) != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Watchpoints must cover 1, 2, 4 or 8 bytes at an address aligned to their length",
+            ));
+        }
+        let slot = self.with_tracer(move |tracer| {
+            tracer.update_watchpoints(|watchpoints| {
+                let slot = watchpoints
+                    .slots
+                    .iter()
+                    .position(Option::is_none)
+                    .ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::OutOfMemory,
+                            "All four debug registers are in use",
+                        )
+                    })?;
+                watchpoints.slots[slot] = Some(Slot {
+                    address,
+                    len,
+                    kind,
+                    hits: Vec::new(),
+                    summary: BTreeMap::new(),
+                });
+                Ok(slot)
+            })
+        })?;
+        Ok(Watchpoint {
+            session: self,
+            slot,
+            address,
+        })
+    }
+}
+
+impl Watchpoint<'_> {
+    /// The address being watched.
+    #[must_use]
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Take every hit since the last call, in the order they happened.
+    ///
+    /// # Errors
+    /// Returns an error if the tracer thread has exited.
+    pub fn take_hits(&self) -> std::io::Result<Vec<WatchpointHit>> {
+        let slot = self.slot;
+        self.session.with_tracer(move |tracer| {
+            Ok(tracer.watchpoints.slots[slot]
+                .as_mut()
+                .map(|slot| std::mem::take(&mut slot.hits))
+                .unwrap_or_default())
+        })
+    }
+
+    /// Every hit since the watchpoint was created, grouped by instruction address and sorted by
+    /// address.
+    ///
+    /// # Errors
+    /// Returns an error if the tracer thread has exited.
+    pub fn summary(&self) -> std::io::Result<Vec<InstructionHits>> {
+        let slot = self.slot;
+        self.session.with_tracer(move |tracer| {
+            Ok(tracer.watchpoints.slots[slot]
+                .as_ref()
+                .map(|slot| slot.summary.values().cloned().collect())
+                .unwrap_or_default())
+        })
+    }
+}
+
+impl Drop for Watchpoint<'_> {
+    fn drop(&mut self) {
+        let slot = self.slot;
+        let _ = self.session.with_tracer(move |tracer| {
+            tracer.update_watchpoints(|watchpoints| {
+                watchpoints.slots[slot] = None;
+                Ok(())
+            })
+        });
+    }
+}
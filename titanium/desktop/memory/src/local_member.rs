@@ -1,4 +1,4 @@
-use crate::Memory;
+use crate::{Memory, MemoryError};
 
 /// This struct provides functions for modifying the memory of a program from within the address
 /// space of that program. This may be helpful for debug functions, or for an injected DLL.
@@ -75,13 +75,10 @@ impl<T: Sized + Copy> Memory<T> for LocalMember<T> {
 
     fn get_offset(&self) -> std::io::Result<usize> {
         let mut offset = 0_usize;
-        for i in 0..self.offsets.len() - 1 {
-            offset = offset.wrapping_add(self.offsets[i]);
+        for level in 0..self.offsets.len() - 1 {
+            offset = offset.wrapping_add(self.offsets[level]);
             if offset == 0 {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Would be a null dereference!",
-                ));
+                return Err(MemoryError::NullPointer { level }.into());
             }
             // We can't guarantee alignment, so we must use `read_unaligned()`
             // to ensure that its ok to read from, as `read()` requires that
@@ -89,6 +86,9 @@ impl<T: Sized + Copy> Memory<T> for LocalMember<T> {
             unsafe {
                 offset = (offset as *const usize).read_unaligned();
             }
+            if offset == 0 {
+                return Err(MemoryError::NullPointer { level }.into());
+            }
         }
         Ok(offset.wrapping_add(self.offsets[self.offsets.len() - 1]))
     }
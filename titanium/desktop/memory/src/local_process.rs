@@ -0,0 +1,114 @@
+use crate::{Architecture, CopyAddress, MemoryError, Permissions, ProtectAddress, PutAddress};
+
+/// The memory of the current process, accessed directly through pointers in the same way as a
+/// [`LocalMember`].
+///
+/// `LocalProcess` implements [`CopyAddress`], [`PutAddress`] and [`ProtectAddress`], so APIs that
+/// work on another process through a [`ProcessHandle`], such as [`Detour`], also work from a
+/// library that has been injected into the target.
+///
+/// # Safety
+///
+/// Like [`LocalMember`], these functions are technically ***not safe***. Reading or writing an
+/// address that is not mapped crashes the process instead of returning an error, and only null
+/// pointers are checked for.
+///
+/// [`LocalMember`]: struct.LocalMember.html
+/// [`CopyAddress`]: trait.CopyAddress.html
+/// [`PutAddress`]: trait.PutAddress.html
+/// [`ProtectAddress`]: trait.ProtectAddress.html
+/// [`ProcessHandle`]: type.ProcessHandle.html
+/// [`Detour`]: struct.Detour.html
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalProcess;
+
+impl LocalProcess {
+    /// Allocate memory in the current process. See [`allocate`].
+    ///
+    /// # Errors
+    /// Returns an error if the allocation fails or if nothing is free within range of `near`.
+    ///
+    /// [`allocate`]: fn.allocate.html
+    pub fn allocate(
+        &self,
+        size: usize,
+        permissions: Permissions,
+        near: Option<usize>,
+    ) -> std::io::Result<usize> {
+        crate::platform::allocate_local(size, permissions, near)
+    }
+
+    /// Free memory allocated with [`LocalProcess::allocate`].
+    ///
+    /// # Errors
+    /// Returns an error if the memory cannot be freed.
+    pub fn free(&self, address: usize, size: usize) -> std::io::Result<()> {
+        crate::platform::free_local(address, size)
+    }
+}
+
+fn null_pointer() -> std::io::Error {
+    MemoryError::NullPointer { level: 0 }.into()
+}
+
+impl CopyAddress for LocalProcess {
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn get_pointer_width(&self) -> Architecture {
+        Architecture::from_native()
+    }
+
+    fn copy_address(&self, addr: usize, buf: &mut [u8]) -> std::io::Result<()> {
+        if addr == 0 {
+            return Err(null_pointer());
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(addr as *const u8, buf.as_mut_ptr(), buf.len());
+        }
+        Ok(())
+    }
+}
+
+impl PutAddress for LocalProcess {
+    fn put_address(&self, addr: usize, buf: &[u8]) -> std::io::Result<()> {
+        if addr == 0 {
+            return Err(null_pointer());
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), addr as *mut u8, buf.len());
+        }
+        Ok(())
+    }
+}
+
+impl ProtectAddress for LocalProcess {
+    fn protect_address(
+        &self,
+        addr: usize,
+        len: usize,
+        permissions: Permissions,
+    ) -> std::io::Result<Permissions> {
+        crate::platform::protect_local(addr, len, permissions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_null_pointers() {
+        let mut buffer = [0_u8; 8];
+        let error = LocalProcess.copy_address(0, &mut buffer).unwrap_err();
+        assert!(matches!(MemoryError::from(error), MemoryError::NullPointer { level: 0 }));
+        let error = LocalProcess.put_address(0, &buffer).unwrap_err();
+        assert!(matches!(MemoryError::from(error), MemoryError::NullPointer { level: 0 }));
+
+        let null = 0_usize;
+        let chain = [std::ptr::addr_of!(null) as usize, 0x10, 0x8];
+        let error = LocalProcess.get_offset(&chain).unwrap_err();
+        assert!(matches!(MemoryError::from(error), MemoryError::NullPointer { level: 0 }));
+        let error = LocalProcess.get_offset(&[0, 0x10]).unwrap_err();
+        assert!(matches!(MemoryError::from(error), MemoryError::NullPointer { level: 0 }));
+    }
+}
@@ -3,7 +3,10 @@ use mach::kern_return::KERN_SUCCESS;
 use mach::port::{mach_port_name_t, MACH_PORT_NULL};
 use std::process::Child;
 
-use super::{Architecture, CopyAddress, ProcessHandleExt, PutAddress, TryIntoProcessHandle};
+use super::{
+    Architecture, CopyAddress, MemoryRegion, ProcessHandleExt, ProtectAddress, PutAddress,
+    TryIntoProcessHandle,
+};
 
 /// On OS X a `Pid` is just a `libc::pid_t`.
 pub type Pid = pid_t;
@@ -109,4 +112,76 @@ impl CopyAddress for ProcessHandle {
             ))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Region enumeration has not been implemented on macOS yet.
+pub(crate) fn regions(_handle: &ProcessHandle) -> std::io::Result<Vec<MemoryRegion>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Memory region enumeration is not supported on macOS",
+    ))
+}
+
+/// Remote allocation has not been implemented on macOS yet.
+pub(crate) fn allocate(
+    _handle: &ProcessHandle,
+    _size: usize,
+    _permissions: crate::Permissions,
+    _near: Option<usize>,
+) -> std::io::Result<usize> {
+    Err(unsupported_allocation())
+}
+
+/// Remote allocation has not been implemented on macOS yet.
+pub(crate) fn free(_handle: &ProcessHandle, _address: usize, _size: usize) -> std::io::Result<()> {
+    Err(unsupported_allocation())
+}
+
+/// Changing page protection has not been implemented on macOS yet.
+pub(crate) fn protect(
+    _handle: &ProcessHandle,
+    _address: usize,
+    _len: usize,
+    _permissions: crate::Permissions,
+) -> std::io::Result<()> {
+    Err(unsupported_allocation())
+}
+
+/// Changing page protection has not been implemented on macOS yet.
+impl ProtectAddress for ProcessHandle {
+    fn protect_address(
+        &self,
+        _addr: usize,
+        _len: usize,
+        _permissions: crate::Permissions,
+    ) -> std::io::Result<crate::Permissions> {
+        Err(unsupported_allocation())
+    }
+}
+
+pub(crate) fn allocate_local(
+    _size: usize,
+    _permissions: crate::Permissions,
+    _near: Option<usize>,
+) -> std::io::Result<usize> {
+    Err(unsupported_allocation())
+}
+
+pub(crate) fn free_local(_address: usize, _size: usize) -> std::io::Result<()> {
+    Err(unsupported_allocation())
+}
+
+pub(crate) fn protect_local(
+    _address: usize,
+    _len: usize,
+    _permissions: crate::Permissions,
+) -> std::io::Result<crate::Permissions> {
+    Err(unsupported_allocation())
+}
+
+fn unsupported_allocation() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Remote allocation is not supported on macOS",
+    )
+}
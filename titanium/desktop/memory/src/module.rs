@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::{regions, ProcessHandle};
+
+/// An executable or shared library loaded into the address space of a process.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Module {
+    /// The file name of the module, such as `libc.so.6`.
+    pub name: String,
+    /// The full path of the file the module was loaded from.
+    pub path: PathBuf,
+    /// The address the module is loaded at.
+    pub base: usize,
+    /// The number of bytes spanned by all of the mappings of the module.
+    pub size: usize,
+}
+
+impl Module {
+    /// Returns `true` if `addr` lies inside of the module.
+    #[must_use]
+    pub fn contains(&self, addr: usize) -> bool {
+        self.base <= addr && addr < self.base + self.size
+    }
+
+    /// Returns `true` if `name` is either the file name or the full path of the module.
+    #[must_use]
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.path.as_os_str() == name
+    }
+}
+
+/// List the executables and shared libraries loaded into a process, in ascending address order.
+///
+/// Modules are built from the file-backed [`MemoryRegion`]s of the process, so every mapping of
+/// the same file is treated as part of one module.
+///
+/// # Errors
+/// Returns an error if the regions of the process cannot be read.
+///
+/// [`MemoryRegion`]: struct.MemoryRegion.html
+pub fn modules(handle: &ProcessHandle) -> std::io::Result<Vec<Module>> {
+    let mut modules: Vec<Module> = Vec::new();
+    let mut indices: HashMap<(u64, PathBuf), usize> = HashMap::new();
+    for region in regions(handle)? {
+        let path = match &region.path {
+            Some(path) if region.is_file_backed() && path.is_absolute() => path.clone(),
+            _ => continue,
+        };
+        match indices.get(&(region.inode, path.clone())) {
+            Some(&index) => {
+                let module = &mut modules[index];
+                let end = (module.base + module.size).max(region.end);
+                module.base = module.base.min(region.start);
+                module.size = end - module.base;
+            }
+            None => {
+                indices.insert((region.inode, path.clone()), modules.len());
+                modules.push(Module {
+                    name: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    path,
+                    base: region.start,
+                    size: region.size(),
+                });
+            }
+        }
+    }
+    Ok(modules)
+}
+
+/// Find a loaded module by its file name (for example `libgame.so`) or its full path.
+///
+/// # Errors
+/// Returns an error if the regions of the process cannot be read, or a `std::io::Error` with a
+/// `std::io::ErrorKind` of `NotFound` if no module matches `name`.
+pub fn find_module(handle: &ProcessHandle, name: &str) -> std::io::Result<Module> {
+    modules(handle)?
+        .into_iter()
+        .find(|module| module.matches(name))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Module `{name}` not found"),
+            )
+        })
+}
+
+/// Get the address a module is loaded at. See [`find_module`].
+///
+/// # Errors
+/// Returns an error if the regions of the process cannot be read, or if no module matches
+/// `name`.
+///
+/// [`find_module`]: fn.find_module.html
+pub fn module_base(handle: &ProcessHandle, name: &str) -> std::io::Result<usize> {
+    find_module(handle, name).map(|module| module.base)
+}
@@ -0,0 +1,485 @@
+use std::sync::{Arc, Mutex, MutexGuard, Once, Weak};
+
+use crate::{CopyAddress, ProcessHandle, PutAddress};
+
+/// Every live [`PatchSet`], so that they can be restored when the process exits without
+/// dropping them.
+static LIVE_SETS: Mutex<Vec<Weak<Mutex<Patches>>>> = Mutex::new(Vec::new());
+static REGISTER_EXIT_HANDLER: Once = Once::new();
+
+extern "C" fn restore_at_exit() {
+    let sets = LIVE_SETS
+        .lock()
+        .map(|mut sets| std::mem::take(&mut *sets))
+        .unwrap_or_default();
+    restore_sets(&sets);
+}
+
+/// Restore every set before a panic aborts the process, since nothing is dropped and `atexit`
+/// handlers do not run. The panicking thread may hold any of the locks, so none are waited for.
+#[cfg(panic = "abort")]
+fn restore_before_abort() {
+    let sets = LIVE_SETS
+        .try_lock()
+        .map(|mut sets| std::mem::take(&mut *sets))
+        .unwrap_or_default();
+    restore_sets(&sets);
+}
+
+fn restore_sets(sets: &[Weak<Mutex<Patches>>]) {
+    for set in sets.iter().filter_map(Weak::upgrade) {
+        // A set that is locked by another thread is left alone rather than risking a deadlock
+        // while the process is exiting.
+        if let Ok(mut patches) = set.try_lock() {
+            let _ = patches.restore_all();
+        }
+    }
+}
+
+/// The error type for [`PatchSet`]
+#[derive(Debug, thiserror::Error)]
+pub enum PatchError {
+    /// The bytes in memory were not the ones expected
+    #[error("Unexpected bytes at {address:#x} (expected {expected:02x?}, found {found:02x?})")]
+    Mismatch {
+        /// The address of the patch
+        address: usize,
+        /// The bytes that should have been there
+        expected: Vec<u8>,
+        /// The bytes that were actually there
+        found: Vec<u8>,
+    },
+    /// The expected bytes are not the same length as the patch
+    #[error("Expected {expected} bytes but the patch is {patch} bytes long")]
+    LengthMismatch {
+        /// The length of the expected bytes
+        expected: usize,
+        /// The length of the patch
+        patch: usize,
+    },
+    /// The patch overlaps with another patch in the set
+    #[error("Patch at {address:#x} overlaps with another patch")]
+    Overlap {
+        /// The address of the patch
+        address: usize,
+    },
+    /// The patch is not part of the set
+    #[error("Unknown patch")]
+    UnknownPatch,
+    /// Reading or writing memory failed
+    #[error("Reading or writing process memory failed")]
+    Io(#[from] std::io::Error),
+}
+
+/// Identifies a single patch in a [`PatchSet`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PatchId(usize);
+
+#[derive(Debug)]
+struct Patch {
+    address: usize,
+    original: Vec<u8>,
+    patched: Vec<u8>,
+    enabled: bool,
+}
+
+impl Patch {
+    fn overlaps(&self, address: usize, len: usize) -> bool {
+        self.address < address + len && address < self.address + self.patched.len()
+    }
+}
+
+#[derive(Debug)]
+struct Patches {
+    handle: ProcessHandle,
+    patches: Vec<Option<Patch>>,
+}
+
+impl Patches {
+    fn get(&mut self, id: PatchId) -> Result<&mut Patch, PatchError> {
+        self.patches
+            .get_mut(id.0)
+            .and_then(Option::as_mut)
+            .ok_or(PatchError::UnknownPatch)
+    }
+
+    fn set_enabled(&mut self, id: PatchId, enabled: bool) -> Result<(), PatchError> {
+        let handle = self.handle;
+        let patch = self.get(id)?;
+        if patch.enabled == enabled {
+            return Ok(());
+        }
+        if enabled {
+            // Make sure nothing else changed the code since the original bytes were saved.
+            let mut current = vec![0_u8; patch.original.len()];
+            handle.copy_address(patch.address, &mut current)?;
+            if current != patch.original {
+                return Err(PatchError::Mismatch {
+                    address: patch.address,
+                    expected: patch.original.clone(),
+                    found: current,
+                });
+            }
+            write(&handle, patch.address, &patch.patched)?;
+        } else {
+            write(&handle, patch.address, &patch.original)?;
+        }
+        patch.enabled = enabled;
+        Ok(())
+    }
+
+    fn restore_all(&mut self) -> Result<(), PatchError> {
+        let mut result = Ok(());
+        for index in 0..self.patches.len() {
+            if let Err(error) = self.set_enabled(PatchId(index), false) {
+                if !matches!(error, PatchError::UnknownPatch) && result.is_ok() {
+                    result = Err(error);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Write to memory, making the pages writable for the duration of the write if needed.
+///
+/// On Linux, writes through `/proc/<pid>/mem` ignore the page protection, so that is tried
+/// before changing it.
+pub(crate) fn write(handle: &ProcessHandle, address: usize, bytes: &[u8]) -> std::io::Result<()> {
+    let error = match handle.put_address(address, bytes) {
+        Ok(()) => return Ok(()),
+        Err(error) => error,
+    };
+    #[cfg(target_os = "linux")]
+    if crate::platform::force_write(handle.0, address, bytes).is_ok() {
+        return Ok(());
+    }
+    let end = address + bytes.len();
+    let Ok(regions) = crate::regions(handle) else {
+        return Err(error);
+    };
+    let regions: Vec<_> = regions
+        .filter(|region| region.start < end && address < region.end)
+        .collect();
+    if regions.is_empty() || regions.iter().all(|region| region.permissions.write) {
+        return Err(error);
+    }
+    let mut result = Ok(());
+    let mut changed = Vec::with_capacity(regions.len());
+    for region in &regions {
+        let mut permissions = region.permissions;
+        permissions.write = true;
+        let start = region.start.max(address);
+        result = crate::protect(handle, start, region.end.min(end) - start, permissions);
+        if result.is_err() {
+            break;
+        }
+        changed.push(region);
+    }
+    if result.is_ok() {
+        result = handle.put_address(address, bytes);
+    }
+    // Every region that was made writable is restored, even if another one fails.
+    for region in changed {
+        let start = region.start.max(address);
+        let restored = crate::protect(handle, start, region.end.min(end) - start, region.permissions);
+        if result.is_ok() {
+            result = restored;
+        }
+    }
+    result
+}
+
+/// A set of byte patches to another process, such as NOPs, flipped jumps or changed constants,
+/// that remembers the bytes it replaced.
+///
+/// Every patch checks the bytes it is replacing before it is written, and the original bytes are
+/// written back when it is disabled. Pages that are not writable, such as code, are made writable
+/// for the duration of each write. Every enabled patch is restored when the `PatchSet` is
+/// dropped, including while unwinding from a panic, and when the process exits through `exit` or
+/// by returning from `main`. When panics abort, a panic hook restores them before the abort.
+///
+/// Patches are **not** restored if the process is killed by a signal such as `SIGINT`, `SIGTERM`
+/// or `SIGKILL`, calls `abort`, or crashes, and stay in the target. Tools that should clean up
+/// after Ctrl+C have to catch those signals themselves and drop the set or call
+/// [`PatchSet::restore_all`] before exiting.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, PatchSet};
+/// let handle = get_handle("game").unwrap();
+/// let mut patches = PatchSet::new(handle);
+/// // Replace `sub [rax+0x10], ecx` with NOPs so that health never goes down
+/// let god_mode = patches
+///     .apply(0x1234, &[0x90, 0x90, 0x90], Some(&[0x29, 0x48, 0x10]))
+///     .unwrap();
+/// // ...
+/// patches.toggle(god_mode).unwrap();
+/// ```
+///
+/// On Linux, pages are made writable by [`protect`], which attaches to the process with
+/// `ptrace`, so a `PatchSet` cannot write to code while another debugger is attached.
+///
+/// [`protect`]: fn.protect.html
+#[derive(Debug)]
+pub struct PatchSet {
+    patches: Arc<Mutex<Patches>>,
+}
+
+impl PatchSet {
+    /// Create an empty `PatchSet` for a process.
+    #[must_use]
+    pub fn new(handle: ProcessHandle) -> Self {
+        let patches = Arc::new(Mutex::new(Patches {
+            handle,
+            patches: Vec::new(),
+        }));
+        REGISTER_EXIT_HANDLER.call_once(|| {
+            unsafe {
+                libc::atexit(restore_at_exit);
+            }
+            #[cfg(panic = "abort")]
+            {
+                let previous = std::panic::take_hook();
+                std::panic::set_hook(Box::new(move |info| {
+                    restore_before_abort();
+                    previous(info);
+                }));
+            }
+        });
+        if let Ok(mut sets) = LIVE_SETS.lock() {
+            sets.retain(|set| set.strong_count() > 0);
+            sets.push(Arc::downgrade(&patches));
+        }
+        Self { patches }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Patches> {
+        self.patches
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Add a patch that writes `bytes` to `address` without enabling it. If `expected` is given,
+    /// the bytes currently at `address` must match it.
+    ///
+    /// # Errors
+    /// Returns an error if the memory cannot be read, if it does not match `expected`, or if the
+    /// patch overlaps with another patch in the set.
+    pub fn add(
+        &mut self,
+        address: usize,
+        bytes: &[u8],
+        expected: Option<&[u8]>,
+    ) -> Result<PatchId, PatchError> {
+        let mut patches = self.lock();
+        if let Some(expected) = expected {
+            if expected.len() != bytes.len() {
+                return Err(PatchError::LengthMismatch {
+                    expected: expected.len(),
+                    patch: bytes.len(),
+                });
+            }
+        }
+        if patches
+            .patches
+            .iter()
+            .flatten()
+            .any(|patch| patch.overlaps(address, bytes.len()))
+        {
+            return Err(PatchError::Overlap { address });
+        }
+        let mut original = vec![0_u8; bytes.len()];
+        patches.handle.copy_address(address, &mut original)?;
+        if let Some(expected) = expected {
+            if original != expected {
+                return Err(PatchError::Mismatch {
+                    address,
+                    expected: expected.to_vec(),
+                    found: original,
+                });
+            }
+        }
+        patches.patches.push(Some(Patch {
+            address,
+            original,
+            patched: bytes.to_vec(),
+            enabled: false,
+        }));
+        Ok(PatchId(patches.patches.len() - 1))
+    }
+
+    /// Add a patch and enable it straight away. See [`PatchSet::add`].
+    ///
+    /// # Errors
+    /// Returns an error if the patch cannot be added or written.
+    pub fn apply(
+        &mut self,
+        address: usize,
+        bytes: &[u8],
+        expected: Option<&[u8]>,
+    ) -> Result<PatchId, PatchError> {
+        let id = self.add(address, bytes, expected)?;
+        if let Err(error) = self.enable(id) {
+            self.lock().patches[id.0] = None;
+            return Err(error);
+        }
+        Ok(id)
+    }
+
+    /// Write the patched bytes.
+    ///
+    /// # Errors
+    /// Returns an error if the patch is not part of the set, if the original bytes have changed
+    /// since the patch was added, or if the memory cannot be written.
+    pub fn enable(&self, id: PatchId) -> Result<(), PatchError> {
+        self.lock().set_enabled(id, true)
+    }
+
+    /// Write the original bytes back.
+    ///
+    /// # Errors
+    /// Returns an error if the patch is not part of the set or if the memory cannot be written.
+    pub fn disable(&self, id: PatchId) -> Result<(), PatchError> {
+        self.lock().set_enabled(id, false)
+    }
+
+    /// Enable the patch if it is disabled, or disable it if it is enabled, returning whether it
+    /// is now enabled.
+    ///
+    /// # Errors
+    /// Returns an error if the patch cannot be enabled or disabled.
+    pub fn toggle(&self, id: PatchId) -> Result<bool, PatchError> {
+        let mut patches = self.lock();
+        let enabled = !patches.get(id)?.enabled;
+        patches.set_enabled(id, enabled)?;
+        Ok(enabled)
+    }
+
+    /// Returns `true` if the patch is enabled, or `false` if it is disabled or not part of the
+    /// set.
+    #[must_use]
+    pub fn is_enabled(&self, id: PatchId) -> bool {
+        self.lock().get(id).is_ok_and(|patch| patch.enabled)
+    }
+
+    /// Disable a patch and remove it from the set.
+    ///
+    /// # Errors
+    /// Returns an error if the patch is not part of the set or if the original bytes cannot be
+    /// written back, in which case the patch stays in the set.
+    pub fn remove(&mut self, id: PatchId) -> Result<(), PatchError> {
+        let mut patches = self.lock();
+        patches.set_enabled(id, false)?;
+        patches.patches[id.0] = None;
+        Ok(())
+    }
+
+    /// Disable every patch, writing back all of the original bytes.
+    ///
+    /// # Errors
+    /// Returns the first error that occurred, after trying to restore every patch.
+    pub fn restore_all(&self) -> Result<(), PatchError> {
+        self.lock().restore_all()
+    }
+
+    /// The number of patches in the set.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().patches.iter().flatten().count()
+    }
+
+    /// Returns `true` if the set has no patches.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for PatchSet {
+    fn drop(&mut self) {
+        let _ = self.restore_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pid, TryIntoProcessHandle};
+
+    fn own_set() -> PatchSet {
+        PatchSet::new(
+            (std::process::id() as Pid)
+                .try_into_process_handle()
+                .unwrap(),
+        )
+    }
+
+    fn read(buffer: &[u8]) -> Vec<u8> {
+        std::hint::black_box(buffer).to_vec()
+    }
+
+    #[test]
+    fn verifies_the_bytes_it_replaces() {
+        let mut buffer = vec![1_u8, 2, 3, 4, 5, 6];
+        let address = buffer.as_ptr() as usize;
+        let mut patches = own_set();
+
+        let mismatch = patches.add(address, &[9, 9], Some(&[2, 2])).unwrap_err();
+        assert!(matches!(mismatch, PatchError::Mismatch { found, .. } if found == [1, 2]));
+        let length = patches.add(address, &[9, 9], Some(&[1])).unwrap_err();
+        assert!(matches!(
+            length,
+            PatchError::LengthMismatch {
+                expected: 1,
+                patch: 2
+            }
+        ));
+
+        let id = patches.add(address, &[9, 9], Some(&[1, 2])).unwrap();
+        let overlap = patches.add(address + 1, &[9], None).unwrap_err();
+        assert!(matches!(overlap, PatchError::Overlap { .. }));
+
+        // Something else changed the bytes since the patch was added.
+        buffer[0] = 7;
+        assert!(matches!(
+            patches.enable(id),
+            Err(PatchError::Mismatch { .. })
+        ));
+        assert!(!patches.is_enabled(id));
+        assert_eq!(read(&buffer), [7, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn toggles_patches() {
+        let buffer = vec![1_u8, 2, 3, 4];
+        let address = buffer.as_ptr() as usize;
+        let mut patches = own_set();
+
+        let id = patches.apply(address + 1, &[8, 9], None).unwrap();
+        assert!(patches.is_enabled(id));
+        assert_eq!(read(&buffer), [1, 8, 9, 4]);
+        assert!(!patches.toggle(id).unwrap());
+        assert_eq!(read(&buffer), [1, 2, 3, 4]);
+        assert!(patches.toggle(id).unwrap());
+        assert_eq!(read(&buffer), [1, 8, 9, 4]);
+
+        patches.remove(id).unwrap();
+        assert_eq!(read(&buffer), [1, 2, 3, 4]);
+        assert!(patches.is_empty());
+        assert!(matches!(patches.toggle(id), Err(PatchError::UnknownPatch)));
+    }
+
+    #[test]
+    fn restores_patches_when_dropped() {
+        let buffer = vec![1_u8, 2, 3, 4];
+        let address = buffer.as_ptr() as usize;
+        let mut patches = own_set();
+        patches.apply(address, &[5], Some(&[1])).unwrap();
+        patches.apply(address + 2, &[6, 7], None).unwrap();
+        patches.add(address + 1, &[8], None).unwrap();
+        assert_eq!(read(&buffer), [5, 2, 6, 7]);
+
+        drop(patches);
+        assert_eq!(read(&buffer), [1, 2, 3, 4]);
+    }
+}
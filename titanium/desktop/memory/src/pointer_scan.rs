@@ -0,0 +1,523 @@
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{modules, regions, CopyAddress, Module, ProcessHandle};
+
+/// The magic bytes at the start of a saved [`PointerMap`].
+const MAGIC: &[u8; 4] = b"TIPM";
+/// The version of the saved [`PointerMap`] format.
+const VERSION: u32 = 1;
+/// The number of bytes read from the target at a time while generating a [`PointerMap`].
+const CHUNK_SIZE: usize = 4 << 20;
+/// The most pointers [`PointerMap::load`] allocates room for before reading them.
+const MAX_PREALLOCATED: usize = 1 << 20;
+
+/// A static pointer path to an address, written as `module+base -> off1 -> off2 ...`.
+///
+/// The first offset is relative to the base of the module, and the rest are the offsets added to
+/// each pointer along the chain. [`PointerPath::to_offsets`] turns a path into offsets usable with
+/// [`CopyAddress::get_offset`] or [`DataMember::new_offset`], and it can also be passed straight
+/// to [`DataMember::new_module_offset`] along with the module name.
+///
+/// Paths can be written and parsed in the form `libgame.so+0x1a2b0 -> 0x18 -> 0x40`.
+///
+/// [`CopyAddress::get_offset`]: trait.CopyAddress.html#method.get_offset
+/// [`DataMember::new_offset`]: struct.DataMember.html#method.new_offset
+/// [`DataMember::new_module_offset`]: struct.DataMember.html#method.new_module_offset
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PointerPath {
+    /// The file name of the module the path starts in.
+    pub module: String,
+    /// The offset of the static pointer from the module base, followed by the offset added to
+    /// each pointer in the chain.
+    pub offsets: Vec<usize>,
+}
+
+impl PointerPath {
+    /// Turn the path into absolute offsets for a process, by adding the base of the module to the
+    /// first offset.
+    ///
+    /// # Errors
+    /// Returns an error if the module cannot be found in the process.
+    pub fn to_offsets(&self, handle: &ProcessHandle) -> std::io::Result<Vec<usize>> {
+        let base = crate::module_base(handle, &self.module)?;
+        Ok(self.offsets_from(base))
+    }
+
+    /// Follow the path in a process, returning the address it ends at.
+    ///
+    /// # Errors
+    /// Returns an error if the module cannot be found, or if any pointer along the path cannot
+    /// be read.
+    pub fn resolve(&self, handle: &ProcessHandle) -> std::io::Result<usize> {
+        handle.get_offset(&self.to_offsets(handle)?)
+    }
+
+    fn offsets_from(&self, base: usize) -> Vec<usize> {
+        let mut offsets = self.offsets.clone();
+        if let Some(first) = offsets.first_mut() {
+            *first = first.wrapping_add(base);
+        }
+        offsets
+    }
+}
+
+impl std::fmt::Display for PointerPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut offsets = self.offsets.iter();
+        write!(f, "{}+{:#x}", self.module, offsets.next().unwrap_or(&0))?;
+        for offset in offsets {
+            write!(f, " -> {offset:#x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a path in the form `libgame.so+0x1a2b0 -> 0x18 -> 0x40`.
+impl FromStr for PointerPath {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> std::io::Result<Self> {
+        let invalid = || {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid pointer path `{s}`"),
+            )
+        };
+        let parse = |offset: &str| {
+            usize::from_str_radix(offset.trim().trim_start_matches("0x"), 16).map_err(|_| invalid())
+        };
+        let mut parts = s.split("->");
+        let (module, base) = parts
+            .next()
+            .and_then(|first| first.trim().rsplit_once('+'))
+            .ok_or_else(invalid)?;
+        let mut offsets = vec![parse(base)?];
+        for offset in parts {
+            offsets.push(parse(offset)?);
+        }
+        Ok(Self {
+            module: module.to_owned(),
+            offsets,
+        })
+    }
+}
+
+/// Options for [`PointerMap::find_paths`].
+#[derive(Clone, Debug)]
+pub struct PointerScanOptions {
+    /// The maximum number of pointers to follow. Defaults to `5`.
+    pub max_depth: usize,
+    /// The largest offset allowed between a pointer and the address it leads to. Defaults to
+    /// `0x1000`.
+    pub max_offset: usize,
+    /// The names of the modules that paths may start in. If empty, any module is allowed.
+    pub modules: Vec<String>,
+    /// Stop searching once this many paths are found. Defaults to `100_000`.
+    pub max_results: usize,
+    /// Stop searching once this many pointers have been followed, which bounds the time a search
+    /// takes through memory that is dense with pointers. Defaults to `10_000_000`.
+    pub max_visited: usize,
+}
+
+impl Default for PointerScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            max_offset: 0x1000,
+            modules: Vec::new(),
+            max_results: 100_000,
+            max_visited: 10_000_000,
+        }
+    }
+}
+
+/// Every pointer found in a process at some point in time, used to search for pointer paths.
+///
+/// A map is generated once with [`PointerMap::generate`], and can be saved to disk with
+/// [`PointerMap::save`] so that it can be searched later, without the process running. Once the
+/// process restarts, [`rescan`] keeps only the paths that still lead to the value.
+///
+/// [`rescan`]: fn.rescan.html
+#[derive(Clone, Debug, Default)]
+pub struct PointerMap {
+    /// The modules of the process, with their sizes extended over any trailing `.bss` mapping.
+    modules: Vec<Module>,
+    /// Every `(value, address)` pair where `address` holds a pointer to `value`, sorted by value.
+    pointers: Vec<(usize, usize)>,
+}
+
+impl PointerMap {
+    /// Find every aligned pointer in the readable and writable memory of a process, along with
+    /// every pointer stored in a module, that points into readable memory.
+    ///
+    /// # Errors
+    /// Returns an error if the regions or modules of the process cannot be read.
+    pub fn generate(handle: &ProcessHandle) -> std::io::Result<Self> {
+        let regions: Vec<_> = regions(handle)?
+            .filter(|region| region.permissions.read)
+            .collect();
+        let mut modules = modules(handle)?;
+        for module in &mut modules {
+            // `.bss` is mapped anonymously directly after the rest of a module, but statics in it
+            // are still at a fixed offset from the module base.
+            let end = module.base + module.size;
+            if let Some(bss) = regions
+                .iter()
+                .find(|region| region.start == end && region.path.is_none())
+            {
+                module.size += bss.size();
+            }
+        }
+
+        let width = handle.get_pointer_width().pointer_size();
+        let is_valid = |value: usize| {
+            let index = regions.partition_point(|region| region.end <= value);
+            regions.get(index).is_some_and(|region| region.contains(value))
+        };
+        let mut pointers = Vec::new();
+        let mut buffer = vec![0_u8; CHUNK_SIZE];
+        for region in &regions {
+            let in_module = modules.iter().any(|module| module.contains(region.start));
+            if !(region.permissions.write || in_module) {
+                continue;
+            }
+            let mut start = region.start;
+            while start < region.end {
+                let len = (region.end - start).min(CHUNK_SIZE);
+                let chunk = &mut buffer[..len];
+                if handle.copy_address(start, chunk).is_ok() {
+                    for (index, bytes) in chunk.chunks_exact(width).enumerate() {
+                        let value = handle.get_pointer_width().pointer_from_ne_bytes(bytes);
+                        if value != 0 && is_valid(value) {
+                            pointers.push((value, start + index * width));
+                        }
+                    }
+                }
+                start += len;
+            }
+        }
+        pointers.sort_unstable();
+        Ok(Self { modules, pointers })
+    }
+
+    /// The number of pointers in the map.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.pointers.len()
+    }
+
+    /// Returns `true` if the map contains no pointers.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.pointers.is_empty()
+    }
+
+    /// Search the map for static pointer paths that lead to `target`.
+    #[must_use]
+    pub fn find_paths(&self, target: usize, options: &PointerScanOptions) -> Vec<PointerPath> {
+        let statics: Vec<&Module> = self
+            .modules
+            .iter()
+            .filter(|module| {
+                options.modules.is_empty() || options.modules.iter().any(|name| module.matches(name))
+            })
+            .collect();
+        let mut paths = Vec::new();
+        let mut chain = Vec::new();
+        let mut visited = 0;
+        self.search(target, options, &statics, &mut chain, &mut paths, &mut visited);
+        paths
+    }
+
+    /// Find the pointers that lead to `target`, recording a path for each one that is static and
+    /// searching further back from the rest. `chain` holds the address of each pointer followed
+    /// from the original target and the offset added to it, innermost first, and `visited`
+    /// counts every pointer followed so far.
+    fn search(
+        &self,
+        target: usize,
+        options: &PointerScanOptions,
+        statics: &[&Module],
+        chain: &mut Vec<(usize, usize)>,
+        paths: &mut Vec<PointerPath>,
+        visited: &mut usize,
+    ) {
+        let lowest = target.saturating_sub(options.max_offset);
+        let first = self.pointers.partition_point(|(value, _)| *value < lowest);
+        let last = self.pointers.partition_point(|(value, _)| *value <= target);
+        for &(value, address) in &self.pointers[first..last] {
+            if paths.len() >= options.max_results || *visited >= options.max_visited {
+                return;
+            }
+            // A pointer that is already part of the chain only leads around a cycle, and any path
+            // through it is longer than the one that skips the cycle.
+            if chain.iter().any(|(previous, _)| *previous == address) {
+                continue;
+            }
+            *visited += 1;
+            chain.push((address, target - value));
+            if let Some(module) = statics.iter().find(|module| module.contains(address)) {
+                paths.push(PointerPath {
+                    module: module.name.clone(),
+                    offsets: std::iter::once(address - module.base)
+                        .chain(chain.iter().rev().map(|(_, offset)| *offset))
+                        .collect(),
+                });
+            }
+            if chain.len() < options.max_depth {
+                self.search(address, options, statics, chain, paths, visited);
+            }
+            chain.pop();
+        }
+    }
+
+    /// Save the map to a file, so that it can be searched without the process running.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut file = BufWriter::new(std::fs::File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&(self.modules.len() as u64).to_le_bytes())?;
+        for module in &self.modules {
+            let path = module.path.to_string_lossy();
+            file.write_all(&(path.len() as u64).to_le_bytes())?;
+            file.write_all(path.as_bytes())?;
+            file.write_all(&(module.base as u64).to_le_bytes())?;
+            file.write_all(&(module.size as u64).to_le_bytes())?;
+        }
+        file.write_all(&(self.pointers.len() as u64).to_le_bytes())?;
+        for (value, address) in &self.pointers {
+            file.write_all(&(*value as u64).to_le_bytes())?;
+            file.write_all(&(*address as u64).to_le_bytes())?;
+        }
+        file.flush()
+    }
+
+    /// Load a map saved with [`PointerMap::save`].
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, or if it is not a valid pointer map.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut file = BufReader::new(std::fs::File::open(path)?);
+        let mut magic = [0_u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut file)? != VERSION {
+            return Err(invalid_data("Not a pointer map, or a pointer map from another version"));
+        }
+        let mut modules = Vec::new();
+        for _ in 0..read_u64(&mut file)? {
+            let len = read_usize(&mut file)?;
+            let mut path = Vec::new();
+            (&mut file).take(len as u64).read_to_end(&mut path)?;
+            if path.len() != len {
+                return Err(invalid_data("Truncated module path"));
+            }
+            let path = std::path::PathBuf::from(String::from_utf8_lossy(&path).into_owned());
+            modules.push(Module {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                path,
+                base: read_usize(&mut file)?,
+                size: read_usize(&mut file)?,
+            });
+        }
+        let count = read_usize(&mut file)?;
+        // The count is not trusted to size the allocation up front, since a corrupt file could
+        // claim any number of pointers.
+        let mut pointers = Vec::with_capacity(count.min(MAX_PREALLOCATED));
+        for _ in 0..count {
+            pointers.push((read_usize(&mut file)?, read_usize(&mut file)?));
+        }
+        Ok(Self { modules, pointers })
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut bytes = [0_u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut bytes = [0_u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> std::io::Result<usize> {
+    usize::try_from(read_u64(reader)?).map_err(|_| invalid_data("Address is too large"))
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Follow each path in a (possibly restarted) process, keeping only those that still lead to
+/// `target`.
+///
+/// # Errors
+/// Returns an error if the modules of the process cannot be read.
+pub fn rescan(
+    handle: &ProcessHandle,
+    paths: &[PointerPath],
+    target: usize,
+) -> std::io::Result<Vec<PointerPath>> {
+    let modules = modules(handle)?;
+    Ok(paths
+        .iter()
+        .filter(|path| {
+            modules
+                .iter()
+                .find(|module| module.matches(&path.module))
+                .and_then(|module| handle.get_offset(&path.offsets_from(module.base)).ok())
+                == Some(target)
+        })
+        .cloned()
+        .collect())
+}
+
+/// Save pointer paths to a text file, one per line.
+///
+/// # Errors
+/// Returns an error if the file cannot be written.
+pub fn save_paths<P: AsRef<Path>>(path: P, paths: &[PointerPath]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(std::fs::File::create(path)?);
+    for pointer_path in paths {
+        writeln!(file, "{pointer_path}")?;
+    }
+    file.flush()
+}
+
+/// Load pointer paths saved with [`save_paths`].
+///
+/// # Errors
+/// Returns an error if the file cannot be read, or if a line is not a valid pointer path.
+///
+/// [`save_paths`]: fn.save_paths.html
+pub fn load_paths<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<PointerPath>> {
+    BufReader::new(std::fs::File::open(path)?)
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|line| line?.parse())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A map where `game+0x100 -> 0x10 -> 0x40` leads to `0x9040`.
+    fn chain() -> PointerMap {
+        PointerMap {
+            modules: vec![Module {
+                name: String::from("game"),
+                path: std::path::PathBuf::from("/opt/game/game"),
+                base: 0x1000,
+                size: 0x1000,
+            }],
+            pointers: vec![(0x5000, 0x1100), (0x9000, 0x5010)],
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("pointer_scan_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn parses_and_displays_paths() {
+        let path: PointerPath = "libgame.so+0x1a2b0 -> 0x18->40".parse().unwrap();
+        assert_eq!(path.module, "libgame.so");
+        assert_eq!(path.offsets, [0x1a2b0, 0x18, 0x40]);
+        assert_eq!(path.to_string(), "libgame.so+0x1a2b0 -> 0x18 -> 0x40");
+        assert_eq!(path.to_string().parse::<PointerPath>().unwrap(), path);
+
+        let plus: PointerPath = "lib+plus.so+0x10".parse().unwrap();
+        assert_eq!(plus.module, "lib+plus.so");
+        assert_eq!(plus.offsets_from(0x1000), [0x1010]);
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        for path in ["", "libgame.so", "libgame.so+0xzz", "libgame.so+0x10 -> ", "a+1 -> 0x1g"] {
+            let error = path.parse::<PointerPath>().unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{path}");
+        }
+    }
+
+    #[test]
+    fn finds_static_paths() {
+        let paths = chain().find_paths(0x9040, &PointerScanOptions::default());
+        assert_eq!(paths, ["game+0x100 -> 0x10 -> 0x40".parse().unwrap()]);
+
+        let shallow = PointerScanOptions {
+            max_depth: 1,
+            ..PointerScanOptions::default()
+        };
+        assert!(chain().find_paths(0x9040, &shallow).is_empty());
+        let elsewhere = PointerScanOptions {
+            modules: vec![String::from("libc.so.6")],
+            ..PointerScanOptions::default()
+        };
+        assert!(chain().find_paths(0x9040, &elsewhere).is_empty());
+    }
+
+    #[test]
+    fn skips_cycles_and_stops_at_the_visit_limit() {
+        // Two heap objects that point at each other, one of which a static points to.
+        let mut map = chain();
+        map.pointers = vec![(0x5000, 0x1100), (0x5000, 0x6008), (0x6000, 0x5008)];
+        let options = PointerScanOptions {
+            max_depth: 10,
+            ..PointerScanOptions::default()
+        };
+        let paths = map.find_paths(0x6010, &options);
+        assert_eq!(paths, ["game+0x100 -> 0x8 -> 0x10".parse().unwrap()]);
+
+        let limited = PointerScanOptions {
+            max_visited: 1,
+            ..options
+        };
+        assert!(map.find_paths(0x6010, &limited).is_empty());
+    }
+
+    #[test]
+    fn saves_and_loads_maps() {
+        let file = temp_path("map");
+        chain().save(&file).unwrap();
+        let loaded = PointerMap::load(&file);
+        let mut bytes = std::fs::read(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.modules, chain().modules);
+        assert_eq!(loaded.pointers, chain().pointers);
+
+        bytes.truncate(bytes.len() - 1);
+        std::fs::write(&file, &bytes).unwrap();
+        let truncated = PointerMap::load(&file);
+        bytes[0] = b'X';
+        std::fs::write(&file, &bytes).unwrap();
+        let corrupt = PointerMap::load(&file);
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(truncated.unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(corrupt.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn saves_and_loads_paths() {
+        let file = temp_path("paths");
+        let paths: Vec<PointerPath> = ["game+0x100 -> 0x10 -> 0x40", "libc.so.6+0x8"]
+            .iter()
+            .map(|path| path.parse().unwrap())
+            .collect();
+        save_paths(&file, &paths).unwrap();
+        let loaded = load_paths(&file);
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(loaded.unwrap(), paths);
+    }
+}
@@ -0,0 +1,395 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::{Pid, ProcessHandle, TryIntoProcessHandle};
+
+/// How often [`wait_for`] looks for the process again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `/proc/<pid>/comm` is cut off after this many bytes.
+const COMM_LEN: usize = 15;
+
+/// How a [`ProcessQuery`] matches the name of a process.
+#[derive(Clone, Debug)]
+enum NameMatch {
+    Exact(String),
+    Prefix(String),
+    Regex(Regex),
+}
+
+impl NameMatch {
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            Self::Exact(exact) => name == exact,
+            Self::Prefix(prefix) => name.starts_with(prefix.as_str()),
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// A process found by a [`ProcessQuery`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProcessInfo {
+    /// The id of the process.
+    pub pid: Pid,
+    /// The id of the process that started it.
+    pub ppid: Pid,
+    /// The real user id of its owner.
+    pub uid: u32,
+    /// The name of the process. The kernel only keeps the first 15 bytes, so longer names are
+    /// taken from the first argument of the command line when it starts with them.
+    pub name: String,
+    /// The path of the executable, if this process may read it.
+    pub exe: Option<PathBuf>,
+    /// The arguments of the command line, starting with the program.
+    pub cmdline: Vec<String>,
+}
+
+impl ProcessInfo {
+    /// Get a [`ProcessHandle`] for the process.
+    ///
+    /// # Errors
+    /// Returns an error if the process cannot be opened.
+    ///
+    /// [`ProcessHandle`]: type.ProcessHandle.html
+    pub fn handle(&self) -> std::io::Result<ProcessHandle> {
+        self.pid.try_into_process_handle()
+    }
+}
+
+/// A search for running processes, read straight from `/proc`.
+///
+/// Every filter that is set has to match. The files of each process are only read as far as the
+/// filters need, so the command lines of processes with another name are never read, and
+/// [`ProcessQuery::matches`] returns an iterator that only moves on to the next process when it
+/// is asked for one. Zombies, which have exited but not been reaped, never match.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{wait_for, ProcessQuery};
+/// # use std::time::Duration;
+/// let query = ProcessQuery::new()
+///     .name_regex(r"^game(-x64)?$")
+///     .unwrap()
+///     .cmdline_contains("--windowed");
+/// for process in query.matches().unwrap() {
+///     println!("{} {} {:?}", process.pid, process.name, process.exe);
+/// }
+/// // Start before the game does
+/// let game = wait_for(&query, Duration::from_secs(60)).unwrap();
+/// let handle = game.handle().unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ProcessQuery {
+    name: Option<NameMatch>,
+    exe: Option<PathBuf>,
+    cmdline: Option<String>,
+    uid: Option<u32>,
+    ppid: Option<Pid>,
+}
+
+impl ProcessQuery {
+    /// A query that matches every process.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match processes named exactly `name`.
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(NameMatch::Exact(name.into()));
+        self
+    }
+
+    /// Only match processes whose name starts with `prefix`.
+    #[must_use]
+    pub fn name_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.name = Some(NameMatch::Prefix(prefix.into()));
+        self
+    }
+
+    /// Only match processes whose name matches the regular expression `pattern` anywhere, unless
+    /// it is anchored with `^` and `$`.
+    ///
+    /// # Errors
+    /// Returns an error if `pattern` is not a valid regular expression.
+    pub fn name_regex(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.name = Some(NameMatch::Regex(Regex::new(pattern)?));
+        Ok(self)
+    }
+
+    /// Only match processes running the executable at `path`.
+    #[must_use]
+    pub fn exe(mut self, path: impl Into<PathBuf>) -> Self {
+        self.exe = Some(path.into());
+        self
+    }
+
+    /// Only match processes whose command line, with its arguments joined by spaces, contains
+    /// `text`.
+    #[must_use]
+    pub fn cmdline_contains(mut self, text: impl Into<String>) -> Self {
+        self.cmdline = Some(text.into());
+        self
+    }
+
+    /// Only match processes owned by the user `uid`.
+    #[must_use]
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Only match processes started by the process `ppid`.
+    #[must_use]
+    pub fn parent(mut self, ppid: Pid) -> Self {
+        self.ppid = Some(ppid);
+        self
+    }
+
+    /// Every running process that matches, in order of their ids.
+    ///
+    /// # Errors
+    /// Returns an error if `/proc` cannot be read.
+    pub fn matches(&self) -> std::io::Result<ProcessMatches<'_>> {
+        let mut pids: Vec<Pid> = std::fs::read_dir("/proc")?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        pids.sort_unstable();
+        Ok(ProcessMatches {
+            query: self,
+            pids: pids.into_iter(),
+        })
+    }
+
+    /// The first running process that matches, if there is one.
+    ///
+    /// # Errors
+    /// Returns an error if `/proc` cannot be read.
+    pub fn first(&self) -> std::io::Result<Option<ProcessInfo>> {
+        Ok(self.matches()?.next())
+    }
+
+    /// Read a process if it matches. Processes that exit while they are being read never match.
+    fn read(&self, pid: Pid) -> Option<ProcessInfo> {
+        let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+        // Zombies have already exited, and are only waiting for their parent to reap them.
+        let state = status.lines().find_map(|line| line.strip_prefix("State:"))?;
+        if state.trim_start().starts_with(['Z', 'X']) {
+            return None;
+        }
+        let ppid = status_field(&status, "PPid")?;
+        let uid = status_field(&status, "Uid")?;
+        if self.ppid.is_some_and(|wanted| wanted != ppid) || self.uid.is_some_and(|wanted| wanted != uid) {
+            return None;
+        }
+
+        let comm = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Name:"))?
+            .trim()
+            .to_owned();
+        // The name can only be cut off if it is as long as the kernel keeps, so the command line
+        // is left for later unless it is.
+        let truncated = comm.len() >= COMM_LEN;
+        if !truncated && self.name.as_ref().is_some_and(|name| !name.is_match(&comm)) {
+            return None;
+        }
+
+        let exe = std::fs::read_link(format!("/proc/{pid}/exe")).ok();
+        if let Some(wanted) = &self.exe {
+            if exe.as_deref() != Some(wanted.as_path()) {
+                return None;
+            }
+        }
+
+        let cmdline = read_cmdline(pid)?;
+        let name = if truncated {
+            full_name(&comm, &cmdline, exe.as_deref())
+        } else {
+            comm
+        };
+        if truncated && self.name.as_ref().is_some_and(|wanted| !wanted.is_match(&name)) {
+            return None;
+        }
+        if let Some(text) = &self.cmdline {
+            if !cmdline.join(" ").contains(text.as_str()) {
+                return None;
+            }
+        }
+
+        Some(ProcessInfo {
+            pid,
+            ppid,
+            uid,
+            name,
+            exe,
+            cmdline,
+        })
+    }
+}
+
+/// An iterator over the processes that match a [`ProcessQuery`], created with
+/// [`ProcessQuery::matches`].
+#[derive(Debug)]
+pub struct ProcessMatches<'a> {
+    query: &'a ProcessQuery,
+    pids: std::vec::IntoIter<Pid>,
+}
+
+impl Iterator for ProcessMatches<'_> {
+    type Item = ProcessInfo;
+
+    fn next(&mut self) -> Option<ProcessInfo> {
+        let query = self.query;
+        self.pids.find_map(|pid| query.read(pid))
+    }
+}
+
+/// Wait until a process matches `query`, checking ten times a second, and return the first one
+/// that does.
+///
+/// # Errors
+/// Returns an error with the kind `TimedOut` if no process matches within `timeout`, or if
+/// `/proc` cannot be read.
+pub fn wait_for(query: &ProcessQuery, timeout: Duration) -> std::io::Result<ProcessInfo> {
+    let start = Instant::now();
+    loop {
+        if let Some(process) = query.first()? {
+            return Ok(process);
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("No process matched within {timeout:?}"),
+            ));
+        }
+        std::thread::sleep(POLL_INTERVAL.min(timeout - elapsed));
+    }
+}
+
+/// The first number of a field in `/proc/<pid>/status`.
+fn status_field<T: std::str::FromStr>(status: &str, field: &str) -> Option<T> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The arguments in `/proc/<pid>/cmdline`, which are separated by null bytes. Kernel threads
+/// have none.
+fn read_cmdline(pid: Pid) -> Option<Vec<String>> {
+    let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    Some(
+        cmdline
+            .split(|byte| *byte == 0)
+            .filter(|argument| !argument.is_empty())
+            .map(|argument| String::from_utf8_lossy(argument).into_owned())
+            .collect(),
+    )
+}
+
+/// The whole name of a process whose name was cut off, from the program in its command line or
+/// its executable.
+fn full_name(comm: &str, cmdline: &[String], exe: Option<&Path>) -> String {
+    cmdline
+        .first()
+        .map(Path::new)
+        .into_iter()
+        .chain(exe)
+        .filter_map(|path| path.file_name()?.to_str())
+        .find(|name| name.starts_with(comm))
+        .unwrap_or(comm)
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn own_pid() -> Pid {
+        std::process::id() as Pid
+    }
+
+    fn own_exe() -> PathBuf {
+        std::fs::read_link("/proc/self/exe").unwrap()
+    }
+
+    #[test]
+    fn finds_the_current_process() {
+        let ppid = unsafe { libc::getppid() };
+        let uid = unsafe { libc::getuid() };
+        let query = ProcessQuery::new().parent(ppid).uid(uid).exe(own_exe());
+        let found: Vec<_> = query
+            .matches()
+            .unwrap()
+            .map(|process| process.pid)
+            .collect();
+        assert!(found.contains(&own_pid()));
+
+        let process = query
+            .matches()
+            .unwrap()
+            .find(|process| process.pid == own_pid())
+            .unwrap();
+        assert_eq!(process.ppid, ppid);
+        assert_eq!(process.exe, Some(own_exe()));
+        assert_eq!(process.cmdline[0], std::env::args().next().unwrap());
+    }
+
+    #[test]
+    fn matches_long_names_in_full() {
+        // The name of the test binary is longer than the kernel keeps.
+        let name = own_exe().file_name().unwrap().to_str().unwrap().to_owned();
+        assert!(name.len() > COMM_LEN);
+        let mine = |query: ProcessQuery| {
+            query
+                .matches()
+                .unwrap()
+                .any(|process| process.pid == own_pid())
+        };
+        assert!(mine(ProcessQuery::new().name(name.as_str())));
+        assert!(mine(ProcessQuery::new().name_prefix(&name[..COMM_LEN + 1])));
+        assert!(mine(
+            ProcessQuery::new()
+                .name_regex(&format!("^{}$", regex::escape(&name)))
+                .unwrap()
+        ));
+        assert!(!mine(ProcessQuery::new().name(&name[..COMM_LEN])));
+        assert!(!mine(ProcessQuery::new().parent(own_pid())));
+    }
+
+    #[test]
+    fn takes_cut_off_names_from_the_command_line() {
+        let cmdline = [
+            String::from("/opt/game/a-very-long-game-name"),
+            String::from("--windowed"),
+        ];
+        assert_eq!(
+            full_name("a-very-long-gam", &cmdline, None),
+            "a-very-long-game-name"
+        );
+        let exe = Path::new("/opt/game/a-very-long-game-name.x86_64");
+        assert_eq!(
+            full_name("a-very-long-gam", &[], Some(exe)),
+            "a-very-long-game-name.x86_64"
+        );
+        assert_eq!(
+            full_name("something-else-", &cmdline, Some(exe)),
+            "something-else-"
+        );
+    }
+
+    #[test]
+    fn times_out_waiting() {
+        let query = ProcessQuery::new().name("no process has this name");
+        let error = wait_for(&query, Duration::ZERO).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    }
+}
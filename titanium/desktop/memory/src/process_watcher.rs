@@ -0,0 +1,461 @@
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+
+use crate::{DataMember, Memory, MemoryError, Pid, ProcessHandle, ProcessInfo, ProcessQuery};
+
+/// How often the watcher checks `/proc` while it waits for the process to start, or to exit when
+/// `pidfd_open` is not available.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Something that changes with the watched process: the state of a [`WatchedMember`].
+trait Rebind: Send + Sync {
+    fn rebind(&self, handle: Option<ProcessHandle>);
+}
+
+/// A running instance of the watched process.
+#[derive(Debug)]
+struct Instance {
+    info: ProcessInfo,
+    handle: ProcessHandle,
+    /// When the process started, in clock ticks after boot, which tells it apart from a later
+    /// process that reuses its id.
+    start_time: u64,
+    /// A file descriptor that becomes readable when the process exits, on Linux 5.3 and later.
+    pidfd: Option<OwnedFd>,
+}
+
+impl Instance {
+    fn new(info: ProcessInfo) -> Option<Self> {
+        let handle = info.handle().ok()?;
+        let (_, start_time) = process_state(info.pid)?;
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, info.pid, 0) };
+        let pidfd = i32::try_from(pidfd)
+            .ok()
+            .filter(|fd| *fd >= 0)
+            .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+        Some(Self {
+            info,
+            handle,
+            start_time,
+            pidfd,
+        })
+    }
+
+    /// Returns `true` if this is still the same process and it has not exited.
+    fn is_alive(&self) -> bool {
+        process_state(self.info.pid)
+            .is_some_and(|(state, start_time)| start_time == self.start_time && state != 'Z' && state != 'X')
+    }
+
+    /// Wait up to `timeout` for the process to exit, returning `true` if it has.
+    fn wait_for_exit(&self, timeout: Duration) -> bool {
+        if let Some(pidfd) = &self.pidfd {
+            let mut poll = libc::pollfd {
+                fd: pidfd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+            let ready = unsafe { libc::poll(&mut poll, 1, timeout) };
+            // If polling fails the process is checked through `/proc` instead.
+            if ready >= 0 {
+                return ready > 0;
+            }
+        }
+        let start = Instant::now();
+        loop {
+            if !self.is_alive() {
+                return true;
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return false;
+            }
+            std::thread::sleep(POLL_INTERVAL.min(timeout - elapsed));
+        }
+    }
+}
+
+/// Something that happened to the watched process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProcessEvent {
+    /// A process matching the query was found, either for the first time or after the last one
+    /// exited, and every [`WatchedMember`] now points into it.
+    ///
+    /// [`WatchedMember`]: struct.WatchedMember.html
+    Started {
+        /// The process that was found.
+        process: ProcessInfo,
+        /// `true` if an earlier instance of the process was watched before this one.
+        relaunch: bool,
+    },
+    /// The watched process exited. Every [`WatchedMember`] fails until it starts again.
+    ///
+    /// [`WatchedMember`]: struct.WatchedMember.html
+    Exited {
+        /// The id the process had.
+        pid: Pid,
+    },
+}
+
+/// Follows a process across restarts, so that long-running tools survive the game being closed
+/// and opened again.
+///
+/// The watcher looks for a process matching a [`ProcessQuery`], reports when it exits through a
+/// `pidfd` (or by polling `/proc` on kernels older than 5.3), and looks for the next instance.
+/// [`WatchedMember`]s created by the watcher are pointed at every new instance automatically,
+/// including members relative to the base of a module, which moves every time.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{Memory, ProcessEvent, ProcessQuery, ProcessWatcher};
+/// # use std::time::Duration;
+/// let mut watcher = ProcessWatcher::new(ProcessQuery::new().name("game"));
+/// let health = watcher.module_member::<f32>("game", vec![0x1234, 0x30]);
+/// loop {
+///     match watcher.wait(Duration::from_millis(500)).unwrap() {
+///         Some(ProcessEvent::Started { process, .. }) => println!("{} started", process.pid),
+///         Some(ProcessEvent::Exited { pid }) => println!("{pid} exited"),
+///         None => {}
+///     }
+///     if let Ok(health) = unsafe { health.read() } {
+///         println!("Health: {health}");
+///     }
+/// }
+/// ```
+///
+/// [`ProcessQuery`]: struct.ProcessQuery.html
+/// [`WatchedMember`]: struct.WatchedMember.html
+pub struct ProcessWatcher {
+    query: ProcessQuery,
+    current: Option<Instance>,
+    /// The id and start time of the last instance, so that it is not found again after it exits.
+    previous: Option<(Pid, u64)>,
+    members: Vec<Weak<dyn Rebind>>,
+}
+
+impl std::fmt::Debug for ProcessWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessWatcher")
+            .field("query", &self.query)
+            .field("current", &self.current)
+            .field("previous", &self.previous)
+            .field("members", &self.members.len())
+            .finish()
+    }
+}
+
+impl ProcessWatcher {
+    /// Watch for processes matching `query`. Nothing is looked for until [`ProcessWatcher::wait`]
+    /// is called, which reports a process that is already running as started.
+    #[must_use]
+    pub fn new(query: ProcessQuery) -> Self {
+        Self {
+            query,
+            current: None,
+            previous: None,
+            members: Vec::new(),
+        }
+    }
+
+    /// The process being watched, if it is running.
+    #[must_use]
+    pub fn process(&self) -> Option<&ProcessInfo> {
+        self.current.as_ref().map(|instance| &instance.info)
+    }
+
+    /// A handle to the process being watched, if it is running.
+    #[must_use]
+    pub fn handle(&self) -> Option<ProcessHandle> {
+        self.current.as_ref().map(|instance| instance.handle)
+    }
+
+    /// Wait up to `timeout` for the process to start or exit, returning what happened, or `None`
+    /// if nothing did. A `timeout` of zero only checks.
+    ///
+    /// # Errors
+    /// Returns an error if `/proc` cannot be read.
+    pub fn wait(&mut self, timeout: Duration) -> std::io::Result<Option<ProcessEvent>> {
+        if let Some(instance) = &self.current {
+            if !instance.wait_for_exit(timeout) {
+                return Ok(None);
+            }
+            let pid = instance.info.pid;
+            self.previous = Some((pid, instance.start_time));
+            self.current = None;
+            self.rebind(None);
+            return Ok(Some(ProcessEvent::Exited { pid }));
+        }
+
+        let start = Instant::now();
+        loop {
+            if let Some(instance) = self.find()? {
+                let process = instance.info.clone();
+                let handle = instance.handle;
+                self.current = Some(instance);
+                self.rebind(Some(handle));
+                return Ok(Some(ProcessEvent::Started {
+                    process,
+                    relaunch: self.previous.is_some(),
+                }));
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Ok(None);
+            }
+            std::thread::sleep(POLL_INTERVAL.min(timeout - elapsed));
+        }
+    }
+
+    /// The first matching process that is not the instance that just exited.
+    fn find(&self) -> std::io::Result<Option<Instance>> {
+        Ok(self
+            .query
+            .matches()?
+            .filter_map(Instance::new)
+            .find(|instance| self.previous != Some((instance.info.pid, instance.start_time))))
+    }
+
+    /// Point every member that is still alive at `handle`.
+    fn rebind(&mut self, handle: Option<ProcessHandle>) {
+        self.members.retain(|member| match member.upgrade() {
+            Some(member) => {
+                member.rebind(handle);
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// A member at `offsets` in every instance of the process. The offsets work in the same way
+    /// as [`DataMember::new_offset`].
+    ///
+    /// [`DataMember::new_offset`]: struct.DataMember.html#method.new_offset
+    pub fn member<T>(&mut self, offsets: Vec<usize>) -> WatchedMember<T>
+    where
+        T: Copy + Send + 'static,
+    {
+        self.watch(None, offsets)
+    }
+
+    /// A member whose first offset is relative to the base of `module` in every instance of the
+    /// process. The offsets work in the same way as [`DataMember::new_module_offset`].
+    ///
+    /// [`DataMember::new_module_offset`]: struct.DataMember.html#method.new_module_offset
+    pub fn module_member<T>(&mut self, module: &str, offsets: Vec<usize>) -> WatchedMember<T>
+    where
+        T: Copy + Send + 'static,
+    {
+        self.watch(Some(module.to_owned()), offsets)
+    }
+
+    fn watch<T>(&mut self, module: Option<String>, offsets: Vec<usize>) -> WatchedMember<T>
+    where
+        T: Copy + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(MemberState {
+                handle: None,
+                last_pid: None,
+                module,
+                offsets,
+                member: None,
+            }),
+        });
+        shared.rebind(self.handle());
+        let weak = Arc::downgrade(&shared);
+        self.members.push(weak);
+        WatchedMember { shared }
+    }
+}
+
+#[derive(Debug)]
+struct MemberState<T> {
+    handle: Option<ProcessHandle>,
+    last_pid: Option<Pid>,
+    module: Option<String>,
+    offsets: Vec<usize>,
+    /// The member in the current instance, once its module has been found.
+    member: Option<DataMember<T>>,
+}
+
+impl<T: Copy> MemberState<T> {
+    /// The member in the current instance, resolving it first if needed.
+    fn resolve(&mut self) -> std::io::Result<&DataMember<T>> {
+        let member = match self.member.take() {
+            Some(member) => member,
+            None => {
+                let Some(handle) = self.handle else {
+                    return Err(match self.last_pid {
+                        Some(pid) => MemoryError::ProcessGone { pid },
+                        None => MemoryError::ProcessNotFound {
+                            name: "the watched process".to_owned(),
+                        },
+                    }
+                    .into());
+                };
+                match &self.module {
+                    Some(module) => {
+                        DataMember::new_module_offset(handle, module, self.offsets.clone())?
+                    }
+                    None => DataMember::new_offset(handle, self.offsets.clone()),
+                }
+            }
+        };
+        Ok(self.member.insert(member))
+    }
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    state: Mutex<MemberState<T>>,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, MemberState<T>> {
+        self.state.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+impl<T: Copy + Send> Rebind for Shared<T> {
+    fn rebind(&self, handle: Option<ProcessHandle>) {
+        let mut state = self.lock();
+        if let Some(old) = state.handle {
+            state.last_pid = Some(old.0);
+        }
+        state.handle = handle;
+        state.member = None;
+        // Modules may not be loaded yet, in which case this is tried again on the next access.
+        let _ = state.resolve();
+    }
+}
+
+/// A [`DataMember`] that follows a process watched by a [`ProcessWatcher`] across restarts.
+///
+/// While the process is not running, every access fails with [`MemoryError::ProcessGone`] (or
+/// [`MemoryError::ProcessNotFound`] if it has not been found yet). A member relative to a module
+/// that has not been loaded yet is resolved again on every access until it is.
+///
+/// [`DataMember`]: struct.DataMember.html
+/// [`ProcessWatcher`]: struct.ProcessWatcher.html
+/// [`MemoryError::ProcessGone`]: enum.MemoryError.html#variant.ProcessGone
+/// [`MemoryError::ProcessNotFound`]: enum.MemoryError.html#variant.ProcessNotFound
+pub struct WatchedMember<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> std::fmt::Debug for WatchedMember<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.shared.lock();
+        f.debug_struct("WatchedMember")
+            .field("handle", &state.handle)
+            .field("module", &state.module)
+            .field("offsets", &state.offsets)
+            .field("resolved", &state.member.is_some())
+            .finish()
+    }
+}
+
+impl<T: Copy> WatchedMember<T> {
+    /// The member in the current instance of the process.
+    ///
+    /// # Errors
+    /// Returns an error if the process is not running, or if the module of the member cannot be
+    /// found in it.
+    pub fn member(&self) -> std::io::Result<DataMember<T>> {
+        self.shared.lock().resolve().cloned()
+    }
+
+    /// Returns `true` if the member points into a running process.
+    #[must_use]
+    pub fn is_resolved(&self) -> bool {
+        self.shared.lock().resolve().is_ok()
+    }
+}
+
+impl<T: Copy> Memory<T> for WatchedMember<T> {
+    fn set_offset(&mut self, new_offsets: Vec<usize>) {
+        let mut state = self.shared.lock();
+        state.offsets = new_offsets;
+        state.member = None;
+    }
+
+    fn get_offset(&self) -> std::io::Result<usize> {
+        self.shared.lock().resolve()?.get_offset()
+    }
+
+    unsafe fn read(&self) -> std::io::Result<T> {
+        self.shared.lock().resolve()?.read()
+    }
+
+    fn write(&self, value: &T) -> std::io::Result<()> {
+        self.shared.lock().resolve()?.write(value)
+    }
+}
+
+/// The state of a process and when it started, from `/proc/<pid>/stat`.
+fn process_state(pid: Pid) -> Option<(char, u64)> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The name in brackets can contain spaces and brackets, so the fields start after the last
+    // closing bracket.
+    let mut fields = stat.get(stat.rfind(')')? + 1..)?.split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    // The start time is the 22nd field, and the state is the 3rd.
+    let start_time = fields.nth(18)?.parse().ok()?;
+    Some((state, start_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Child, Command};
+
+    fn spawn_sleep() -> Child {
+        Command::new("sleep").arg("30").spawn().unwrap()
+    }
+
+    #[test]
+    fn reports_children_starting_and_exiting() {
+        let query = ProcessQuery::new()
+            .parent(std::process::id() as Pid)
+            .name("sleep");
+        let mut watcher = ProcessWatcher::new(query);
+        let member = watcher.member::<u32>(vec![0x1000]);
+        assert!(watcher.wait(Duration::ZERO).unwrap().is_none());
+        assert!(!member.is_resolved());
+
+        let mut child = spawn_sleep();
+        let pid = child.id() as Pid;
+        match watcher.wait(Duration::from_secs(5)).unwrap() {
+            Some(ProcessEvent::Started { process, relaunch }) => {
+                assert_eq!(process.pid, pid);
+                assert!(!relaunch);
+            }
+            event => panic!("expected the child to start, got {event:?}"),
+        }
+        assert_eq!(watcher.handle().map(|handle| handle.0), Some(pid));
+        assert!(member.is_resolved());
+        assert!(watcher.wait(Duration::ZERO).unwrap().is_none());
+
+        child.kill().unwrap();
+        assert_eq!(
+            watcher.wait(Duration::from_secs(5)).unwrap(),
+            Some(ProcessEvent::Exited { pid })
+        );
+        child.wait().unwrap();
+        assert!(watcher.process().is_none());
+        assert!(!member.is_resolved());
+        let gone = unsafe { member.read() }.map_err(MemoryError::from);
+        assert!(matches!(gone, Err(MemoryError::ProcessGone { pid: gone }) if gone == pid));
+
+        let mut relaunched = spawn_sleep();
+        let event = watcher.wait(Duration::from_secs(5)).unwrap();
+        relaunched.kill().unwrap();
+        relaunched.wait().unwrap();
+        assert!(matches!(
+            event,
+            Some(ProcessEvent::Started { process, relaunch: true })
+                if process.pid == relaunched.id() as Pid
+        ));
+    }
+}
@@ -0,0 +1,437 @@
+use crate::{Architecture, CopyAddress, DataMember, ProcessHandle};
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// Something registered with a [`ReadCache`]: a fixed range, or a pointer chain whose addresses
+/// are resolved again after every refresh.
+#[derive(Debug)]
+enum Entry {
+    Range {
+        address: usize,
+        len: usize,
+    },
+    Chain {
+        offsets: Vec<usize>,
+        len: usize,
+        /// The ranges the chain needed the last time it was resolved: every pointer along the
+        /// way and the value at the end.
+        resolved: Vec<(usize, usize)>,
+    },
+}
+
+/// A page-aligned range of memory copied during the last refresh.
+#[derive(Debug)]
+struct Span {
+    start: usize,
+    bytes: Vec<u8>,
+    valid: bool,
+}
+
+impl Span {
+    fn end(&self) -> usize {
+        self.start + self.bytes.len()
+    }
+}
+
+/// A snapshot of the parts of another process that are read every frame, so that every read in a
+/// tick sees the same instant of its state.
+///
+/// Addresses, pointer chains and [`DataMember`]s are registered with the cache, which merges them
+/// into page-aligned spans and copies all of them with a single
+/// [`CopyAddress::copy_addresses`] in [`ReadCache::refresh`]. The cache implements
+/// [`CopyAddress`] itself, so anything that reads through a `CopyAddress`, such as
+/// [`DataMember::read_from`] or [`RemoteStruct::read`], reads the snapshot instead of the
+/// process.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, DataMember, ReadCache};
+/// let handle = get_handle("game").unwrap();
+/// let health = DataMember::<f32>::new_offset(handle, vec![0x1234, 0x30]);
+/// let ammo = DataMember::<u32>::new_offset(handle, vec![0x1234, 0x38]);
+/// let mut cache = ReadCache::new(handle);
+/// cache.register_member(&health);
+/// cache.register_member(&ammo);
+/// loop {
+///     cache.refresh().unwrap();
+///     let (health, ammo) = unsafe { (health.read_from(&cache), ammo.read_from(&cache)) };
+///     // draw the overlay
+/// #   break;
+/// }
+/// ```
+///
+/// Pointer chains are resolved again from the snapshot after every refresh. If a pointer along a
+/// chain changed, the new addresses are copied straight away with a second batch, and are part of
+/// the spans from the next refresh on. Reads of anything that is not in a span, including spans
+/// that could not be copied, go to the process directly.
+///
+/// [`DataMember`]: struct.DataMember.html
+/// [`CopyAddress`]: trait.CopyAddress.html
+/// [`CopyAddress::copy_addresses`]: trait.CopyAddress.html#method.copy_addresses
+/// [`DataMember::read_from`]: struct.DataMember.html#method.read_from
+/// [`RemoteStruct::read`]: trait.RemoteStruct.html#method.read
+#[derive(Debug)]
+pub struct ReadCache<T: CopyAddress = ProcessHandle> {
+    source: T,
+    entries: Vec<Entry>,
+    spans: Vec<Span>,
+    ticks: u64,
+}
+
+impl<T: CopyAddress> ReadCache<T> {
+    /// Create an empty cache that reads from `source`.
+    #[must_use]
+    pub fn new(source: T) -> Self {
+        Self {
+            source,
+            entries: Vec::new(),
+            spans: Vec::new(),
+            ticks: 0,
+        }
+    }
+
+    /// Copy `len` bytes at `address` on every refresh.
+    pub fn register(&mut self, address: usize, len: usize) {
+        self.entries.push(Entry::Range { address, len });
+    }
+
+    /// Copy the `len` bytes at the end of a pointer chain on every refresh, along with every
+    /// pointer along the way. The offsets work in the same way as [`CopyAddress::get_offset`].
+    ///
+    /// [`CopyAddress::get_offset`]: trait.CopyAddress.html#method.get_offset
+    pub fn register_chain(&mut self, offsets: Vec<usize>, len: usize) {
+        let resolved = resolve(&self.source, &offsets, len);
+        self.entries.push(Entry::Chain {
+            offsets,
+            len,
+            resolved,
+        });
+    }
+
+    /// Copy the value of a [`DataMember`] on every refresh, along with every pointer in its
+    /// chain of offsets.
+    ///
+    /// [`DataMember`]: struct.DataMember.html
+    pub fn register_member<U: Copy>(&mut self, member: &DataMember<U>) {
+        self.register_chain(member.offsets().to_vec(), std::mem::size_of::<U>());
+    }
+
+    /// Forget everything that was registered, along with the snapshot.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.spans.clear();
+    }
+
+    /// The number of times the cache has been refreshed.
+    #[must_use]
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// The source the cache copies from.
+    #[must_use]
+    pub fn source(&self) -> &T {
+        &self.source
+    }
+
+    /// Copy every registered range from the process, replacing the previous snapshot.
+    ///
+    /// # Errors
+    /// Returns the first error if any span cannot be copied. The spans that could be copied are
+    /// still updated, and reads from the ones that could not go to the process directly.
+    pub fn refresh(&mut self) -> std::io::Result<()> {
+        let ranges: Vec<_> = self
+            .entries
+            .iter()
+            .flat_map(|entry| match entry {
+                Entry::Range { address, len } => vec![(*address, *len)],
+                Entry::Chain { resolved, .. } => resolved.clone(),
+            })
+            .collect();
+        self.spans = merge(ranges);
+        let result = self.copy_spans(0);
+        self.ticks += 1;
+        // Spans that could not be copied are dropped, so that reads from them go to the process
+        // and the spans copied below cannot be shadowed by them.
+        self.spans.retain(|span| span.valid);
+
+        // Follow the chains through the new snapshot, and copy anything they lead to that it
+        // does not have yet.
+        let mut missing = Vec::new();
+        let mut entries = std::mem::take(&mut self.entries);
+        for entry in &mut entries {
+            if let Entry::Chain {
+                offsets,
+                len,
+                resolved,
+            } = entry
+            {
+                *resolved = resolve(&*self, offsets, *len);
+                missing.extend(
+                    resolved
+                        .iter()
+                        .filter(|(address, len)| !self.contains(*address, *len))
+                        .copied(),
+                );
+            }
+        }
+        self.entries = entries;
+        if missing.is_empty() {
+            return result;
+        }
+        let first_new = self.spans.len();
+        self.spans.extend(merge(missing));
+        let second = self.copy_spans(first_new);
+        self.spans.retain(|span| span.valid);
+        self.spans.sort_by_key(|span| span.start);
+        self.spans = coalesce(std::mem::take(&mut self.spans));
+        result.and(second)
+    }
+
+    /// Copy every span from `first` on with one batch.
+    fn copy_spans(&mut self, first: usize) -> std::io::Result<()> {
+        let mut entries: Vec<_> = self.spans[first..]
+            .iter_mut()
+            .map(|span| (span.start, span.bytes.as_mut_slice()))
+            .collect();
+        let results = self.source.copy_addresses(&mut entries);
+        let mut first_error = Ok(());
+        for (span, result) in self.spans[first..].iter_mut().zip(results) {
+            span.valid = result.is_ok();
+            if let Err(error) = result {
+                if first_error.is_ok() {
+                    first_error = Err(error);
+                }
+            }
+        }
+        first_error
+    }
+
+    /// The valid span that contains all of `len` bytes at `address`.
+    fn span(&self, address: usize, len: usize) -> Option<&Span> {
+        let index = self
+            .spans
+            .partition_point(|span| span.start <= address)
+            .checked_sub(1)?;
+        let span = &self.spans[index];
+        (span.valid && address.checked_add(len)? <= span.end()).then_some(span)
+    }
+
+    /// Returns `true` if all of `len` bytes at `address` are in the snapshot.
+    #[must_use]
+    pub fn contains(&self, address: usize, len: usize) -> bool {
+        self.span(address, len).is_some()
+    }
+}
+
+/// Sort ranges and merge them into page-aligned spans, joining spans that touch.
+fn merge(mut ranges: Vec<(usize, usize)>) -> Vec<Span> {
+    ranges.retain(|(_, len)| *len > 0);
+    ranges.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (address, len) in ranges {
+        let start = address & !(PAGE_SIZE - 1);
+        let end = address
+            .saturating_add(len)
+            .saturating_add(PAGE_SIZE - 1)
+            & !(PAGE_SIZE - 1);
+        match merged.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(start, end)| Span {
+            start,
+            bytes: vec![0; end - start],
+            valid: false,
+        })
+        .collect()
+}
+
+/// Join sorted, valid spans that overlap, since `span` only looks at the last span
+/// that starts at or before an address. Where spans overlap, the bytes of the earlier one are
+/// kept.
+fn coalesce(spans: Vec<Span>) -> Vec<Span> {
+    let mut joined: Vec<Span> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match joined.last_mut() {
+            Some(last) if span.start < last.end() => {
+                if span.end() > last.end() {
+                    let skip = last.end() - span.start;
+                    last.bytes.extend_from_slice(&span.bytes[skip..]);
+                }
+            }
+            _ => joined.push(span),
+        }
+    }
+    joined
+}
+
+/// The ranges a pointer chain reads: every pointer along the way, and `len` bytes at the end.
+/// A chain that cannot be followed stops at the first pointer that cannot be read.
+fn resolve<S: CopyAddress + ?Sized>(source: &S, offsets: &[usize], len: usize) -> Vec<(usize, usize)> {
+    let Some((last, pointers)) = offsets.split_last() else {
+        return Vec::new();
+    };
+    let arch = source.get_pointer_width();
+    let width = arch.pointer_size();
+    let mut ranges = Vec::with_capacity(offsets.len());
+    let mut pointer = vec![0_u8; width];
+    let mut address: usize = 0;
+    for offset in pointers {
+        address = address.wrapping_add(*offset);
+        ranges.push((address, width));
+        if source.copy_address(address, &mut pointer).is_err() {
+            return ranges;
+        }
+        address = arch.pointer_from_ne_bytes(&pointer);
+    }
+    ranges.push((address.wrapping_add(*last), len));
+    ranges
+}
+
+/// Read from the snapshot, or from the process if the range is not in it.
+impl<T: CopyAddress> CopyAddress for ReadCache<T> {
+    fn copy_address(&self, addr: usize, buf: &mut [u8]) -> std::io::Result<()> {
+        match self.span(addr, buf.len()) {
+            Some(span) => {
+                let offset = addr - span.start;
+                buf.copy_from_slice(&span.bytes[offset..offset + buf.len()]);
+                Ok(())
+            }
+            None => self.source.copy_address(addr, buf),
+        }
+    }
+
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn get_pointer_width(&self) -> Architecture {
+        self.source.get_pointer_width()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LocalProcess;
+
+    /// Reads through [`LocalProcess`], failing every read while `fail` is set.
+    #[derive(Debug, Default)]
+    struct Failing {
+        fail: std::cell::Cell<bool>,
+    }
+
+    impl CopyAddress for Failing {
+        fn copy_address(&self, addr: usize, buf: &mut [u8]) -> std::io::Result<()> {
+            if self.fail.get() {
+                Err(std::io::ErrorKind::InvalidInput.into())
+            } else {
+                LocalProcess.copy_address(addr, buf)
+            }
+        }
+
+        fn get_pointer_width(&self) -> Architecture {
+            LocalProcess.get_pointer_width()
+        }
+    }
+
+    fn read_usize<S: CopyAddress>(source: &S, address: usize) -> usize {
+        let mut bytes = [0_u8; std::mem::size_of::<usize>()];
+        source.copy_address(address, &mut bytes).unwrap();
+        usize::from_ne_bytes(bytes)
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent_ranges() {
+        let spans = merge(vec![
+            (0x3000, 4),
+            (0x1ff8, 0x10),
+            (0x1010, 8),
+            (0x2000, 1),
+            (0x8000, 0),
+            (0x6123, 1),
+        ]);
+        let ranges: Vec<_> = spans.iter().map(|span| (span.start, span.end())).collect();
+        assert_eq!(ranges, [(0x1000, 0x4000), (0x6000, 0x7000)]);
+    }
+
+    #[test]
+    fn coalesces_overlapping_spans() {
+        let span = |start, byte, len| Span {
+            start,
+            bytes: vec![byte; len],
+            valid: true,
+        };
+        let spans = coalesce(vec![
+            span(0x1000, 1, 0x2000),
+            span(0x2000, 2, 0x2000),
+            span(0x2000, 3, 0x1000),
+            span(0x5000, 4, 0x1000),
+        ]);
+        let ranges: Vec<_> = spans.iter().map(|span| (span.start, span.end())).collect();
+        assert_eq!(ranges, [(0x1000, 0x4000), (0x5000, 0x6000)]);
+        assert_eq!(spans[0].bytes[0x1fff], 1);
+        assert_eq!(spans[0].bytes[0x2000], 2);
+    }
+
+    #[test]
+    fn copies_again_where_a_changed_pointer_leads() {
+        // A pointer and two values, each on a page of its own.
+        let stride = 2 * PAGE_SIZE / std::mem::size_of::<usize>();
+        let mut memory = vec![0_usize; 3 * stride];
+        let base = memory.as_mut_ptr();
+        let (pointer, first, second) = unsafe { (base, base.add(stride), base.add(2 * stride)) };
+        unsafe {
+            pointer.write_volatile(first as usize);
+            first.write_volatile(1);
+            second.write_volatile(2);
+        }
+
+        let mut cache = ReadCache::new(LocalProcess);
+        cache.register_chain(vec![pointer as usize, 0], std::mem::size_of::<usize>());
+        cache.refresh().unwrap();
+        assert_eq!(read_usize(&cache, first as usize), 1);
+        assert!(!cache.contains(second as usize, 1));
+
+        unsafe { pointer.write_volatile(second as usize) };
+        cache.refresh().unwrap();
+        assert!(cache.contains(second as usize, std::mem::size_of::<usize>()));
+        assert_eq!(
+            cache.source().get_offset(&[pointer as usize, 0]).unwrap(),
+            second as usize
+        );
+        assert_eq!(
+            cache.get_offset(&[pointer as usize, 0]).unwrap(),
+            second as usize
+        );
+
+        // The value was copied into the snapshot, so changing it is not seen until the next
+        // refresh.
+        unsafe { second.write_volatile(3) };
+        assert_eq!(read_usize(&cache, second as usize), 2);
+        cache.refresh().unwrap();
+        assert_eq!(read_usize(&cache, second as usize), 3);
+        assert_eq!(cache.ticks(), 3);
+        drop(memory);
+    }
+
+    #[test]
+    fn reads_unreadable_spans_from_the_source() {
+        let value = Box::new(7_usize);
+        let address = std::ptr::addr_of!(*value) as usize;
+        let mut cache = ReadCache::new(Failing::default());
+        cache.register(address, std::mem::size_of::<usize>());
+
+        cache.source().fail.set(true);
+        assert!(cache.refresh().is_err());
+        assert!(!cache.contains(address, 1));
+        cache.source().fail.set(false);
+        assert_eq!(read_usize(&cache, address), 7);
+
+        cache.refresh().unwrap();
+        assert!(cache.contains(address, 1));
+        cache.source().fail.set(true);
+        assert_eq!(read_usize(&cache, address), 7);
+    }
+}
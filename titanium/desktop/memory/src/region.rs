@@ -0,0 +1,252 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::ProcessHandle;
+
+/// The access permissions of a [`MemoryRegion`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Permissions {
+    /// The region can be read from.
+    pub read: bool,
+    /// The region can be written to.
+    pub write: bool,
+    /// The region can be executed.
+    pub execute: bool,
+    /// The region is shared with other processes, rather than being copy-on-write.
+    pub shared: bool,
+}
+
+impl Permissions {
+    /// Returns `true` if every permission set in `other` is also set in `self`. The `shared` flag
+    /// is ignored.
+    #[must_use]
+    pub fn contains(self, other: Permissions) -> bool {
+        (self.read || !other.read) && (self.write || !other.write) && (self.execute || !other.execute)
+    }
+}
+
+/// Parses permissions in the `rwxp` format used by `/proc/<pid>/maps`.
+impl FromStr for Permissions {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> std::io::Result<Self> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 4 {
+            return Err(invalid_data(format!("Invalid permission string `{s}`")));
+        }
+        Ok(Self {
+            read: bytes[0] == b'r',
+            write: bytes[1] == b'w',
+            execute: bytes[2] == b'x',
+            shared: bytes[3] == b's',
+        })
+    }
+}
+
+impl std::fmt::Display for Permissions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' },
+            if self.shared { 's' } else { 'p' },
+        )
+    }
+}
+
+/// A contiguous mapping in the address space of a process.
+///
+/// Regions are returned by [`regions`] in ascending address order. On Linux a region is a single
+/// line of `/proc/<pid>/maps`, and can also be parsed from one with [`str::parse`].
+///
+/// [`regions`]: fn.regions.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryRegion {
+    /// The first address of the region.
+    pub start: usize,
+    /// The address one past the end of the region.
+    pub end: usize,
+    /// The access permissions of the region.
+    pub permissions: Permissions,
+    /// The offset into the backing file that the region starts at.
+    pub offset: u64,
+    /// The inode of the backing file, or `0` for anonymous mappings.
+    pub inode: u64,
+    /// The backing file of the region, or a pseudo-path such as `[heap]` or `[stack]`.
+    pub path: Option<PathBuf>,
+}
+
+impl MemoryRegion {
+    /// The size of the region in bytes.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns `true` if `addr` lies inside of the region.
+    #[must_use]
+    pub fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    /// Returns `true` if the region is mapped from a file on disk.
+    #[must_use]
+    pub fn is_file_backed(&self) -> bool {
+        self.inode != 0
+    }
+}
+
+/// Parses a single line of `/proc/<pid>/maps`, for example
+/// `7f2c3e600000-7f2c3e628000 r--p 00000000 08:01 1835053    /usr/lib/libc.so.6`.
+impl FromStr for MemoryRegion {
+    type Err = std::io::Error;
+
+    fn from_str(line: &str) -> std::io::Result<Self> {
+        let mut fields = line.splitn(6, ' ');
+        let mut next = || {
+            fields
+                .next()
+                .ok_or_else(|| invalid_data(format!("Truncated memory map line `{line}`")))
+        };
+        let (start, end) = next()?
+            .split_once('-')
+            .ok_or_else(|| invalid_data(format!("Invalid address range in `{line}`")))?;
+        let start = usize::from_str_radix(start, 16).map_err(invalid_data)?;
+        let end = usize::from_str_radix(end, 16).map_err(invalid_data)?;
+        let permissions = next()?.parse()?;
+        let offset = u64::from_str_radix(next()?, 16).map_err(invalid_data)?;
+        let _device = next()?;
+        let inode = next()?.parse().map_err(invalid_data)?;
+        let path = fields
+            .next()
+            .map(str::trim_start)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from);
+
+        Ok(Self {
+            start,
+            end,
+            permissions,
+            offset,
+            inode,
+            path,
+        })
+    }
+}
+
+/// An iterator over the [`MemoryRegion`]s of a process, created by [`regions`].
+///
+/// The regions are all captured when the iterator is created, so they form a consistent view of
+/// the address space at that point in time.
+///
+/// [`regions`]: fn.regions.html
+#[derive(Debug)]
+pub struct Regions {
+    inner: std::vec::IntoIter<MemoryRegion>,
+}
+
+impl Iterator for Regions {
+    type Item = MemoryRegion;
+
+    fn next(&mut self) -> Option<MemoryRegion> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for Regions {
+    fn next_back(&mut self) -> Option<MemoryRegion> {
+        self.inner.next_back()
+    }
+}
+
+impl ExactSizeIterator for Regions {}
+
+/// List the mapped regions of a process's address space.
+///
+/// On Linux this reads `/proc/<pid>/maps`.
+///
+/// # Errors
+/// Returns an error if the memory map of the process cannot be read, or if region enumeration is
+/// not supported on the current platform.
+pub fn regions(handle: &ProcessHandle) -> std::io::Result<Regions> {
+    Ok(Regions {
+        inner: crate::platform::regions(handle)?.into_iter(),
+    })
+}
+
+fn invalid_data<E>(error: E) -> std::io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_file_backed_line() {
+        let region: MemoryRegion =
+            "7f2c3e600000-7f2c3e628000 r-xp 00001000 08:01 1835053    /usr/lib/libc.so.6"
+                .parse()
+                .unwrap();
+        assert_eq!(region.start, 0x7f2c_3e60_0000);
+        assert_eq!(region.end, 0x7f2c_3e62_8000);
+        assert_eq!(region.size(), 0x28000);
+        assert_eq!(region.permissions.to_string(), "r-xp");
+        assert_eq!(region.offset, 0x1000);
+        assert_eq!(region.inode, 1_835_053);
+        assert_eq!(region.path, Some(PathBuf::from("/usr/lib/libc.so.6")));
+        assert!(region.is_file_backed());
+    }
+
+    #[test]
+    fn parses_anonymous_and_pseudo_lines() {
+        let anonymous: MemoryRegion = "7ffd1000-7ffd3000 rw-p 00000000 00:00 0 ".parse().unwrap();
+        assert_eq!(anonymous.path, None);
+        assert!(!anonymous.is_file_backed());
+
+        let stack: MemoryRegion = "7ffd1000-7ffd3000 rw-s 00000000 00:00 0                  [stack]"
+            .parse()
+            .unwrap();
+        assert_eq!(stack.path, Some(PathBuf::from("[stack]")));
+        assert!(stack.permissions.shared);
+    }
+
+    #[test]
+    fn keeps_spaces_in_paths() {
+        let region: MemoryRegion = "1000-2000 r--p 00000000 08:01 42   /tmp/a b (deleted)"
+            .parse()
+            .unwrap();
+        assert_eq!(region.path, Some(PathBuf::from("/tmp/a b (deleted)")));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "",
+            "1000 r--p 0 08:01 0",
+            "1000-2000 r--p",
+            "1000-zz r--p 0 08:01 0",
+            "1000-2000 rw 0 08:01 0",
+        ] {
+            let error = line.parse::<MemoryRegion>().unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{line}");
+        }
+    }
+
+    #[test]
+    fn permissions_contains() {
+        let rwx: Permissions = "rwxp".parse().unwrap();
+        let rw: Permissions = "rw-s".parse().unwrap();
+        assert!(rwx.contains(rw));
+        assert!(!rw.contains(rwx));
+    }
+}
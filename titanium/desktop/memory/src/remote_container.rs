@@ -0,0 +1,544 @@
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+use crate::{CopyAddress, RemoteField, RemotePtr, RemoteStruct};
+
+/// The most elements a container is allowed to have unless it is changed with `with_max_len`.
+const DEFAULT_MAX_LEN: usize = 0x10000;
+
+/// The error type for [`RemoteVec`], [`RemotePtrArray`] and [`RemoteList`]
+///
+/// [`RemoteVec`]: struct.RemoteVec.html
+/// [`RemotePtrArray`]: struct.RemotePtrArray.html
+/// [`RemoteList`]: struct.RemoteList.html
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteContainerError {
+    /// The container has more elements than its limit
+    #[error("Container at {address:#x} has {len} elements, more than the limit of {max_len}")]
+    TooLong {
+        /// The address of the container
+        address: usize,
+        /// The number of elements it claims to have, or the number read so far for a list
+        len: usize,
+        /// The limit that was exceeded
+        max_len: usize,
+    },
+    /// The header of the container does not make sense, so the address probably does not point
+    /// to a container of that layout
+    #[error("Invalid container at {address:#x}: {reason}")]
+    InvalidLayout {
+        /// The address of the container
+        address: usize,
+        /// What was wrong with it
+        reason: &'static str,
+    },
+    /// A linked list leads back to a node that was already read
+    #[error("Linked list loops back to the node at {address:#x}")]
+    Cycle {
+        /// The address of the node that was reached twice
+        address: usize,
+    },
+    /// Reading memory failed
+    #[error("Reading process memory failed")]
+    Io(#[from] std::io::Error),
+}
+
+/// The header of a [`RemoteVec`].
+///
+/// [`RemoteVec`]: struct.RemoteVec.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VecHeader {
+    /// The address of the first element.
+    pub data: usize,
+    /// The number of elements.
+    pub len: usize,
+    /// The number of elements that fit before the vector has to reallocate.
+    pub capacity: usize,
+}
+
+/// A C++ `std::vector<T>` in another process, such as a `std::vector<Entity*>` of
+/// `RemotePtr<Entity>`.
+///
+/// Both libstdc++ and Microsoft's STL store a vector as three pointers: the first element, the
+/// end of the elements, and the end of the capacity. The header is checked before anything else
+/// is read, so a stale or wrong address returns an error instead of reading garbage, and the
+/// elements are read with a single [`CopyAddress::copy_address`].
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, RemotePtr, RemoteVec};
+/// # let entity_list = 0x1234;
+/// let handle = get_handle("game").unwrap();
+/// let entities: RemoteVec<RemotePtr<u8>> = RemoteVec::new(entity_list).with_max_len(1024);
+/// for entity in entities.read(&handle).unwrap() {
+///     println!("{:#x}", entity.address());
+/// }
+/// ```
+///
+/// [`CopyAddress::copy_address`]: trait.CopyAddress.html#tymethod.copy_address
+#[derive(Debug)]
+pub struct RemoteVec<T> {
+    address: usize,
+    max_len: usize,
+    max_capacity: Option<usize>,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: RemoteField> RemoteVec<T> {
+    /// A vector whose header is at `address`.
+    #[must_use]
+    pub fn new(address: usize) -> Self {
+        Self {
+            address,
+            max_len: DEFAULT_MAX_LEN,
+            max_capacity: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set the most elements the vector may have before it is treated as invalid. Defaults to
+    /// `65536`.
+    #[must_use]
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Set the largest capacity the vector may have before it is treated as invalid. Defaults to
+    /// twice the limit on its length, as a vector that grows by doubling can have up to that
+    /// much room.
+    #[must_use]
+    pub fn with_max_capacity(mut self, max_capacity: usize) -> Self {
+        self.max_capacity = Some(max_capacity);
+        self
+    }
+
+    /// The address of the header of the vector.
+    #[must_use]
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Read and validate the header of the vector.
+    ///
+    /// # Errors
+    /// Returns an error if the header cannot be read, if its pointers are out of order or do not
+    /// line up with the size of `T`, if the vector has more elements than the limit, or if its
+    /// capacity is larger than the limit on it.
+    pub fn header<H: CopyAddress + ?Sized>(
+        &self,
+        handle: &H,
+    ) -> Result<VecHeader, RemoteContainerError> {
+        let arch = handle.get_pointer_width();
+        let word = arch.pointer_size();
+        let mut header = vec![0_u8; 3 * word];
+        handle.copy_address(self.address, &mut header)?;
+        let first = arch.pointer_from_ne_bytes(&header[..word]);
+        let last = arch.pointer_from_ne_bytes(&header[word..2 * word]);
+        let end = arch.pointer_from_ne_bytes(&header[2 * word..]);
+
+        let invalid = |reason| RemoteContainerError::InvalidLayout {
+            address: self.address,
+            reason,
+        };
+        if first > last || last > end {
+            return Err(invalid("pointers are out of order"));
+        }
+        if first == 0 && end != 0 {
+            return Err(invalid("null pointer to the elements"));
+        }
+        let size = T::remote_size(arch);
+        if size == 0 {
+            return Err(invalid("elements have no size"));
+        }
+        let (len, capacity) = ((last - first) / size, (end - first) / size);
+        if len * size != last - first || capacity * size != end - first {
+            return Err(invalid("size is not a multiple of the element size"));
+        }
+        if len > self.max_len {
+            return Err(RemoteContainerError::TooLong {
+                address: self.address,
+                len,
+                max_len: self.max_len,
+            });
+        }
+        let max_capacity = self
+            .max_capacity
+            .unwrap_or_else(|| self.max_len.saturating_mul(2));
+        if capacity > max_capacity {
+            return Err(invalid("capacity is larger than the limit"));
+        }
+        Ok(VecHeader {
+            data: first,
+            len,
+            capacity,
+        })
+    }
+
+    /// The number of elements in the vector.
+    ///
+    /// # Errors
+    /// Returns an error if the header is invalid. See [`RemoteVec::header`].
+    pub fn len<H: CopyAddress + ?Sized>(&self, handle: &H) -> Result<usize, RemoteContainerError> {
+        self.header(handle).map(|header| header.len)
+    }
+
+    /// Returns `true` if the vector has no elements.
+    ///
+    /// # Errors
+    /// Returns an error if the header is invalid. See [`RemoteVec::header`].
+    pub fn is_empty<H: CopyAddress + ?Sized>(
+        &self,
+        handle: &H,
+    ) -> Result<bool, RemoteContainerError> {
+        self.len(handle).map(|len| len == 0)
+    }
+
+    /// Read every element of the vector.
+    ///
+    /// # Errors
+    /// Returns an error if the header is invalid or the elements cannot be read.
+    pub fn read<H: CopyAddress + ?Sized>(&self, handle: &H) -> Result<Vec<T>, RemoteContainerError> {
+        let header = self.header(handle)?;
+        Ok(read_elements(handle, header.data, header.len)?)
+    }
+}
+
+/// Read `len` consecutive elements at `address` with one copy.
+fn read_elements<H: CopyAddress + ?Sized, T: RemoteField>(
+    handle: &H,
+    address: usize,
+    len: usize,
+) -> std::io::Result<Vec<T>> {
+    let arch = handle.get_pointer_width();
+    let size = T::remote_size(arch);
+    let mut bytes = vec![0_u8; size * len];
+    if len != 0 {
+        handle.copy_address(address, &mut bytes)?;
+    }
+    Ok(bytes
+        .chunks_exact(size.max(1))
+        .take(len)
+        .map(|element| T::from_bytes(element, arch))
+        .collect())
+}
+
+/// A fixed-size array of pointers to `T` in another process, such as an entity table where empty
+/// slots are null.
+///
+/// The pointers are read with a single [`CopyAddress::copy_address`].
+///
+/// [`CopyAddress::copy_address`]: trait.CopyAddress.html#tymethod.copy_address
+#[derive(Debug)]
+pub struct RemotePtrArray<T> {
+    address: usize,
+    len: usize,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> RemotePtrArray<T> {
+    /// An array of `len` pointers starting at `address`.
+    #[must_use]
+    pub fn new(address: usize, len: usize) -> Self {
+        Self {
+            address,
+            len,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The address of the first pointer.
+    #[must_use]
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// The number of pointers in the array.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the array has no pointers.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Read every pointer in the array, including null ones.
+    ///
+    /// # Errors
+    /// Returns an error if the array cannot be read.
+    pub fn read<H: CopyAddress + ?Sized>(
+        &self,
+        handle: &H,
+    ) -> Result<Vec<RemotePtr<T>>, RemoteContainerError> {
+        Ok(read_elements(handle, self.address, self.len)?)
+    }
+}
+
+impl<T: RemoteStruct> RemotePtrArray<T> {
+    /// Read the value behind every pointer that is not null, along with its index in the array.
+    /// The values are read together with [`CopyAddress::copy_addresses`].
+    ///
+    /// # Errors
+    /// Returns an error if the array or any of the values cannot be read.
+    ///
+    /// [`CopyAddress::copy_addresses`]: trait.CopyAddress.html#method.copy_addresses
+    pub fn read_targets<H: CopyAddress + ?Sized>(
+        &self,
+        handle: &H,
+    ) -> Result<Vec<(usize, T)>, RemoteContainerError> {
+        let arch = handle.get_pointer_width();
+        let size = T::remote_size(arch);
+        let pointers: Vec<_> = self
+            .read(handle)?
+            .into_iter()
+            .enumerate()
+            .filter(|(_, pointer)| !pointer.is_null())
+            .collect();
+        let mut buffers = vec![vec![0_u8; size]; pointers.len()];
+        let mut entries: Vec<_> = pointers
+            .iter()
+            .zip(&mut buffers)
+            .map(|((_, pointer), buffer)| (pointer.address(), buffer.as_mut_slice()))
+            .collect();
+        for result in handle.copy_addresses(&mut entries) {
+            result?;
+        }
+        Ok(pointers
+            .iter()
+            .zip(&buffers)
+            .map(|((index, _), buffer)| (*index, T::from_bytes(buffer, arch)))
+            .collect())
+    }
+}
+
+/// An intrusive singly linked list in another process, where every node is a `T` that holds a
+/// pointer to the next node at `next_offset`.
+///
+/// The list ends at a null pointer, or at a sentinel node for circular lists such as
+/// `std::list`. Each node and its next pointer are read with a single
+/// [`CopyAddress::copy_address`], and a list that loops back on itself or grows past its limit
+/// returns an error instead of running forever.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, RemoteList};
+/// # let first_entity = 0x1234;
+/// let handle = get_handle("game").unwrap();
+/// // Every entity has a pointer to the next one at offset 0x8
+/// let entities: RemoteList<u32> = RemoteList::new(first_entity, 0x8);
+/// for (address, id) in entities.read(&handle).unwrap() {
+///     println!("{address:#x}: {id}");
+/// }
+/// ```
+///
+/// [`CopyAddress::copy_address`]: trait.CopyAddress.html#tymethod.copy_address
+#[derive(Debug)]
+pub struct RemoteList<T> {
+    first: usize,
+    next_offset: usize,
+    sentinel: Option<usize>,
+    max_len: usize,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: RemoteField> RemoteList<T> {
+    /// A list whose first node is at `first`, which may be `0` for an empty list.
+    #[must_use]
+    pub fn new(first: usize, next_offset: usize) -> Self {
+        Self {
+            first,
+            next_offset,
+            sentinel: None,
+            max_len: DEFAULT_MAX_LEN,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// A list whose first node is pointed to by the pointer at `head`.
+    ///
+    /// # Errors
+    /// Returns an error if the pointer cannot be read.
+    pub fn from_head<H: CopyAddress + ?Sized>(
+        handle: &H,
+        head: usize,
+        next_offset: usize,
+    ) -> std::io::Result<Self> {
+        let arch = handle.get_pointer_width();
+        let mut pointer = vec![0_u8; arch.pointer_size()];
+        handle.copy_address(head, &mut pointer)?;
+        Ok(Self::new(arch.pointer_from_ne_bytes(&pointer), next_offset))
+    }
+
+    /// Stop at the node at `sentinel` instead of at a null pointer, without reading it.
+    #[must_use]
+    pub fn with_sentinel(mut self, sentinel: usize) -> Self {
+        self.sentinel = Some(sentinel);
+        self
+    }
+
+    /// Set the most nodes the list may have before it is treated as invalid. Defaults to
+    /// `65536`.
+    #[must_use]
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Read every node of the list, along with its address.
+    ///
+    /// # Errors
+    /// Returns an error if a node cannot be read, if the list loops back on itself, or if it
+    /// has more nodes than the limit.
+    pub fn read<H: CopyAddress + ?Sized>(
+        &self,
+        handle: &H,
+    ) -> Result<Vec<(usize, T)>, RemoteContainerError> {
+        let arch = handle.get_pointer_width();
+        let word = arch.pointer_size();
+        let mut node = vec![0_u8; T::remote_size(arch).max(self.next_offset + word)];
+        let mut visited = HashSet::new();
+        let mut nodes = Vec::new();
+        let mut address = self.first;
+        while address != 0 && Some(address) != self.sentinel {
+            if !visited.insert(address) {
+                return Err(RemoteContainerError::Cycle { address });
+            }
+            if nodes.len() == self.max_len {
+                return Err(RemoteContainerError::TooLong {
+                    address: self.first,
+                    len: nodes.len() + 1,
+                    max_len: self.max_len,
+                });
+            }
+            handle.copy_address(address, &mut node)?;
+            nodes.push((address, T::from_bytes(&node, arch)));
+            address = arch.pointer_from_ne_bytes(&node[self.next_offset..self.next_offset + word]);
+        }
+        Ok(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LocalProcess;
+
+    /// The header of a vector over `elements`, with room for `capacity` of them.
+    fn header(elements: &[u32], capacity: usize) -> [usize; 3] {
+        let first = elements.as_ptr() as usize;
+        [first, first + elements.len() * 4, first + capacity * 4]
+    }
+
+    #[test]
+    fn reads_vectors_within_their_limits() {
+        let elements = [1_u32, 2, 3, 4];
+        let header = header(&elements, 6);
+        let vec: RemoteVec<u32> = RemoteVec::new(header.as_ptr() as usize);
+        assert_eq!(
+            vec.header(&LocalProcess).unwrap(),
+            VecHeader {
+                data: elements.as_ptr() as usize,
+                len: 4,
+                capacity: 6,
+            }
+        );
+        assert_eq!(vec.read(&LocalProcess).unwrap(), elements);
+
+        let short = RemoteVec::<u32>::new(header.as_ptr() as usize).with_max_len(3);
+        assert!(matches!(
+            short.read(&LocalProcess),
+            Err(RemoteContainerError::TooLong {
+                len: 4,
+                max_len: 3,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn limits_the_capacity_of_vectors() {
+        let elements = [1_u32, 2];
+        let roomy = header(&elements, 9);
+        let vec = RemoteVec::<u32>::new(roomy.as_ptr() as usize).with_max_len(4);
+        // The capacity defaults to twice the length limit.
+        assert!(matches!(
+            vec.header(&LocalProcess),
+            Err(RemoteContainerError::InvalidLayout { reason, .. })
+                if reason == "capacity is larger than the limit"
+        ));
+        let vec = vec.with_max_capacity(9);
+        assert_eq!(vec.len(&LocalProcess).unwrap(), 2);
+        let fits = header(&elements, 8);
+        let vec = RemoteVec::<u32>::new(fits.as_ptr() as usize).with_max_len(4);
+        assert_eq!(vec.read(&LocalProcess).unwrap(), elements);
+    }
+
+    #[test]
+    fn rejects_invalid_vector_headers() {
+        let elements = [1_u32, 2];
+        let first = elements.as_ptr() as usize;
+        for header in [
+            [first + 8, first, first + 8],
+            [first, first + 6, first + 8],
+            [0, 0, 8],
+        ] {
+            let vec = RemoteVec::<u32>::new(header.as_ptr() as usize);
+            assert!(matches!(
+                vec.read(&LocalProcess),
+                Err(RemoteContainerError::InvalidLayout { .. })
+            ));
+        }
+        let empty = [0_usize; 3];
+        assert!(RemoteVec::<u32>::new(empty.as_ptr() as usize)
+            .is_empty(&LocalProcess)
+            .unwrap());
+    }
+
+    #[test]
+    fn reads_pointer_arrays_with_null_slots() {
+        let pointers = [0x1000_usize, 0, 0x3000];
+        let array = RemotePtrArray::<u32>::new(pointers.as_ptr() as usize, 3);
+        let read: Vec<_> = array
+            .read(&LocalProcess)
+            .unwrap()
+            .iter()
+            .map(RemotePtr::address)
+            .collect();
+        assert_eq!(read, pointers);
+    }
+
+    #[test]
+    fn stops_lists_at_cycles_sentinels_and_limits() {
+        // Nodes of a value and a pointer to the next node.
+        let mut nodes = [[1_u64, 0], [2, 0], [3, 0]];
+        let address = |nodes: &[[u64; 2]; 3], index: usize| nodes.as_ptr() as usize + index * 16;
+        nodes[0][1] = address(&nodes, 1) as u64;
+        nodes[1][1] = address(&nodes, 2) as u64;
+        let first = address(&nodes, 0);
+        let values = |list: &RemoteList<u64>| -> Result<Vec<u64>, RemoteContainerError> {
+            Ok(list
+                .read(&LocalProcess)?
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect())
+        };
+        assert_eq!(values(&RemoteList::new(first, 8)).unwrap(), [1, 2, 3]);
+        let sentinel = RemoteList::new(first, 8).with_sentinel(address(&nodes, 2));
+        assert_eq!(values(&sentinel).unwrap(), [1, 2]);
+        assert!(matches!(
+            values(&RemoteList::new(first, 8).with_max_len(2)),
+            Err(RemoteContainerError::TooLong {
+                len: 3,
+                max_len: 2,
+                ..
+            })
+        ));
+
+        nodes[2][1] = address(&nodes, 1) as u64;
+        assert!(matches!(
+            values(&RemoteList::new(first, 8)),
+            Err(RemoteContainerError::Cycle { address: cycle }) if cycle == address(&nodes, 1)
+        ));
+    }
+}
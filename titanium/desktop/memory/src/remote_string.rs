@@ -0,0 +1,583 @@
+use crate::CopyAddress;
+
+/// How many bytes of a NUL-terminated string are read at a time while looking for the end.
+const CHUNK_SIZE: usize = 256;
+const PAGE_SIZE: usize = 0x1000;
+
+/// The error type for the remote string readers, such as [`read_c_string`]
+///
+/// [`read_c_string`]: fn.read_c_string.html
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteStringError {
+    /// The string is longer than [`StringOptions::max_len`], or no terminator was found within it
+    #[error("String at {address:#x} is longer than {max_len} code units")]
+    TooLong {
+        /// The address of the string
+        address: usize,
+        /// The limit that was exceeded
+        max_len: usize,
+    },
+    /// The string is not valid in its encoding, and lossy decoding was not enabled
+    #[error("String at {address:#x} is not valid {encoding:?}")]
+    InvalidEncoding {
+        /// The address of the characters of the string
+        address: usize,
+        /// The encoding the string was decoded as
+        encoding: TextEncoding,
+    },
+    /// The fields of the string object do not make sense, such as a length greater than its
+    /// capacity, so the address probably does not point to a string of that layout
+    #[error("Invalid string object at {address:#x}: {reason}")]
+    InvalidLayout {
+        /// The address of the string object
+        address: usize,
+        /// What was wrong with it
+        reason: &'static str,
+    },
+    /// Reading memory failed
+    #[error("Reading process memory failed")]
+    Io(#[from] std::io::Error),
+}
+
+/// The encoding of the characters of a string in another process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextEncoding {
+    /// UTF-8, with one-byte code units. This is also correct for plain ASCII.
+    Utf8,
+    /// ISO-8859-1, where every byte is the character with the same value. Decoding never fails.
+    Latin1,
+    /// Little-endian UTF-16, with two-byte code units, as used by `wchar_t` on Windows and Wine.
+    Utf16Le,
+}
+
+impl TextEncoding {
+    /// The number of bytes in a code unit.
+    #[must_use]
+    pub fn unit_size(self) -> usize {
+        match self {
+            TextEncoding::Utf8 | TextEncoding::Latin1 => 1,
+            TextEncoding::Utf16Le => 2,
+        }
+    }
+
+    /// Decode `bytes`, which were read from `address`, replacing invalid sequences with
+    /// `U+FFFD` if `lossy` is set.
+    fn decode(self, bytes: &[u8], address: usize, lossy: bool) -> Result<String, RemoteStringError> {
+        let invalid = || RemoteStringError::InvalidEncoding {
+            address,
+            encoding: self,
+        };
+        match self {
+            TextEncoding::Utf8 if lossy => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            TextEncoding::Utf8 => String::from_utf8(bytes.to_vec()).map_err(|_| invalid()),
+            TextEncoding::Latin1 => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+            TextEncoding::Utf16Le => {
+                let units = bytes
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]));
+                if lossy {
+                    Ok(char::decode_utf16(units)
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .collect())
+                } else {
+                    char::decode_utf16(units)
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| invalid())
+                }
+            }
+        }
+    }
+}
+
+/// Options for the remote string readers.
+#[derive(Clone, Copy, Debug)]
+pub struct StringOptions {
+    /// The most code units (bytes, or pairs of bytes for UTF-16) to read, not counting a
+    /// terminator. Longer strings are an error, which stops a bad pointer or a garbage length
+    /// from reading megabytes of memory. Defaults to `4096`.
+    pub max_len: usize,
+    /// Replace invalid sequences with `U+FFFD` instead of returning an error. Defaults to
+    /// `false`.
+    pub lossy: bool,
+}
+
+impl Default for StringOptions {
+    fn default() -> Self {
+        Self {
+            max_len: 4096,
+            lossy: false,
+        }
+    }
+}
+
+/// Read `len` code units at `address` and decode them.
+fn read_units<T: CopyAddress + ?Sized>(
+    handle: &T,
+    address: usize,
+    len: usize,
+    encoding: TextEncoding,
+    options: StringOptions,
+) -> Result<String, RemoteStringError> {
+    if len > options.max_len {
+        return Err(RemoteStringError::TooLong {
+            address,
+            max_len: options.max_len,
+        });
+    }
+    let mut bytes = vec![0_u8; len * encoding.unit_size()];
+    handle.copy_address(address, &mut bytes)?;
+    encoding.decode(&bytes, address, options.lossy)
+}
+
+/// Read a pointer-sized value from the start of `bytes`.
+fn read_word<T: CopyAddress + ?Sized>(handle: &T, bytes: &[u8]) -> usize {
+    handle.get_pointer_width().pointer_from_ne_bytes(bytes)
+}
+
+/// Read a NUL-terminated string, such as a C `char*` or `wchar_t*` on Windows.
+///
+/// The string is read in small chunks that never cross into the next page, so a short string at
+/// the end of a mapping can be read.
+///
+/// # Errors
+/// Returns an error if memory cannot be read, if no terminator is found within
+/// [`StringOptions::max_len`] code units, or if the string is not valid in `encoding` and
+/// `lossy` is not set.
+///
+/// [`StringOptions::max_len`]: struct.StringOptions.html#structfield.max_len
+pub fn read_c_string<T: CopyAddress + ?Sized>(
+    handle: &T,
+    address: usize,
+    encoding: TextEncoding,
+    options: StringOptions,
+) -> Result<String, RemoteStringError> {
+    let unit = encoding.unit_size();
+    let limit = options.max_len.saturating_add(1).saturating_mul(unit);
+    let mut bytes = Vec::new();
+    let mut chunk = [0_u8; CHUNK_SIZE];
+    while bytes.len() < limit {
+        let current = address.wrapping_add(bytes.len());
+        let len = CHUNK_SIZE
+            .min(PAGE_SIZE - current % PAGE_SIZE)
+            .min(limit - bytes.len());
+        handle.copy_address(current, &mut chunk[..len])?;
+        bytes.extend_from_slice(&chunk[..len]);
+        // Only whole, aligned code units can be the terminator.
+        let terminator = bytes
+            .chunks_exact(unit)
+            .position(|code_unit| code_unit.iter().all(|&byte| byte == 0));
+        if let Some(terminator) = terminator {
+            bytes.truncate(terminator * unit);
+            return encoding.decode(&bytes, address, options.lossy);
+        }
+    }
+    Err(RemoteStringError::TooLong {
+        address,
+        max_len: options.max_len,
+    })
+}
+
+/// The type of the length in front of a length-prefixed string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LengthPrefix {
+    /// A `u8`, as in Pascal short strings.
+    U8,
+    /// A little-endian `u16`.
+    U16,
+    /// A little-endian `u32`, as in many engines and network formats.
+    U32,
+    /// A little-endian `u64`.
+    U64,
+}
+
+impl LengthPrefix {
+    /// The number of bytes in the length.
+    #[must_use]
+    pub fn size(self) -> usize {
+        match self {
+            LengthPrefix::U8 => 1,
+            LengthPrefix::U16 => 2,
+            LengthPrefix::U32 => 4,
+            LengthPrefix::U64 => 8,
+        }
+    }
+}
+
+/// Read a string whose length, in code units, is stored directly in front of its characters.
+///
+/// # Errors
+/// Returns an error if memory cannot be read, if the length is more than
+/// [`StringOptions::max_len`], or if the string is not valid in `encoding` and `lossy` is not
+/// set.
+///
+/// [`StringOptions::max_len`]: struct.StringOptions.html#structfield.max_len
+pub fn read_prefixed_string<T: CopyAddress + ?Sized>(
+    handle: &T,
+    address: usize,
+    prefix: LengthPrefix,
+    encoding: TextEncoding,
+    options: StringOptions,
+) -> Result<String, RemoteStringError> {
+    let mut length = [0_u8; 8];
+    handle.copy_address(address, &mut length[..prefix.size()])?;
+    let len = usize::try_from(u64::from_le_bytes(length)).unwrap_or(usize::MAX);
+    read_units(
+        handle,
+        address.wrapping_add(prefix.size()),
+        len,
+        encoding,
+        options,
+    )
+}
+
+/// The standard library a C++ `std::string` was compiled with, which decides its layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StdStringLayout {
+    /// GCC's libstdc++ with the C++11 ABI: a pointer to the characters, the length, and then a
+    /// 16-byte buffer for short strings that shares its space with the capacity.
+    Libstdcxx,
+    /// Microsoft's STL: a 16-byte buffer for short strings that shares its space with the
+    /// pointer to the characters, then the length and the capacity.
+    Msvc,
+}
+
+/// The size of the buffer that short strings are stored in, in both layouts.
+const SSO_BUFFER_SIZE: usize = 16;
+
+/// Read a C++ `std::string`, detecting whether it is stored inline by the small string
+/// optimization or on the heap.
+///
+/// `encoding` selects the character type, so [`TextEncoding::Utf16Le`] reads a `std::u16string`,
+/// or a `std::wstring` with [`StdStringLayout::Msvc`]. The pointer width of the process is used,
+/// so 32-bit targets are read correctly.
+///
+/// # Errors
+/// Returns an error if memory cannot be read, if the length is more than
+/// [`StringOptions::max_len`], if the string object is not consistent with `layout`, or if the
+/// string is not valid in `encoding` and `lossy` is not set.
+///
+/// [`TextEncoding::Utf16Le`]: enum.TextEncoding.html#variant.Utf16Le
+/// [`StdStringLayout::Msvc`]: enum.StdStringLayout.html#variant.Msvc
+/// [`StringOptions::max_len`]: struct.StringOptions.html#structfield.max_len
+pub fn read_std_string<T: CopyAddress + ?Sized>(
+    handle: &T,
+    address: usize,
+    layout: StdStringLayout,
+    encoding: TextEncoding,
+    options: StringOptions,
+) -> Result<String, RemoteStringError> {
+    let word = handle.get_pointer_width().pointer_size();
+    let mut object = vec![0_u8; SSO_BUFFER_SIZE + 2 * word];
+    handle.copy_address(address, &mut object)?;
+    let sso_capacity = SSO_BUFFER_SIZE / encoding.unit_size() - 1;
+    let invalid = |reason| RemoteStringError::InvalidLayout { address, reason };
+
+    let (data, len) = match layout {
+        StdStringLayout::Libstdcxx => {
+            let data = read_word(handle, &object[..word]);
+            let len = read_word(handle, &object[word..2 * word]);
+            let buffer = address.wrapping_add(2 * word);
+            if data == buffer {
+                if len > sso_capacity {
+                    return Err(invalid("inline string is longer than its buffer"));
+                }
+            } else if read_word(handle, &object[2 * word..3 * word]) < len {
+                return Err(invalid("length is greater than capacity"));
+            }
+            (data, len)
+        }
+        StdStringLayout::Msvc => {
+            let len = read_word(handle, &object[SSO_BUFFER_SIZE..SSO_BUFFER_SIZE + word]);
+            let capacity = read_word(handle, &object[SSO_BUFFER_SIZE + word..]);
+            if capacity < len {
+                return Err(invalid("length is greater than capacity"));
+            }
+            if capacity <= sso_capacity {
+                (address, len)
+            } else {
+                (read_word(handle, &object[..word]), len)
+            }
+        }
+    };
+    if data == 0 {
+        return Err(invalid("null pointer to the characters"));
+    }
+    read_units(handle, data, len, encoding, options)
+}
+
+/// Where the capacity, pointer and length of a Rust `String` or `Vec` are, as indices of the
+/// three words it is made of.
+///
+/// Rust makes no promise about the order of these fields, and it has changed between compiler
+/// versions. [`RustVecLayout::default`] is the order current versions of rustc use, and
+/// [`RustVecLayout::new`] describes any other.
+///
+/// [`RustVecLayout::default`]: struct.RustVecLayout.html#method.default
+/// [`RustVecLayout::new`]: struct.RustVecLayout.html#method.new
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RustVecLayout {
+    capacity: usize,
+    pointer: usize,
+    len: usize,
+}
+
+impl RustVecLayout {
+    /// A layout with the capacity, pointer and length at the given word indices, or `None` if
+    /// they are not 0, 1 and 2 in some order.
+    #[must_use]
+    pub fn new(capacity: usize, pointer: usize, len: usize) -> Option<Self> {
+        let mut indices = [capacity, pointer, len];
+        indices.sort_unstable();
+        (indices == [0, 1, 2]).then_some(Self {
+            capacity,
+            pointer,
+            len,
+        })
+    }
+}
+
+/// The capacity, then the pointer, then the length, which is the order current versions of rustc
+/// use.
+impl Default for RustVecLayout {
+    fn default() -> Self {
+        Self {
+            capacity: 0,
+            pointer: 1,
+            len: 2,
+        }
+    }
+}
+
+/// Read a Rust `String`, or any `Vec<u8>` of UTF-8, whose fields are in the order given by
+/// `layout`.
+///
+/// The layout is assumed rather than detected, so a `String` from a compiler that orders its
+/// fields differently is misread, usually failing with an invalid layout or an unreadable
+/// pointer. Pass
+/// `RustVecLayout::default()` for programs built with a recent rustc.
+///
+/// # Errors
+/// Returns an error if memory cannot be read, if the length is more than
+/// [`StringOptions::max_len`], if the string object is not consistent, or if the string is not
+/// valid UTF-8 and `lossy` is not set.
+///
+/// [`StringOptions::max_len`]: struct.StringOptions.html#structfield.max_len
+pub fn read_rust_string<T: CopyAddress + ?Sized>(
+    handle: &T,
+    address: usize,
+    layout: RustVecLayout,
+    options: StringOptions,
+) -> Result<String, RemoteStringError> {
+    let word = handle.get_pointer_width().pointer_size();
+    let mut object = vec![0_u8; 3 * word];
+    handle.copy_address(address, &mut object)?;
+    let field = |index: usize| read_word(handle, &object[index * word..(index + 1) * word]);
+    let (capacity, data, len) = (field(layout.capacity), field(layout.pointer), field(layout.len));
+    if capacity < len {
+        return Err(RemoteStringError::InvalidLayout {
+            address,
+            reason: "length is greater than capacity",
+        });
+    }
+    if len == 0 {
+        return Ok(String::new());
+    }
+    if data == 0 {
+        return Err(RemoteStringError::InvalidLayout {
+            address,
+            reason: "null pointer to the characters",
+        });
+    }
+    read_units(handle, data, len, TextEncoding::Utf8, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LocalProcess;
+
+    const WORD: usize = std::mem::size_of::<usize>();
+
+    /// A string object laid out as `words`, followed by room for a short string.
+    #[repr(C)]
+    struct Object {
+        words: [usize; 4],
+    }
+
+    impl Object {
+        fn address(&self) -> usize {
+            std::ptr::addr_of!(self.words) as usize
+        }
+
+        fn set_bytes(&mut self, offset: usize, bytes: &[u8]) {
+            let words = std::ptr::addr_of_mut!(self.words).cast::<u8>();
+            unsafe {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), words.add(offset), bytes.len())
+            };
+        }
+    }
+
+    fn read_gcc(object: &Object) -> Result<String, RemoteStringError> {
+        read_std_string(
+            &LocalProcess,
+            object.address(),
+            StdStringLayout::Libstdcxx,
+            TextEncoding::Utf8,
+            StringOptions::default(),
+        )
+    }
+
+    #[test]
+    fn reads_inline_and_heap_std_strings() {
+        let mut inline = Object { words: [0; 4] };
+        inline.words[0] = inline.address() + 2 * WORD;
+        inline.words[1] = 5;
+        inline.set_bytes(2 * WORD, b"short\0");
+        assert_eq!(read_gcc(&inline).unwrap(), "short");
+
+        let heap = String::from("a string too long for the buffer");
+        let object = Object {
+            words: [heap.as_ptr() as usize, heap.len(), heap.len(), 0],
+        };
+        assert_eq!(read_gcc(&object).unwrap(), heap);
+
+        let mut msvc = Object {
+            words: [0, 0, 3, SSO_BUFFER_SIZE - 1],
+        };
+        msvc.set_bytes(0, b"abc\0");
+        let read = |object: &Object| {
+            read_std_string(
+                &LocalProcess,
+                object.address(),
+                StdStringLayout::Msvc,
+                TextEncoding::Utf8,
+                StringOptions::default(),
+            )
+        };
+        assert_eq!(read(&msvc).unwrap(), "abc");
+        let msvc_heap = Object {
+            words: [heap.as_ptr() as usize, 0, heap.len(), heap.len() + 8],
+        };
+        assert_eq!(read(&msvc_heap).unwrap(), heap);
+    }
+
+    #[test]
+    fn rejects_inconsistent_std_strings() {
+        let mut inline = Object { words: [0; 4] };
+        inline.words[0] = inline.address() + 2 * WORD;
+        inline.words[1] = SSO_BUFFER_SIZE;
+        assert!(matches!(
+            read_gcc(&inline),
+            Err(RemoteStringError::InvalidLayout { .. })
+        ));
+
+        let heap = "heap";
+        let object = Object {
+            words: [heap.as_ptr() as usize, 4, 3, 0],
+        };
+        assert!(matches!(
+            read_gcc(&object),
+            Err(RemoteStringError::InvalidLayout { .. })
+        ));
+        let null = Object {
+            words: [0, 4, 4, 0],
+        };
+        assert!(matches!(
+            read_gcc(&null),
+            Err(RemoteStringError::InvalidLayout { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_c_strings_up_to_the_end_of_a_mapping() {
+        let page = PAGE_SIZE;
+        let mapping = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                2 * page,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(mapping, libc::MAP_FAILED);
+        let guard = mapping as usize + page;
+        assert_eq!(
+            unsafe { libc::mprotect(guard as *mut _, page, libc::PROT_NONE) },
+            0
+        );
+        let address = guard - 4;
+        let text = c"end".to_bytes_with_nul();
+        unsafe { std::ptr::copy_nonoverlapping(text.as_ptr(), address as *mut u8, text.len()) };
+
+        let options = StringOptions::default();
+        let read = read_c_string(&LocalProcess, address, TextEncoding::Utf8, options);
+        let short = StringOptions {
+            max_len: 2,
+            ..options
+        };
+        let too_long = read_c_string(&LocalProcess, address, TextEncoding::Utf8, short);
+        unsafe { libc::munmap(mapping, 2 * page) };
+        assert_eq!(read.unwrap(), "end");
+        assert!(matches!(
+            too_long,
+            Err(RemoteStringError::TooLong { max_len: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn bounds_and_decodes_prefixed_strings() {
+        let bytes = [3_u8, 0, b'h', 0, 0xe9, 0, b'y', 0];
+        let address = bytes.as_ptr() as usize;
+        let read = |max_len| {
+            read_prefixed_string(
+                &LocalProcess,
+                address,
+                LengthPrefix::U16,
+                TextEncoding::Utf16Le,
+                StringOptions {
+                    max_len,
+                    lossy: false,
+                },
+            )
+        };
+        assert_eq!(read(3).unwrap(), "h\u{e9}y");
+        assert!(matches!(
+            read(2),
+            Err(RemoteStringError::TooLong { max_len: 2, .. })
+        ));
+
+        let invalid = [2_u8, b'a', 0xff];
+        let read = |lossy| {
+            read_prefixed_string(
+                &LocalProcess,
+                invalid.as_ptr() as usize,
+                LengthPrefix::U8,
+                TextEncoding::Utf8,
+                StringOptions { max_len: 16, lossy },
+            )
+        };
+        assert!(matches!(
+            read(false),
+            Err(RemoteStringError::InvalidEncoding { .. })
+        ));
+        assert_eq!(read(true).unwrap(), "a\u{fffd}");
+    }
+
+    #[test]
+    fn reads_rust_strings_in_any_order() {
+        let text = String::from("rusty");
+        let words = [text.len(), text.as_ptr() as usize, text.capacity()];
+        let layout = RustVecLayout::new(2, 1, 0).unwrap();
+        let read = read_rust_string(
+            &LocalProcess,
+            words.as_ptr() as usize,
+            layout,
+            StringOptions::default(),
+        );
+        assert_eq!(read.unwrap(), "rusty");
+        assert!(RustVecLayout::new(0, 0, 1).is_none());
+    }
+}
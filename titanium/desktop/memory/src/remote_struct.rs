@@ -0,0 +1,358 @@
+use std::marker::PhantomData;
+
+use crate::{Architecture, CopyAddress, Endianness, MemoryError, PutAddress};
+
+/// A value that can be decoded from, and encoded into, the bytes of a struct in another process.
+///
+/// This is implemented for the integer and floating point types, `bool`, arrays, [`RemotePtr`],
+/// and every type that derives [`RemoteStruct`], so structs can be nested inside each other.
+/// `usize` and `isize` are not implemented because their size depends on the target process, so
+/// pointers need to be [`RemotePtr`] or marked with `ptr`.
+///
+/// [`RemotePtr`]: struct.RemotePtr.html
+/// [`RemoteStruct`]: trait.RemoteStruct.html
+pub trait RemoteField: Sized {
+    /// The number of bytes the value takes up in a process with the architecture `arch`.
+    fn remote_size(arch: Architecture) -> usize;
+
+    /// Decode the value from the start of `bytes`.
+    ///
+    /// # Panics
+    /// If `bytes` is shorter than [`RemoteField::remote_size`].
+    fn from_bytes(bytes: &[u8], arch: Architecture) -> Self;
+
+    /// Encode the value into the start of `bytes`, leaving the rest of it alone.
+    ///
+    /// # Panics
+    /// If `bytes` is shorter than [`RemoteField::remote_size`].
+    fn write_bytes(&self, bytes: &mut [u8], arch: Architecture);
+}
+
+/// A struct in another process, read with a single [`CopyAddress::copy_address`] of its whole
+/// span rather than one read for every field.
+///
+/// This is usually implemented with `#[derive(RemoteStruct)]` from the macros crate, where every
+/// field is given its offset from the start of the struct:
+///
+/// ```rust,no_run
+/// # use macros::RemoteStruct;
+/// # use titanium_desktop_memory::{ProcessHandle, RemotePtr, RemoteStruct};
+/// # #[derive(RemoteStruct)]
+/// # #[remote(crate = "titanium_desktop_memory")]
+/// # struct Weapon {}
+/// #[derive(RemoteStruct)]
+/// # #[remote(crate = "titanium_desktop_memory")]
+/// struct Player {
+///     #[remote(offset = 0x30)]
+///     health: f32,
+///     #[remote(offset = 0x34)]
+///     position: [f32; 3],
+///     #[remote(offset = 0x80, ptr)]
+///     weapon: RemotePtr<Weapon>,
+/// }
+///
+/// # fn example(handle: ProcessHandle, address: usize) -> std::io::Result<()> {
+/// let player = Player::read(&handle, address)?;
+/// let weapon = player.weapon.read(&handle)?;
+/// Player::write_health(&handle, address, &100.0)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The derive also generates a `write_<field>` function for every field, which writes only the
+/// bytes of that field, so writing one value does not overwrite the rest of the struct with
+/// values that may already be out of date.
+///
+/// [`CopyAddress::copy_address`]: trait.CopyAddress.html#tymethod.copy_address
+pub trait RemoteStruct: RemoteField {
+    /// Read the struct at `address`.
+    ///
+    /// # Errors
+    /// Returns an error if the span of the struct cannot be read.
+    fn read<H: CopyAddress + ?Sized>(handle: &H, address: usize) -> std::io::Result<Self> {
+        let arch = handle.get_pointer_width();
+        let mut bytes = vec![0_u8; Self::remote_size(arch)];
+        handle.copy_address(address, &mut bytes)?;
+        Ok(Self::from_bytes(&bytes, arch))
+    }
+
+    /// Write every field of the struct to `address`. The bytes between the fields are read first
+    /// and written back unchanged.
+    ///
+    /// # Errors
+    /// Returns an error if the span of the struct cannot be read or written.
+    fn write<H: CopyAddress + PutAddress + ?Sized>(
+        &self,
+        handle: &H,
+        address: usize,
+    ) -> std::io::Result<()> {
+        let arch = handle.get_pointer_width();
+        let mut bytes = vec![0_u8; Self::remote_size(arch)];
+        handle.copy_address(address, &mut bytes)?;
+        self.write_bytes(&mut bytes, arch);
+        handle.put_address(address, &bytes)
+    }
+}
+
+/// Write a single field of a struct at `address`. Used by the `write_<field>` functions that
+/// `#[derive(RemoteStruct)]` generates.
+///
+/// # Errors
+/// Returns an error if the field cannot be written.
+#[doc(hidden)]
+pub fn write_remote_field<H: PutAddress + CopyAddress + ?Sized, T: RemoteField>(
+    handle: &H,
+    address: usize,
+    value: &T,
+) -> std::io::Result<()> {
+    let arch = handle.get_pointer_width();
+    let mut bytes = vec![0_u8; T::remote_size(arch)];
+    value.write_bytes(&mut bytes, arch);
+    handle.put_address(address, &bytes)
+}
+
+macro_rules! impl_remote_field {
+    ($($ty:ty),*) => {$(
+        impl RemoteField for $ty {
+            fn remote_size(_arch: Architecture) -> usize {
+                std::mem::size_of::<$ty>()
+            }
+
+            fn from_bytes(bytes: &[u8], arch: Architecture) -> Self {
+                let mut value = [0_u8; std::mem::size_of::<$ty>()];
+                value.copy_from_slice(&bytes[..std::mem::size_of::<$ty>()]);
+                match arch.endianness() {
+                    Endianness::Little => <$ty>::from_le_bytes(value),
+                    Endianness::Big => <$ty>::from_be_bytes(value),
+                }
+            }
+
+            fn write_bytes(&self, bytes: &mut [u8], arch: Architecture) {
+                let value = match arch.endianness() {
+                    Endianness::Little => self.to_le_bytes(),
+                    Endianness::Big => self.to_be_bytes(),
+                };
+                bytes[..std::mem::size_of::<$ty>()].copy_from_slice(&value);
+            }
+        }
+    )*};
+}
+
+impl_remote_field!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+/// Any byte other than `0` is `true`.
+impl RemoteField for bool {
+    fn remote_size(_arch: Architecture) -> usize {
+        1
+    }
+
+    fn from_bytes(bytes: &[u8], _arch: Architecture) -> Self {
+        bytes[0] != 0
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8], _arch: Architecture) {
+        bytes[0] = u8::from(*self);
+    }
+}
+
+impl<T: RemoteField, const N: usize> RemoteField for [T; N] {
+    fn remote_size(arch: Architecture) -> usize {
+        T::remote_size(arch) * N
+    }
+
+    fn from_bytes(bytes: &[u8], arch: Architecture) -> Self {
+        let size = T::remote_size(arch);
+        std::array::from_fn(|index| T::from_bytes(&bytes[index * size..], arch))
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8], arch: Architecture) {
+        let size = T::remote_size(arch);
+        for (index, value) in self.iter().enumerate() {
+            value.write_bytes(&mut bytes[index * size..], arch);
+        }
+    }
+}
+
+/// A value that holds an address in another process, such as [`RemotePtr`] or `usize`, which
+/// `#[remote(ptr)]` fields are decoded as. The address is as wide as a pointer in the process.
+///
+/// [`RemotePtr`]: struct.RemotePtr.html
+pub trait PointerField {
+    /// Create the value from an address.
+    fn from_address(address: usize) -> Self;
+    /// The address the value holds.
+    fn address(&self) -> usize;
+}
+
+impl PointerField for usize {
+    fn from_address(address: usize) -> Self {
+        address
+    }
+
+    fn address(&self) -> usize {
+        *self
+    }
+}
+
+/// Decode a pointer of the width of `arch`. Used by `#[remote(ptr)]` fields.
+#[doc(hidden)]
+#[must_use]
+pub fn read_remote_pointer<T: PointerField>(bytes: &[u8], arch: Architecture) -> T {
+    T::from_address(arch.pointer_from_ne_bytes(&bytes[..arch.pointer_size()]))
+}
+
+/// Encode a pointer of the width of `arch`. Used by `#[remote(ptr)]` fields.
+#[doc(hidden)]
+pub fn write_remote_pointer<T: PointerField>(value: &T, bytes: &mut [u8], arch: Architecture) {
+    arch.write_pointer(value.address(), bytes);
+}
+
+/// Write a single `#[remote(ptr)]` field of a struct at `address`.
+///
+/// # Errors
+/// Returns an error if the field cannot be written.
+#[doc(hidden)]
+pub fn write_remote_pointer_field<H: PutAddress + CopyAddress + ?Sized, T: PointerField>(
+    handle: &H,
+    address: usize,
+    value: &T,
+) -> std::io::Result<()> {
+    let arch = handle.get_pointer_width();
+    let mut bytes = vec![0_u8; arch.pointer_size()];
+    write_remote_pointer(value, &mut bytes, arch);
+    handle.put_address(address, &bytes)
+}
+
+/// A typed pointer to a `T` in another process, as a field of a [`RemoteStruct`].
+///
+/// Only the address is read along with the struct. The value it points to is read separately with
+/// [`RemotePtr::read`], which returns an error for a null pointer.
+///
+/// [`RemoteStruct`]: trait.RemoteStruct.html
+pub struct RemotePtr<T> {
+    address: usize,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> RemotePtr<T> {
+    /// Create a pointer to `address`.
+    #[must_use]
+    pub fn new(address: usize) -> Self {
+        Self {
+            address,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The address the pointer points to.
+    #[must_use]
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Returns `true` if the pointer is null.
+    #[must_use]
+    pub fn is_null(&self) -> bool {
+        self.address == 0
+    }
+
+    /// A pointer to the field `offset` bytes into the value this points to.
+    #[must_use]
+    pub fn offset<U>(&self, offset: usize) -> RemotePtr<U> {
+        RemotePtr::new(self.address.wrapping_add(offset))
+    }
+}
+
+impl<T: RemoteStruct> RemotePtr<T> {
+    /// Read the value the pointer points to.
+    ///
+    /// # Errors
+    /// Returns [`MemoryError::NullPointer`] if the pointer is null, or an error if the value
+    /// cannot be read.
+    ///
+    /// [`MemoryError::NullPointer`]: enum.MemoryError.html#variant.NullPointer
+    pub fn read<H: CopyAddress + ?Sized>(&self, handle: &H) -> std::io::Result<T> {
+        if self.is_null() {
+            return Err(MemoryError::NullPointer { level: 0 }.into());
+        }
+        T::read(handle, self.address)
+    }
+
+    /// Write every field of the value the pointer points to. See [`RemoteStruct::write`].
+    ///
+    /// # Errors
+    /// Returns [`MemoryError::NullPointer`] if the pointer is null, or an error if the value
+    /// cannot be written.
+    ///
+    /// [`RemoteStruct::write`]: trait.RemoteStruct.html#method.write
+    /// [`MemoryError::NullPointer`]: enum.MemoryError.html#variant.NullPointer
+    pub fn write<H: CopyAddress + PutAddress + ?Sized>(
+        &self,
+        handle: &H,
+        value: &T,
+    ) -> std::io::Result<()> {
+        if self.is_null() {
+            return Err(MemoryError::NullPointer { level: 0 }.into());
+        }
+        value.write(handle, self.address)
+    }
+}
+
+impl<T> PointerField for RemotePtr<T> {
+    fn from_address(address: usize) -> Self {
+        Self::new(address)
+    }
+
+    fn address(&self) -> usize {
+        self.address
+    }
+}
+
+impl<T> RemoteField for RemotePtr<T> {
+    fn remote_size(arch: Architecture) -> usize {
+        arch.pointer_size()
+    }
+
+    fn from_bytes(bytes: &[u8], arch: Architecture) -> Self {
+        read_remote_pointer(bytes, arch)
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8], arch: Architecture) {
+        write_remote_pointer(self, bytes, arch);
+    }
+}
+
+// These are implemented by hand because deriving them would require `T` to implement them too.
+impl<T> Clone for RemotePtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for RemotePtr<T> {}
+
+impl<T> PartialEq for RemotePtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.address == other.address
+    }
+}
+
+impl<T> Eq for RemotePtr<T> {}
+
+impl<T> std::hash::Hash for RemotePtr<T> {
+    fn hash<S: std::hash::Hasher>(&self, state: &mut S) {
+        self.address.hash(state);
+    }
+}
+
+impl<T> Default for RemotePtr<T> {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl<T> std::fmt::Debug for RemotePtr<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RemotePtr({:#x})", self.address)
+    }
+}
@@ -0,0 +1,278 @@
+use std::str::FromStr;
+
+use crate::{find_module, regions, CopyAddress, MemoryRegion, Permissions, ProcessHandle};
+
+/// The number of bytes copied out of the target process at a time while scanning.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// The error type for parsing a [`Pattern`].
+#[derive(Debug, thiserror::Error)]
+pub enum PatternError {
+    /// The pattern did not contain any bytes
+    #[error("Pattern is empty")]
+    Empty,
+    /// The pattern contained something that is not a hex byte or a wildcard
+    #[error("Invalid pattern byte `{0}`")]
+    InvalidByte(String),
+    /// The pattern only contained wildcards, so it would match everywhere
+    #[error("Pattern only contains wildcards")]
+    OnlyWildcards,
+}
+
+/// An array-of-bytes signature with wildcards.
+///
+/// Patterns are written in the same syntax as IDA and Cheat Engine: hex bytes separated by
+/// spaces, where `??` (or `?`) matches any byte. Single nibbles can also be wildcarded, so `4?`
+/// matches any byte from `0x40` to `0x4F`.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::Pattern;
+/// let pattern: Pattern = "48 8B 05 ?? ?? ?? ?? 48 85 C0".parse().unwrap();
+/// assert!(pattern.matches(&[0x48, 0x8B, 0x05, 1, 2, 3, 4, 0x48, 0x85, 0xC0]));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    masks: Vec<u8>,
+}
+
+impl Pattern {
+    /// The number of bytes the pattern matches.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Always `false`, as an empty pattern cannot be parsed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns `true` if `bytes` starts with the pattern.
+    #[must_use]
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        bytes.len() >= self.len()
+            && self
+                .bytes
+                .iter()
+                .zip(&self.masks)
+                .zip(bytes)
+                .all(|((byte, mask), actual)| actual & mask == *byte)
+    }
+
+    /// Find the offsets of the pattern in `haystack`. Only matches that start before `limit` are
+    /// returned, which lets overlapping chunks be searched without reporting a match twice.
+    fn find_in(&self, haystack: &[u8], limit: usize, mode: ScanMode, found: &mut Vec<usize>) {
+        if haystack.len() < self.len() {
+            return;
+        }
+        let last = (haystack.len() - self.len()).min(limit.saturating_sub(1));
+        for offset in 0..=last {
+            if self.matches(&haystack[offset..]) {
+                found.push(offset);
+                if mode == ScanMode::First {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, PatternError> {
+        let mut bytes = Vec::new();
+        let mut masks = Vec::new();
+        for token in s.split_whitespace() {
+            let (byte, mask) = match token.as_bytes() {
+                b"?" | b"??" => (0, 0),
+                [high, low] => {
+                    let (high, high_mask) = parse_nibble(*high)
+                        .ok_or_else(|| PatternError::InvalidByte(token.to_owned()))?;
+                    let (low, low_mask) = parse_nibble(*low)
+                        .ok_or_else(|| PatternError::InvalidByte(token.to_owned()))?;
+                    (high << 4 | low, high_mask << 4 | low_mask)
+                }
+                _ => return Err(PatternError::InvalidByte(token.to_owned())),
+            };
+            bytes.push(byte);
+            masks.push(mask);
+        }
+        if bytes.is_empty() {
+            Err(PatternError::Empty)
+        } else if masks.iter().all(|mask| *mask == 0) {
+            Err(PatternError::OnlyWildcards)
+        } else {
+            Ok(Self { bytes, masks })
+        }
+    }
+}
+
+/// Parse a single hex digit or `?` into its value and mask.
+fn parse_nibble(nibble: u8) -> Option<(u8, u8)> {
+    match nibble {
+        b'?' => Some((0, 0)),
+        _ => (nibble as char)
+            .to_digit(16)
+            .map(|value| (value as u8, 0xF)),
+    }
+}
+
+/// Whether a scan should stop at the first match or find every match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScanMode {
+    /// Stop scanning after the first match.
+    First,
+    /// Find every match.
+    All,
+}
+
+/// Scan `len` bytes starting at `start` for a [`Pattern`], returning the address of each match.
+///
+/// Memory is copied in large chunks with [`CopyAddress::copy_address`], and matches that
+/// straddle two chunks are still found. Chunks that cannot be read are skipped.
+///
+/// [`CopyAddress::copy_address`]: trait.CopyAddress.html#tymethod.copy_address
+pub fn scan<T: CopyAddress>(
+    source: &T,
+    start: usize,
+    len: usize,
+    pattern: &Pattern,
+    mode: ScanMode,
+) -> Vec<usize> {
+    let mut found = Vec::new();
+    scan_into(source, start, len, pattern, mode, &mut found);
+    found
+}
+
+fn scan_into<T: CopyAddress>(
+    source: &T,
+    start: usize,
+    len: usize,
+    pattern: &Pattern,
+    mode: ScanMode,
+    found: &mut Vec<usize>,
+) {
+    let overlap = pattern.len() - 1;
+    let mut buffer = vec![0_u8; CHUNK_SIZE.min(len) + overlap];
+    let mut offset = 0;
+    while offset < len {
+        let chunk = &mut buffer[..(len - offset).min(CHUNK_SIZE + overlap)];
+        if source.copy_address(start + offset, chunk).is_ok() {
+            let first = found.len();
+            pattern.find_in(chunk, CHUNK_SIZE, mode, found);
+            for address in &mut found[first..] {
+                *address += start + offset;
+            }
+            if mode == ScanMode::First && !found.is_empty() {
+                return;
+            }
+        }
+        offset += CHUNK_SIZE;
+    }
+}
+
+/// Scan every readable region of a process whose permissions contain `permissions` for a
+/// [`Pattern`]. For example, pass `Permissions { execute: true, ..Default::default() }` to only
+/// search code.
+///
+/// # Errors
+/// Returns an error if the regions of the process cannot be read.
+pub fn scan_regions(
+    handle: &ProcessHandle,
+    pattern: &Pattern,
+    permissions: Permissions,
+    mode: ScanMode,
+) -> std::io::Result<Vec<usize>> {
+    Ok(scan_each(handle, regions(handle)?, pattern, permissions, mode))
+}
+
+/// Scan the readable regions of a loaded module whose permissions contain `permissions` for a
+/// [`Pattern`]. The module is found with [`find_module`].
+///
+/// # Errors
+/// Returns an error if the regions of the process cannot be read, or if the module cannot be
+/// found.
+///
+/// [`find_module`]: fn.find_module.html
+pub fn scan_module(
+    handle: &ProcessHandle,
+    module: &str,
+    pattern: &Pattern,
+    permissions: Permissions,
+    mode: ScanMode,
+) -> std::io::Result<Vec<usize>> {
+    let module = find_module(handle, module)?;
+    let regions = regions(handle)?.filter(|region| module.contains(region.start));
+    Ok(scan_each(handle, regions, pattern, permissions, mode))
+}
+
+fn scan_each(
+    handle: &ProcessHandle,
+    regions: impl Iterator<Item = MemoryRegion>,
+    pattern: &Pattern,
+    permissions: Permissions,
+    mode: ScanMode,
+) -> Vec<usize> {
+    let mut found = Vec::new();
+    for region in regions {
+        if !region.permissions.read || !region.permissions.contains(permissions) {
+            continue;
+        }
+        scan_into(handle, region.start, region.size(), pattern, mode, &mut found);
+        if mode == ScanMode::First && !found.is_empty() {
+            break;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LocalProcess;
+
+    #[test]
+    fn parses_bytes_and_wildcards() {
+        let pattern: Pattern = "48 8b ?? ? 4? ?F".parse().unwrap();
+        assert_eq!(pattern.len(), 6);
+        assert!(pattern.matches(&[0x48, 0x8B, 0x00, 0xFF, 0x40, 0x0F]));
+        assert!(pattern.matches(&[0x48, 0x8B, 0x12, 0x34, 0x4E, 0xAF, 0x99]));
+        assert!(!pattern.matches(&[0x48, 0x8B, 0x12, 0x34, 0x50, 0xAF]));
+        assert!(!pattern.matches(&[0x48, 0x8B, 0x12, 0x34, 0x4E, 0xAE]));
+        assert!(!pattern.matches(&[0x48, 0x8B, 0x12, 0x34, 0x4E]));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        assert!(matches!("".parse::<Pattern>(), Err(PatternError::Empty)));
+        assert!(matches!(" ".parse::<Pattern>(), Err(PatternError::Empty)));
+        assert!(matches!("?? ?".parse::<Pattern>(), Err(PatternError::OnlyWildcards)));
+        assert!(matches!(
+            "48 8G".parse::<Pattern>(),
+            Err(PatternError::InvalidByte(token)) if token == "8G"
+        ));
+        assert!(matches!(
+            "488B".parse::<Pattern>(),
+            Err(PatternError::InvalidByte(token)) if token == "488B"
+        ));
+    }
+
+    #[test]
+    fn scans_across_chunks() {
+        let mut haystack = vec![0_u8; CHUNK_SIZE + 64];
+        let straddling = CHUNK_SIZE - 2;
+        haystack[4..8].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        haystack[straddling..straddling + 4].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        let pattern: Pattern = "DE ?? BE EF".parse().unwrap();
+        let start = haystack.as_ptr() as usize;
+
+        let all = scan(&LocalProcess, start, haystack.len(), &pattern, ScanMode::All);
+        assert_eq!(all, [start + 4, start + straddling]);
+        let first = scan(&LocalProcess, start, haystack.len(), &pattern, ScanMode::First);
+        assert_eq!(first, [start + 4]);
+        assert!(scan(&LocalProcess, start, 7, &pattern, ScanMode::All).is_empty());
+    }
+}
@@ -0,0 +1,568 @@
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::{
+    modules, regions, Architecture, CopyAddress, Endianness, MemoryRegion, Module, ProcessHandle,
+};
+
+/// The magic bytes at the start of a saved [`Snapshot`].
+const MAGIC: &[u8; 4] = b"TISN";
+/// The version of the saved [`Snapshot`] format.
+const VERSION: u32 = 1;
+const PAGE_SIZE: usize = 0x1000;
+/// The number of bytes read from the target at a time while capturing a [`Snapshot`].
+const CHUNK_SIZE: usize = 4 << 20;
+
+/// A page that could not be read when the snapshot was captured.
+const PAGE_MISSING: u8 = 0;
+/// A page whose contents follow.
+const PAGE_PRESENT: u8 = 1;
+/// A page in an incremental snapshot that is the same as in its base.
+const PAGE_UNCHANGED: u8 = 2;
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+fn not_captured(addr: usize) -> std::io::Error {
+    std::io::Error::other(format!("{addr:#x} is not in the snapshot"))
+}
+
+/// A region of a [`Snapshot`], along with the bytes that were captured from it.
+#[derive(Clone, Debug)]
+struct CapturedRegion {
+    region: MemoryRegion,
+    bytes: Vec<u8>,
+    /// Whether every page of the region could be read.
+    present: Vec<bool>,
+}
+
+impl CapturedRegion {
+    fn capture(handle: &ProcessHandle, region: MemoryRegion) -> Self {
+        let mut bytes = vec![0_u8; region.size()];
+        let mut present = vec![true; region.size().div_ceil(PAGE_SIZE)];
+        for (index, chunk) in bytes.chunks_mut(CHUNK_SIZE).enumerate() {
+            let start = region.start + index * CHUNK_SIZE;
+            if handle.copy_address(start, chunk).is_ok() {
+                continue;
+            }
+            // Fall back to single pages so that one guard page does not lose the whole chunk.
+            for (page, bytes) in chunk.chunks_mut(PAGE_SIZE).enumerate() {
+                if handle.copy_address(start + page * PAGE_SIZE, bytes).is_err() {
+                    present[(index * CHUNK_SIZE) / PAGE_SIZE + page] = false;
+                }
+            }
+        }
+        Self {
+            region,
+            bytes,
+            present,
+        }
+    }
+
+    fn page(&self, index: usize) -> &[u8] {
+        let start = index * PAGE_SIZE;
+        &self.bytes[start..self.bytes.len().min(start + PAGE_SIZE)]
+    }
+}
+
+/// A copy of some or all of the memory of a process at one point in time, along with its regions
+/// and modules, that can be saved to a compressed file and loaded again later.
+///
+/// A `Snapshot` implements [`CopyAddress`], so it can stand in for the process while the game is
+/// not running: [`DataMember::read_from`], [`CopyAddress::get_offset`], [`RemoteStruct::read`]
+/// and [`scan`] all work on it, which makes it possible to develop and test against a recorded
+/// game state.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, scan, Pattern, ScanMode, Snapshot};
+/// let handle = get_handle("game").unwrap();
+/// let before = Snapshot::capture_all(&handle).unwrap();
+/// before.save("before.snap").unwrap();
+/// // ...
+/// let after = Snapshot::capture_all(&handle).unwrap();
+/// // Only the pages that changed since `before` are written
+/// after.save_incremental("after.snap", &before).unwrap();
+///
+/// // Later, without the game running
+/// let before = Snapshot::load("before.snap").unwrap();
+/// let after = Snapshot::load_incremental("after.snap", &before).unwrap();
+/// let pattern: Pattern = "48 8b 05 ?? ?? ?? ??".parse().unwrap();
+/// for region in after.regions() {
+///     let found = scan(&after, region.start, region.size(), &pattern, ScanMode::All);
+/// }
+/// ```
+///
+/// Pages that could not be read while capturing, such as guard pages, are remembered, and
+/// reading them from the snapshot returns an error just like reading them from the process.
+///
+/// [`CopyAddress`]: trait.CopyAddress.html
+/// [`DataMember::read_from`]: struct.DataMember.html#method.read_from
+/// [`CopyAddress::get_offset`]: trait.CopyAddress.html#method.get_offset
+/// [`RemoteStruct::read`]: trait.RemoteStruct.html#method.read
+/// [`scan`]: fn.scan.html
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// Identifies the snapshot, so that an incremental snapshot can check that it is loaded on
+    /// top of the right base.
+    id: u64,
+    arch: Architecture,
+    modules: Vec<Module>,
+    regions: Vec<CapturedRegion>,
+}
+
+impl Snapshot {
+    /// Capture every readable region of a process.
+    ///
+    /// # Errors
+    /// Returns an error if the regions or modules of the process cannot be read.
+    pub fn capture_all(handle: &ProcessHandle) -> std::io::Result<Self> {
+        Self::capture(handle, |_| true)
+    }
+
+    /// Capture the readable regions of a process that `filter` returns `true` for, such as only
+    /// the writable ones, or only the ones of one module.
+    ///
+    /// # Errors
+    /// Returns an error if the regions or modules of the process cannot be read.
+    pub fn capture<F: FnMut(&MemoryRegion) -> bool>(
+        handle: &ProcessHandle,
+        mut filter: F,
+    ) -> std::io::Result<Self> {
+        let modules = modules(handle)?;
+        let regions = regions(handle)?
+            .filter(|region| region.permissions.read && filter(region))
+            .map(|region| CapturedRegion::capture(handle, region))
+            .collect();
+        #[allow(clippy::cast_possible_truncation)]
+        let id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Ok(Self {
+            id,
+            arch: handle.get_pointer_width(),
+            modules,
+            regions,
+        })
+    }
+
+    /// The regions that were captured, in ascending address order.
+    pub fn regions(&self) -> impl Iterator<Item = &MemoryRegion> + '_ {
+        self.regions.iter().map(|captured| &captured.region)
+    }
+
+    /// The modules that were loaded in the process, in ascending address order.
+    #[must_use]
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+
+    /// Find a module by its file name or its full path. See [`find_module`].
+    ///
+    /// [`find_module`]: fn.find_module.html
+    #[must_use]
+    pub fn find_module(&self, name: &str) -> Option<&Module> {
+        self.modules.iter().find(|module| module.matches(name))
+    }
+
+    /// Save the snapshot to a compressed file.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        self.write(path.as_ref(), None)
+    }
+
+    /// Save only the pages that differ from `base`, which is needed to load it again with
+    /// [`Snapshot::load_incremental`]. Regions that are not in `base` are saved in full.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be written.
+    pub fn save_incremental<P: AsRef<Path>>(&self, path: P, base: &Snapshot) -> std::io::Result<()> {
+        self.write(path.as_ref(), Some(base))
+    }
+
+    fn write(&self, path: &Path, base: Option<&Snapshot>) -> std::io::Result<()> {
+        let mut file = BufWriter::new(std::fs::File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        let mut file = ZlibEncoder::new(file, Compression::fast());
+        file.write_all(&self.id.to_le_bytes())?;
+        file.write_all(&base.map_or(0, |base| base.id).to_le_bytes())?;
+        file.write_all(&[architecture_byte(self.arch)])?;
+
+        file.write_all(&(self.modules.len() as u64).to_le_bytes())?;
+        for module in &self.modules {
+            write_path(&mut file, &module.path)?;
+            file.write_all(&(module.base as u64).to_le_bytes())?;
+            file.write_all(&(module.size as u64).to_le_bytes())?;
+        }
+
+        file.write_all(&(self.regions.len() as u64).to_le_bytes())?;
+        for captured in &self.regions {
+            let region = &captured.region;
+            file.write_all(&(region.start as u64).to_le_bytes())?;
+            file.write_all(&(region.end as u64).to_le_bytes())?;
+            file.write_all(region.permissions.to_string().as_bytes())?;
+            file.write_all(&region.offset.to_le_bytes())?;
+            file.write_all(&region.inode.to_le_bytes())?;
+            match &region.path {
+                Some(path) => {
+                    file.write_all(&[1])?;
+                    write_path(&mut file, path)?;
+                }
+                None => file.write_all(&[0])?,
+            }
+
+            let base = base.and_then(|base| {
+                base.regions.iter().find(|other| {
+                    other.region.start == region.start && other.region.end == region.end
+                })
+            });
+            for (index, present) in captured.present.iter().enumerate() {
+                let page = captured.page(index);
+                if !present {
+                    file.write_all(&[PAGE_MISSING])?;
+                } else if base.is_some_and(|base| base.present[index] && base.page(index) == page) {
+                    file.write_all(&[PAGE_UNCHANGED])?;
+                } else {
+                    file.write_all(&[PAGE_PRESENT])?;
+                    file.write_all(page)?;
+                }
+            }
+        }
+        file.finish()?.flush()
+    }
+
+    /// Load a snapshot saved with [`Snapshot::save`].
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, if it is not a valid snapshot, or if it is
+    /// an incremental snapshot.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::read(path.as_ref(), None)
+    }
+
+    /// Load a snapshot saved with [`Snapshot::save_incremental`], taking the pages that did not
+    /// change from `base`. A full snapshot can be loaded with this as well.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, if it is not a valid snapshot, or if it was
+    /// saved against a different base.
+    pub fn load_incremental<P: AsRef<Path>>(path: P, base: &Snapshot) -> std::io::Result<Self> {
+        Self::read(path.as_ref(), Some(base))
+    }
+
+    fn read(path: &Path, base: Option<&Snapshot>) -> std::io::Result<Self> {
+        let mut file = BufReader::new(std::fs::File::open(path)?);
+        let mut magic = [0_u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u32(&mut file)? != VERSION {
+            return Err(invalid_data(
+                "Not a snapshot, or a snapshot from another version",
+            ));
+        }
+        let mut file = ZlibDecoder::new(file);
+        let id = read_u64(&mut file)?;
+        let base_id = read_u64(&mut file)?;
+        let base = match (base_id, base) {
+            (0, _) => None,
+            (_, Some(base)) if base.id == base_id => Some(base),
+            (_, Some(_)) => return Err(invalid_data("Snapshot was saved against another base")),
+            (_, None) => return Err(invalid_data("Snapshot is incremental and needs its base")),
+        };
+        let arch = architecture(read_u8(&mut file)?)
+            .ok_or_else(|| invalid_data("Unsupported pointer width"))?;
+
+        let mut modules = Vec::new();
+        for _ in 0..read_u64(&mut file)? {
+            let path = read_path(&mut file)?;
+            modules.push(Module {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                path,
+                base: read_usize(&mut file)?,
+                size: read_usize(&mut file)?,
+            });
+        }
+
+        let mut regions = Vec::new();
+        for _ in 0..read_u64(&mut file)? {
+            let start = read_usize(&mut file)?;
+            let end = read_usize(&mut file)?;
+            if end < start {
+                return Err(invalid_data("Region ends before it starts"));
+            }
+            // Reads find a region with a binary search, which needs them sorted and apart.
+            if regions
+                .last()
+                .is_some_and(|last: &CapturedRegion| start < last.region.end)
+            {
+                return Err(invalid_data("Regions are out of order or overlap"));
+            }
+            let mut permissions = [0_u8; 4];
+            file.read_exact(&mut permissions)?;
+            let region = MemoryRegion {
+                start,
+                end,
+                permissions: String::from_utf8_lossy(&permissions).parse()?,
+                offset: read_u64(&mut file)?,
+                inode: read_u64(&mut file)?,
+                path: match read_u8(&mut file)? {
+                    0 => None,
+                    _ => Some(read_path(&mut file)?),
+                },
+            };
+
+            let base = base.and_then(|base| {
+                base.regions
+                    .iter()
+                    .find(|other| other.region.start == start && other.region.end == end)
+            });
+            // The buffers grow a page at a time as the pages are decoded, rather than trusting
+            // the size of the region, so a corrupt file fails at its end instead of asking for
+            // an enormous allocation.
+            let mut bytes = Vec::new();
+            let mut present = Vec::new();
+            for index in 0..region.size().div_ceil(PAGE_SIZE) {
+                let page_start = bytes.len();
+                let page_len = PAGE_SIZE.min(region.size() - page_start);
+                bytes.resize(page_start + page_len, 0);
+                let page = &mut bytes[page_start..];
+                present.push(true);
+                match read_u8(&mut file)? {
+                    PAGE_MISSING => present[index] = false,
+                    PAGE_PRESENT => file.read_exact(page)?,
+                    PAGE_UNCHANGED => match base {
+                        Some(base) if base.present[index] => page.copy_from_slice(base.page(index)),
+                        _ => return Err(invalid_data("Unchanged page is missing from the base")),
+                    },
+                    _ => return Err(invalid_data("Invalid page")),
+                }
+            }
+            regions.push(CapturedRegion {
+                region,
+                bytes,
+                present,
+            });
+        }
+        Ok(Self {
+            id,
+            arch,
+            modules,
+            regions,
+        })
+    }
+}
+
+fn write_path<W: Write>(writer: &mut W, path: &Path) -> std::io::Result<()> {
+    let path = path.to_string_lossy();
+    writer.write_all(&(path.len() as u64).to_le_bytes())?;
+    writer.write_all(path.as_bytes())
+}
+
+fn read_path<R: Read>(reader: &mut R) -> std::io::Result<PathBuf> {
+    let len = read_usize(reader)?;
+    let mut path = Vec::new();
+    reader.take(len as u64).read_to_end(&mut path)?;
+    if path.len() != len {
+        return Err(invalid_data("Truncated path"));
+    }
+    Ok(PathBuf::from(String::from_utf8_lossy(&path).into_owned()))
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> std::io::Result<u8> {
+    let mut byte = [0_u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut bytes = [0_u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut bytes = [0_u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> std::io::Result<usize> {
+    usize::try_from(read_u64(reader)?).map_err(|_| invalid_data("Address is too large"))
+}
+
+/// The top bit of the architecture byte is set for big-endian processes.
+const BIG_ENDIAN: u8 = 0x80;
+
+/// An architecture stored as the size of its pointers and a flag for its byte order.
+fn architecture_byte(arch: Architecture) -> u8 {
+    #[allow(clippy::cast_possible_truncation)]
+    let size = arch.pointer_size() as u8;
+    match arch.endianness() {
+        Endianness::Little => size,
+        Endianness::Big => size | BIG_ENDIAN,
+    }
+}
+
+/// The architecture stored by [`architecture_byte`].
+fn architecture(byte: u8) -> Option<Architecture> {
+    let endianness = if byte & BIG_ENDIAN == 0 {
+        Endianness::Little
+    } else {
+        Endianness::Big
+    };
+    Architecture::new(usize::from(byte & !BIG_ENDIAN), endianness)
+}
+
+/// Read from the captured bytes, failing for anything that was not captured.
+impl CopyAddress for Snapshot {
+    fn copy_address(&self, addr: usize, buf: &mut [u8]) -> std::io::Result<()> {
+        let mut copied = 0;
+        while copied < buf.len() {
+            let address = addr.wrapping_add(copied);
+            let index = self
+                .regions
+                .partition_point(|captured| captured.region.start <= address)
+                .checked_sub(1)
+                .ok_or_else(|| not_captured(address))?;
+            let captured = &self.regions[index];
+            if !captured.region.contains(address) {
+                return Err(not_captured(address));
+            }
+            let offset = address - captured.region.start;
+            let len = (buf.len() - copied).min(captured.region.end - address);
+            let pages = offset / PAGE_SIZE..=(offset + len - 1) / PAGE_SIZE;
+            if let Some(page) = pages.into_iter().find(|&page| !captured.present[page]) {
+                return Err(not_captured(captured.region.start + page * PAGE_SIZE));
+            }
+            buf[copied..copied + len].copy_from_slice(&captured.bytes[offset..offset + len]);
+            copied += len;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn get_pointer_width(&self) -> Architecture {
+        self.arch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pid, TryIntoProcessHandle};
+
+    fn own_handle() -> ProcessHandle {
+        (std::process::id() as Pid)
+            .try_into_process_handle()
+            .unwrap()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("snapshot_{}_{name}", std::process::id()))
+    }
+
+    /// Capture only the region that holds `address`.
+    fn capture_around(address: usize) -> Snapshot {
+        Snapshot::capture(&own_handle(), |region| region.contains(address)).unwrap()
+    }
+
+    fn read_u64_at(snapshot: &Snapshot, address: usize) -> u64 {
+        let mut bytes = [0_u8; 8];
+        snapshot.copy_address(address, &mut bytes).unwrap();
+        u64::from_ne_bytes(bytes)
+    }
+
+    #[test]
+    fn saves_and_loads_the_current_process() {
+        let value = Box::new(0x1122_3344_5566_7788_u64);
+        let address = std::ptr::addr_of!(*value) as usize;
+        let snapshot = capture_around(address);
+        assert_eq!(snapshot.regions().count(), 1);
+
+        let path = temp_path("round_trip");
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.get_pointer_width(), snapshot.get_pointer_width());
+        assert!(loaded.regions().eq(snapshot.regions()));
+        assert_eq!(loaded.modules().len(), snapshot.modules().len());
+        assert_eq!(read_u64_at(&loaded, address), 0x1122_3344_5566_7788);
+        let region = loaded.regions().next().unwrap().clone();
+        assert!(loaded.copy_address(region.end, &mut [0]).is_err());
+    }
+
+    #[test]
+    fn rejects_incremental_snapshots_against_another_base() {
+        let mut value = Box::new(1_u64);
+        let address = std::ptr::addr_of!(*value) as usize;
+        let base = capture_around(address);
+        *value = 2;
+        let mut other = capture_around(address);
+        other.id = base.id.wrapping_add(1);
+        let after = capture_around(address);
+
+        let path = temp_path("incremental");
+        after.save_incremental(&path, &base).unwrap();
+        let loaded = Snapshot::load_incremental(&path, &base).unwrap();
+        assert_eq!(read_u64_at(&loaded, address), 2);
+        let wrong_base = Snapshot::load_incremental(&path, &other).unwrap_err();
+        let no_base = Snapshot::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(wrong_base.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            wrong_base.to_string(),
+            "Snapshot was saved against another base"
+        );
+        assert_eq!(no_base.kind(), std::io::ErrorKind::InvalidData);
+        std::hint::black_box(&value);
+    }
+
+    #[test]
+    fn rejects_unsorted_or_overlapping_regions() {
+        let region = |start: usize, end: usize| CapturedRegion {
+            region: MemoryRegion {
+                start,
+                end,
+                permissions: "rw-p".parse().unwrap(),
+                offset: 0,
+                inode: 0,
+                path: None,
+            },
+            bytes: vec![0; end - start],
+            present: vec![true; (end - start).div_ceil(PAGE_SIZE)],
+        };
+        let snapshot = |regions| Snapshot {
+            id: 1,
+            arch: Architecture::from_native(),
+            modules: Vec::new(),
+            regions,
+        };
+
+        let path = temp_path("regions");
+        snapshot(vec![region(0x1000, 0x2000), region(0x2000, 0x3000)])
+            .save(&path)
+            .unwrap();
+        assert!(Snapshot::load(&path).is_ok());
+        for regions in [
+            vec![region(0x2000, 0x3000), region(0x1000, 0x2000)],
+            vec![region(0x1000, 0x3000), region(0x2000, 0x4000)],
+        ] {
+            snapshot(regions).save(&path).unwrap();
+            let error = Snapshot::load(&path).unwrap_err();
+            assert_eq!(error.to_string(), "Regions are out of order or overlap");
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,570 @@
+use std::cmp::Ordering;
+
+use crate::{regions, CopyAddress, DataMember, ProcessHandle};
+
+/// The number of bytes of a region handled by one unit of work during a scan. This also bounds
+/// the offsets stored for each candidate, letting them fit in a `u32`.
+const BLOCK_SIZE: usize = 4 << 20;
+
+/// A type that can be searched for with a [`ScanSession`].
+///
+/// This is implemented for all of the primitive integer and floating point types, and for
+/// `Vec<u8>` to search for byte strings.
+pub trait ScanValue: Clone + Send + Sync {
+    /// The number of bytes the value takes up in memory.
+    fn width(&self) -> usize;
+
+    /// The alignment that values of this type are searched at by default.
+    fn alignment(&self) -> usize {
+        self.width()
+    }
+
+    /// Decode a value from `self.width()` bytes of memory.
+    fn decode(&self, bytes: &[u8]) -> Self;
+
+    /// Returns `true` if the two values are equal. Floating point values are equal if they are
+    /// within `tolerance` of each other.
+    fn scan_eq(&self, other: &Self, tolerance: f64) -> bool;
+
+    /// Compare two values, if the type has an ordering.
+    fn scan_cmp(&self, other: &Self) -> Option<Ordering>;
+
+    /// Add `delta` to the value, if the type supports addition.
+    fn scan_add(&self, delta: &Self) -> Option<Self>;
+
+    /// Returns `true` if the value encoded in `bytes` is equal to `self`.
+    fn scan_eq_bytes(&self, bytes: &[u8], tolerance: f64) -> bool {
+        self.scan_eq(&self.decode(bytes), tolerance)
+    }
+}
+
+macro_rules! impl_scan_value_int {
+    ($($t:ty),*) => {$(
+        impl ScanValue for $t {
+            fn width(&self) -> usize {
+                std::mem::size_of::<$t>()
+            }
+
+            fn decode(&self, bytes: &[u8]) -> Self {
+                <$t>::from_ne_bytes(bytes.try_into().unwrap())
+            }
+
+            fn scan_eq(&self, other: &Self, _tolerance: f64) -> bool {
+                self == other
+            }
+
+            fn scan_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+
+            fn scan_add(&self, delta: &Self) -> Option<Self> {
+                Some(self.wrapping_add(*delta))
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_scan_value_float {
+    ($($t:ty),*) => {$(
+        impl ScanValue for $t {
+            fn width(&self) -> usize {
+                std::mem::size_of::<$t>()
+            }
+
+            fn decode(&self, bytes: &[u8]) -> Self {
+                <$t>::from_ne_bytes(bytes.try_into().unwrap())
+            }
+
+            fn scan_eq(&self, other: &Self, tolerance: f64) -> bool {
+                (f64::from(*self) - f64::from(*other)).abs() <= tolerance
+            }
+
+            fn scan_cmp(&self, other: &Self) -> Option<Ordering> {
+                self.partial_cmp(other)
+            }
+
+            fn scan_add(&self, delta: &Self) -> Option<Self> {
+                Some(self + delta)
+            }
+        }
+    )*};
+}
+
+impl_scan_value_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+impl_scan_value_float!(f32, f64);
+
+/// Byte strings are searched for exactly, at any alignment.
+impl ScanValue for Vec<u8> {
+    fn width(&self) -> usize {
+        self.len()
+    }
+
+    fn alignment(&self) -> usize {
+        1
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Self {
+        bytes.to_vec()
+    }
+
+    fn scan_eq(&self, other: &Self, _tolerance: f64) -> bool {
+        self == other
+    }
+
+    fn scan_cmp(&self, _other: &Self) -> Option<Ordering> {
+        None
+    }
+
+    fn scan_add(&self, _delta: &Self) -> Option<Self> {
+        None
+    }
+
+    fn scan_eq_bytes(&self, bytes: &[u8], _tolerance: f64) -> bool {
+        self.as_slice() == bytes
+    }
+}
+
+/// A condition used to select candidates in a [`ScanSession`].
+///
+/// [`ScanCondition::Exact`] and [`ScanCondition::Between`] can be used for the first scan, while
+/// the other conditions compare against the value from the previous scan and so can only be used
+/// for next scans.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScanCondition<T> {
+    /// The value is equal to the given value.
+    Exact(T),
+    /// The value is between the two given values, inclusive.
+    Between(T, T),
+    /// The value is different to the previous scan.
+    Changed,
+    /// The value is the same as the previous scan.
+    Unchanged,
+    /// The value is greater than the previous scan.
+    Increased,
+    /// The value is less than the previous scan.
+    Decreased,
+    /// The value is the previous scan's value plus the given amount.
+    IncreasedBy(T),
+}
+
+impl<T: ScanValue> ScanCondition<T> {
+    fn is_first_scan(&self) -> bool {
+        matches!(self, ScanCondition::Exact(_) | ScanCondition::Between(..))
+    }
+
+    fn value(&self) -> Option<&T> {
+        match self {
+            ScanCondition::Exact(value)
+            | ScanCondition::Between(value, _)
+            | ScanCondition::IncreasedBy(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Check the value in `bytes` against the condition, using `template` to decode values.
+    fn check(&self, template: &T, bytes: &[u8], previous: &[u8], tolerance: f64) -> bool {
+        match self {
+            ScanCondition::Exact(value) => value.scan_eq_bytes(bytes, tolerance),
+            ScanCondition::Between(low, high) => {
+                let current = template.decode(bytes);
+                current.scan_cmp(low).is_some_and(Ordering::is_ge)
+                    && current.scan_cmp(high).is_some_and(Ordering::is_le)
+            }
+            ScanCondition::Changed => bytes != previous,
+            ScanCondition::Unchanged => bytes == previous,
+            ScanCondition::Increased => {
+                template.decode(bytes).scan_cmp(&template.decode(previous)) == Some(Ordering::Greater)
+            }
+            ScanCondition::Decreased => {
+                template.decode(bytes).scan_cmp(&template.decode(previous)) == Some(Ordering::Less)
+            }
+            ScanCondition::IncreasedBy(delta) => template
+                .decode(previous)
+                .scan_add(delta)
+                .is_some_and(|expected| expected.scan_eq_bytes(bytes, tolerance)),
+        }
+    }
+}
+
+/// The candidates found inside of one block of memory.
+#[derive(Clone, Debug)]
+struct Block {
+    base: usize,
+    /// The offset of each candidate from `base`.
+    offsets: Vec<u32>,
+    /// The value of each candidate from the last scan, `width` bytes each.
+    values: Vec<u8>,
+}
+
+/// An iterative, Cheat Engine style value scan over the writable memory of a process.
+///
+/// A [`ScanSession::first_scan`] finds every address holding a value, and each
+/// [`ScanSession::next_scan`] narrows those candidates down by comparing them to their value from
+/// the previous scan. Candidates are stored compactly as offsets into blocks of memory, and every
+/// scan is split across multiple threads.
+///
+/// ```rust,no_run
+/// # use titanium_desktop_memory::{get_handle, ScanCondition, ScanSession};
+/// let handle = get_handle("game").unwrap();
+/// let mut session = ScanSession::new(handle);
+/// session.first_scan(&ScanCondition::Exact(100_u32)).unwrap();
+/// // ...take some damage in game...
+/// session.next_scan(&ScanCondition::Decreased).unwrap();
+/// let health = session.to_members();
+/// ```
+#[derive(Debug)]
+pub struct ScanSession<T: ScanValue> {
+    handle: ProcessHandle,
+    template: Option<T>,
+    alignment: Option<usize>,
+    tolerance: f64,
+    threads: usize,
+    blocks: Vec<Block>,
+}
+
+impl<T: ScanValue> ScanSession<T> {
+    /// Create a new scan session for a process. No memory is scanned until
+    /// [`ScanSession::first_scan`] is called.
+    #[must_use]
+    pub fn new(handle: ProcessHandle) -> Self {
+        Self {
+            handle,
+            template: None,
+            alignment: None,
+            tolerance: 0.0,
+            threads: std::thread::available_parallelism().map_or(1, usize::from),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Set the maximum difference between two floating point values for them to be considered
+    /// equal. Defaults to `0.0`.
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance;
+    }
+
+    /// Set the alignment that addresses are searched at in the first scan. Defaults to the size
+    /// of the value, or `1` for byte strings.
+    pub fn set_alignment(&mut self, alignment: usize) {
+        self.alignment = Some(alignment.max(1));
+    }
+
+    /// Set the number of threads used to scan. Defaults to the available parallelism.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Scan every readable and writable region of the process, replacing any existing
+    /// candidates with the addresses that match `condition`. Returns the number of candidates.
+    ///
+    /// # Errors
+    /// Returns an error if the regions of the process cannot be read, or a `std::io::Error` with
+    /// a `std::io::ErrorKind` of `InvalidInput` if the condition needs a previous scan, or if it
+    /// is a range and the value type has no ordering.
+    pub fn first_scan(&mut self, condition: &ScanCondition<T>) -> std::io::Result<usize> {
+        let template = match condition.value() {
+            Some(value) if condition.is_first_scan() && value.width() > 0 => value.clone(),
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "The first scan must search for an exact value or a range of values",
+                ))
+            }
+        };
+        let ordered = matches!(condition, ScanCondition::Between(..));
+        if ordered && template.scan_cmp(&template).is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The scanned type cannot be compared",
+            ));
+        }
+        let width = template.width();
+        let alignment = self.alignment.unwrap_or_else(|| template.alignment()).max(1);
+
+        // Each block also reads the first `width - 1` bytes of the next one, so that values
+        // crossing the boundary are found. They are only counted by the block they start in.
+        let mut work = Vec::new();
+        for region in regions(&self.handle)? {
+            if !(region.permissions.read && region.permissions.write) {
+                continue;
+            }
+            let mut start = region.start;
+            while start < region.end {
+                let end = (start + BLOCK_SIZE).min(region.end);
+                work.push((start, end, (end + width - 1).min(region.end)));
+                start = end;
+            }
+        }
+
+        let handle = self.handle;
+        let tolerance = self.tolerance;
+        self.blocks = self.run(work, |(start, end, read_end)| {
+            let mut buffer = vec![0_u8; read_end - start];
+            handle.copy_address(start, &mut buffer).ok()?;
+            let mut block = Block {
+                base: start,
+                offsets: Vec::new(),
+                values: Vec::new(),
+            };
+            let first = (alignment - start % alignment) % alignment;
+            let mut offset = first;
+            while offset < end - start && offset + width <= buffer.len() {
+                let bytes = &buffer[offset..offset + width];
+                if condition.check(&template, bytes, bytes, tolerance) {
+                    #[allow(clippy::cast_possible_truncation)]
+                    block.offsets.push(offset as u32);
+                    block.values.extend_from_slice(bytes);
+                }
+                offset += alignment;
+            }
+            Some(block)
+        });
+        self.template = Some(template);
+        Ok(self.len())
+    }
+
+    /// Re-read every candidate, keeping only those that match `condition`. Returns the number of
+    /// candidates left.
+    ///
+    /// Candidates that can no longer be read, for example because their memory was unmapped, are
+    /// discarded.
+    ///
+    /// # Errors
+    /// Returns a `std::io::Error` with a `std::io::ErrorKind` of `InvalidInput` if there has not
+    /// been a first scan, or if the condition needs comparisons or arithmetic that the value type
+    /// does not support.
+    pub fn next_scan(&mut self, condition: &ScanCondition<T>) -> std::io::Result<usize> {
+        let template = self.template.clone().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "A first scan is needed before a next scan",
+            )
+        })?;
+        let ordered = matches!(
+            condition,
+            ScanCondition::Between(..)
+                | ScanCondition::Increased
+                | ScanCondition::Decreased
+                | ScanCondition::IncreasedBy(_)
+        );
+        if ordered && template.scan_cmp(&template).is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The scanned type cannot be compared or added to",
+            ));
+        }
+
+        let width = template.width();
+        let handle = self.handle;
+        let tolerance = self.tolerance;
+        let blocks = std::mem::take(&mut self.blocks);
+        self.blocks = self.run(blocks, |block| {
+            let last = *block.offsets.last()? as usize;
+            let mut buffer = vec![0_u8; last + width];
+            handle.copy_address(block.base, &mut buffer).ok()?;
+            let mut next = Block {
+                base: block.base,
+                offsets: Vec::new(),
+                values: Vec::new(),
+            };
+            for (offset, previous) in block.offsets.iter().zip(block.values.chunks_exact(width)) {
+                let bytes = &buffer[*offset as usize..*offset as usize + width];
+                if condition.check(&template, bytes, previous, tolerance) {
+                    next.offsets.push(*offset);
+                    next.values.extend_from_slice(bytes);
+                }
+            }
+            (!next.offsets.is_empty()).then_some(next)
+        });
+        Ok(self.len())
+    }
+
+    /// Split `work` across the session's threads, collecting the non-empty blocks in address
+    /// order.
+    fn run<W, F>(&self, work: Vec<W>, scan: F) -> Vec<Block>
+    where
+        W: Send,
+        F: Fn(W) -> Option<Block> + Sync,
+    {
+        let threads = self.threads.min(work.len()).max(1);
+        let mut queues: Vec<Vec<W>> = (0..threads).map(|_| Vec::new()).collect();
+        for (index, item) in work.into_iter().enumerate() {
+            queues[index % threads].push(item);
+        }
+        let scan = &scan;
+        let mut blocks: Vec<Block> = std::thread::scope(|scope| {
+            let workers: Vec<_> = queues
+                .into_iter()
+                .map(|queue| {
+                    scope.spawn(move || {
+                        queue
+                            .into_iter()
+                            .filter_map(scan)
+                            .filter(|block| !block.offsets.is_empty())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        blocks.sort_unstable_by_key(|block| block.base);
+        blocks
+    }
+
+    /// The number of candidates left.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.iter().map(|block| block.offsets.len()).sum()
+    }
+
+    /// Returns `true` if there are no candidates left.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Discard every candidate, so that the next scan must be a first scan.
+    pub fn reset(&mut self) {
+        self.blocks.clear();
+        self.template = None;
+    }
+
+    /// The address of each candidate, in ascending order.
+    pub fn addresses(&self) -> impl Iterator<Item = usize> + '_ {
+        self.blocks.iter().flat_map(|block| {
+            block
+                .offsets
+                .iter()
+                .map(move |offset| block.base + *offset as usize)
+        })
+    }
+
+    /// The address of each candidate along with its value from the last scan.
+    pub fn results(&self) -> impl Iterator<Item = (usize, T)> + '_ {
+        let template = self.template.as_ref();
+        self.blocks.iter().flat_map(move |block| {
+            let width = block.values.len() / block.offsets.len();
+            block
+                .offsets
+                .iter()
+                .zip(block.values.chunks_exact(width))
+                .filter_map(move |(offset, bytes)| {
+                    Some((block.base + *offset as usize, template?.decode(bytes)))
+                })
+        })
+    }
+}
+
+impl<T: ScanValue + Copy> ScanSession<T> {
+    /// Create a [`DataMember`] for each candidate.
+    ///
+    /// [`DataMember`]: struct.DataMember.html
+    #[must_use]
+    pub fn to_members(&self) -> Vec<DataMember<T>> {
+        self.addresses()
+            .map(|address| DataMember::new_offset(self.handle, vec![address]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pid, TryIntoProcessHandle};
+
+    fn own_session<T: ScanValue>() -> ScanSession<T> {
+        let handle = (std::process::id() as Pid)
+            .try_into_process_handle()
+            .unwrap();
+        ScanSession::new(handle)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finds_values_across_block_boundaries() {
+        // A region of two blocks between guard pages, so that it is not merged with its
+        // neighbours and its blocks start where it does.
+        let page = 0x1000;
+        let len = 2 * BLOCK_SIZE + 2 * page;
+        let mapping = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(mapping, libc::MAP_FAILED);
+        let start = mapping as usize + page;
+        assert_eq!(
+            unsafe {
+                libc::mprotect(
+                    start as *mut _,
+                    2 * BLOCK_SIZE,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                )
+            },
+            0
+        );
+
+        let value = 0x0bad_cafe_f00d_d00d_u64;
+        let address = start + BLOCK_SIZE - 3;
+        unsafe { (address as *mut u64).write_unaligned(value) };
+        let mut session = own_session();
+        session.set_alignment(1);
+        session.first_scan(&ScanCondition::Exact(value)).unwrap();
+        let found: Vec<_> = session
+            .addresses()
+            .filter(|found| (start..start + 2 * BLOCK_SIZE).contains(found))
+            .collect();
+        unsafe { libc::munmap(mapping, len) };
+        assert_eq!(found, [address]);
+    }
+
+    #[test]
+    fn narrows_candidates_with_next_scans() {
+        let start = 0x3c1a_77e5_u32;
+        let mut values = vec![start; 3];
+        let base = values.as_mut_ptr();
+        let [increased, decreased, unchanged] = [0, 1, 2].map(|index| unsafe { base.add(index) });
+        let ours = |session: &ScanSession<u32>| -> Vec<usize> {
+            session
+                .addresses()
+                .filter(|&address| (base as usize..base as usize + 12).contains(&address))
+                .collect()
+        };
+
+        let mut session = own_session();
+        session.first_scan(&ScanCondition::Exact(start)).unwrap();
+        assert_eq!(
+            ours(&session),
+            [increased, decreased, unchanged].map(|v| v as usize)
+        );
+        unsafe {
+            increased.write_volatile(start + 1);
+            decreased.write_volatile(start - 1);
+        }
+        session.next_scan(&ScanCondition::Changed).unwrap();
+        assert_eq!(ours(&session), [increased as usize, decreased as usize]);
+        unsafe { increased.write_volatile(start + 2) };
+        session.next_scan(&ScanCondition::Increased).unwrap();
+        assert_eq!(ours(&session), [increased as usize]);
+        assert!(session
+            .results()
+            .any(|(address, value)| address == increased as usize && value == start + 2));
+
+        session.reset();
+        assert!(session.next_scan(&ScanCondition::Unchanged).is_err());
+        session.first_scan(&ScanCondition::Exact(start)).unwrap();
+        assert_eq!(ours(&session), [unchanged as usize]);
+        unsafe { unchanged.write_volatile(start) };
+        session.next_scan(&ScanCondition::Unchanged).unwrap();
+        assert_eq!(ours(&session), [unchanged as usize]);
+        std::hint::black_box(&values);
+    }
+}
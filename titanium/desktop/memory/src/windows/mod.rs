@@ -14,7 +14,10 @@ mod windows {
     };
 }
 
-use super::{Architecture, CopyAddress, ProcessHandleExt, PutAddress, TryIntoProcessHandle};
+use super::{
+    Architecture, CopyAddress, MemoryRegion, ProcessHandleExt, ProtectAddress, PutAddress,
+    TryIntoProcessHandle,
+};
 
 /// On Windows a `Pid` is a unsigned 32-bit integer.
 pub type Pid = u32;
@@ -119,4 +122,206 @@ impl PutAddress for ProcessHandle {
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+
+/// Region enumeration has not been implemented on Windows yet.
+pub(crate) fn regions(_handle: &ProcessHandle) -> std::io::Result<Vec<MemoryRegion>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Memory region enumeration is not supported on Windows",
+    ))
+}
+
+/// Windows reserves memory in blocks of 64 KiB.
+const ALLOCATION_GRANULARITY: usize = 0x1_0000;
+
+/// The `PAGE_*` protection constant for a set of permissions.
+fn page_protection(permissions: crate::Permissions) -> u32 {
+    use winapi::um::winnt::{
+        PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_NOACCESS, PAGE_READONLY,
+        PAGE_READWRITE,
+    };
+    match (permissions.read, permissions.write, permissions.execute) {
+        (_, true, true) => PAGE_EXECUTE_READWRITE,
+        (true, false, true) => PAGE_EXECUTE_READ,
+        (false, false, true) => PAGE_EXECUTE,
+        (_, true, false) => PAGE_READWRITE,
+        (true, false, false) => PAGE_READONLY,
+        (false, false, false) => PAGE_NOACCESS,
+    }
+}
+
+/// The raw `HANDLE` of a `ProcessHandle`, for use with `winapi`.
+fn raw_handle(handle: &ProcessHandle) -> winapi::um::winnt::HANDLE {
+    handle.0 .0 as winapi::um::winnt::HANDLE
+}
+
+/// Use `VirtualAllocEx` to allocate memory in another process on Windows.
+pub(crate) fn allocate(
+    handle: &ProcessHandle,
+    size: usize,
+    permissions: crate::Permissions,
+    near: Option<usize>,
+) -> std::io::Result<usize> {
+    use winapi::um::memoryapi::{VirtualAllocEx, VirtualQueryEx};
+    use winapi::um::winnt::{MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_FREE, MEM_RESERVE};
+
+    let process = raw_handle(handle);
+    let protection = page_protection(permissions);
+    let alloc = |address: usize| {
+        let result = unsafe {
+            VirtualAllocEx(
+                process,
+                address as *mut c_void as _,
+                size,
+                MEM_COMMIT | MEM_RESERVE,
+                protection,
+            )
+        };
+        if result.is_null() {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(result as usize)
+        }
+    };
+    let Some(near) = near else {
+        return alloc(0);
+    };
+
+    let low = near.saturating_sub(crate::allocation::NEAR_RANGE);
+    let high = near.saturating_add(crate::allocation::NEAR_RANGE);
+    let mut free = Vec::new();
+    let mut address = low.max(ALLOCATION_GRANULARITY);
+    while address < high {
+        let mut info: MEMORY_BASIC_INFORMATION = unsafe { std::mem::zeroed() };
+        let written = unsafe {
+            VirtualQueryEx(
+                process,
+                address as *const c_void as _,
+                &mut info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+        if written == 0 {
+            break;
+        }
+        let start = info.BaseAddress as usize;
+        let end = start + info.RegionSize;
+        if info.State == MEM_FREE {
+            free.push((start, end));
+        }
+        address = end;
+    }
+    for candidate in crate::allocation::near_candidates(free, near, size, ALLOCATION_GRANULARITY) {
+        if let Ok(address) = alloc(candidate) {
+            return Ok(address);
+        }
+    }
+    Err(crate::allocation::nothing_near(near))
+}
+
+/// Use `VirtualFreeEx` to free memory in another process on Windows.
+pub(crate) fn free(handle: &ProcessHandle, address: usize, _size: usize) -> std::io::Result<()> {
+    use winapi::um::memoryapi::VirtualFreeEx;
+    use winapi::um::winnt::MEM_RELEASE;
+
+    if unsafe { VirtualFreeEx(raw_handle(handle), address as *mut c_void as _, 0, MEM_RELEASE) } == 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// The permissions described by a `PAGE_*` protection constant.
+fn page_permissions(protection: u32) -> crate::Permissions {
+    use winapi::um::winnt::{
+        PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY,
+        PAGE_READONLY, PAGE_READWRITE, PAGE_WRITECOPY,
+    };
+    // The upper bits are modifiers such as `PAGE_GUARD`.
+    let (read, write, execute) = match protection & 0xff {
+        PAGE_READONLY => (true, false, false),
+        PAGE_READWRITE | PAGE_WRITECOPY => (true, true, false),
+        PAGE_EXECUTE => (false, false, true),
+        PAGE_EXECUTE_READ => (true, false, true),
+        PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY => (true, true, true),
+        _ => (false, false, false),
+    };
+    crate::Permissions {
+        read,
+        write,
+        execute,
+        shared: false,
+    }
+}
+
+/// Use `VirtualProtectEx` to change the protection of memory in another process on Windows.
+impl ProtectAddress for ProcessHandle {
+    fn protect_address(
+        &self,
+        addr: usize,
+        len: usize,
+        permissions: crate::Permissions,
+    ) -> std::io::Result<crate::Permissions> {
+        use winapi::um::memoryapi::VirtualProtectEx;
+
+        let mut previous = 0;
+        if unsafe {
+            VirtualProtectEx(
+                raw_handle(self),
+                addr as *mut c_void as _,
+                len,
+                page_protection(permissions),
+                &mut previous,
+            )
+        } == 0
+        {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(page_permissions(previous))
+        }
+    }
+}
+
+pub(crate) fn protect(
+    handle: &ProcessHandle,
+    address: usize,
+    len: usize,
+    permissions: crate::Permissions,
+) -> std::io::Result<()> {
+    handle.protect_address(address, len, permissions)?;
+    Ok(())
+}
+
+/// A handle to the current process.
+fn own_handle() -> ProcessHandle {
+    use winapi::um::processthreadsapi::GetCurrentProcess;
+
+    (
+        windows::HANDLE(unsafe { GetCurrentProcess() } as isize),
+        Architecture::from_native(),
+    )
+}
+
+/// Allocate memory in the current process.
+pub(crate) fn allocate_local(
+    size: usize,
+    permissions: crate::Permissions,
+    near: Option<usize>,
+) -> std::io::Result<usize> {
+    allocate(&own_handle(), size, permissions, near)
+}
+
+/// Free memory in the current process.
+pub(crate) fn free_local(address: usize, size: usize) -> std::io::Result<()> {
+    free(&own_handle(), address, size)
+}
+
+/// Change the permissions of memory in the current process.
+pub(crate) fn protect_local(
+    address: usize,
+    len: usize,
+    permissions: crate::Permissions,
+) -> std::io::Result<crate::Permissions> {
+    own_handle().protect_address(address, len, permissions)
+}
@@ -0,0 +1,111 @@
+//! Reads and writes structs in the current process through `#[derive(RemoteStruct)]`.
+
+use macros::RemoteStruct;
+use titanium_desktop_memory::{LocalProcess, MemoryError, RemoteField, RemotePtr, RemoteStruct};
+
+#[repr(C)]
+struct LocalWeapon {
+    id: u32,
+    damage: i32,
+}
+
+#[repr(C)]
+struct LocalPlayer {
+    padding: [u8; 0x30],
+    health: f32,
+    position: [f32; 3],
+    weapon: *const LocalWeapon,
+    alive: bool,
+}
+
+#[derive(Debug, RemoteStruct)]
+#[remote(crate = "titanium_desktop_memory")]
+struct Weapon {
+    #[remote(offset = 4)]
+    damage: i32,
+}
+
+#[derive(Debug, RemoteStruct)]
+#[remote(crate = "titanium_desktop_memory", size = 0x50)]
+struct Player {
+    #[remote(offset = 0x30)]
+    health: f32,
+    #[remote(offset = 0x34)]
+    position: [f32; 3],
+    #[remote(offset = 0x40, ptr)]
+    weapon: RemotePtr<Weapon>,
+    #[remote(offset = 0x40, ptr)]
+    weapon_address: usize,
+    #[remote(offset = 0x48)]
+    alive: bool,
+    #[remote(skip)]
+    note: String,
+}
+
+#[derive(Debug, RemoteStruct)]
+#[remote(crate = "titanium_desktop_memory", size = 4)]
+struct Truncated {
+    #[remote(offset = 0x30)]
+    health: f32,
+}
+
+fn player(weapon: &LocalWeapon) -> LocalPlayer {
+    LocalPlayer {
+        padding: [0xAA; 0x30],
+        health: 87.5,
+        position: [1.0, 2.0, 3.0],
+        weapon,
+        alive: true,
+    }
+}
+
+#[test]
+fn reads_fields_and_follows_pointers() {
+    let weapon = LocalWeapon { id: 9, damage: 42 };
+    let local = player(&weapon);
+    let address = std::ptr::addr_of!(local) as usize;
+
+    let player = Player::read(&LocalProcess, address).unwrap();
+    assert_eq!(player.health, 87.5);
+    assert_eq!(player.position, [1.0, 2.0, 3.0]);
+    assert_eq!(player.weapon.address(), std::ptr::addr_of!(weapon) as usize);
+    assert_eq!(player.weapon_address, player.weapon.address());
+    assert!(player.alive);
+    assert!(player.note.is_empty());
+    assert_eq!(player.weapon.read(&LocalProcess).unwrap().damage, 42);
+    let null = RemotePtr::<Weapon>::default().read(&LocalProcess).unwrap_err();
+    assert!(matches!(MemoryError::from(null), MemoryError::NullPointer { level: 0 }));
+}
+
+#[test]
+fn writes_single_fields_and_whole_structs() {
+    let weapon = LocalWeapon { id: 9, damage: 42 };
+    let mut local = player(&weapon);
+    let address = std::ptr::addr_of_mut!(local) as usize;
+
+    Player::write_health(&LocalProcess, address, &100.0).unwrap();
+    let mut player = Player::read(&LocalProcess, address).unwrap();
+    Weapon::write_damage(&LocalProcess, player.weapon.address(), &7).unwrap();
+    player.position = [9.0, 8.0, 7.0];
+    player.alive = false;
+    player.write(&LocalProcess, address).unwrap();
+
+    let local = unsafe { std::ptr::read_volatile(std::ptr::addr_of!(local)) };
+    let weapon = unsafe { std::ptr::read_volatile(std::ptr::addr_of!(weapon)) };
+    assert_eq!(local.health, 100.0);
+    assert_eq!(local.position, [9.0, 8.0, 7.0]);
+    assert!(!local.alive);
+    assert_eq!(local.padding, [0xAA; 0x30]);
+    assert_eq!((weapon.id, weapon.damage), (9, 7));
+}
+
+#[test]
+fn extends_a_size_that_is_too_small() {
+    let weapon = LocalWeapon { id: 9, damage: 42 };
+    let local = player(&weapon);
+    let arch = titanium_desktop_memory::Architecture::from_native();
+    assert_eq!(<Truncated as RemoteField>::remote_size(arch), 0x34);
+    assert_eq!(<Player as RemoteField>::remote_size(arch), 0x50);
+    let truncated = Truncated::read(&LocalProcess, std::ptr::addr_of!(local) as usize).unwrap();
+    assert_eq!(truncated.health, 87.5);
+}
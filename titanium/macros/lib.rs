@@ -8,7 +8,7 @@ extern crate proc_macro;
 #[doc(hidden)]
 use proc_macro::TokenStream;
 #[doc(hidden)]
-use quote::{quote, quote_spanned};
+use quote::{format_ident, quote, quote_spanned};
 #[doc(hidden)]
 use syn::spanned::Spanned;
 
@@ -51,4 +51,193 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
     };
 
     result.into()
-}
\ No newline at end of file
+}
+
+/// The settings of a field of a `#[derive(RemoteStruct)]` struct.
+struct RemoteFieldAttributes {
+    offset: Option<syn::LitInt>,
+    ptr: bool,
+    skip: bool,
+}
+
+fn remote_field_attributes(field: &syn::Field) -> syn::Result<RemoteFieldAttributes> {
+    let mut attributes = RemoteFieldAttributes {
+        offset: None,
+        ptr: false,
+        skip: false,
+    };
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("remote")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("offset") {
+                attributes.offset = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("ptr") {
+                attributes.ptr = true;
+            } else if meta.path.is_ident("skip") {
+                attributes.skip = true;
+            } else {
+                return Err(meta.error("expected `offset`, `ptr` or `skip`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(attributes)
+}
+
+fn derive_remote_struct_impl(input: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut krate: syn::Path = syn::parse_quote!(titanium::desktop::memory);
+    let mut size = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("remote")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse::<syn::LitStr>()?.parse()?;
+            } else if meta.path.is_ident("size") {
+                size = Some(meta.value()?.parse::<syn::LitInt>()?);
+            } else {
+                return Err(meta.error("expected `crate` or `size`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let fields = match &input.data {
+        syn::Data::Struct(syn::DataStruct {
+            fields: syn::Fields::Named(fields),
+            ..
+        }) => &fields.named,
+        _ => {
+            return Err(syn::Error::new(
+                input.span(),
+                "RemoteStruct can only be derived for structs with named fields",
+            ))
+        }
+    };
+
+    let vis = &input.vis;
+    let mut sizes = Vec::new();
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    let mut field_writers = Vec::new();
+    for field in fields {
+        let attributes = remote_field_attributes(field)?;
+        let name = field.ident.as_ref().expect("named fields have names");
+        let ty = &field.ty;
+        if attributes.skip {
+            reads.push(quote! { #name: ::std::default::Default::default() });
+            continue;
+        }
+        let Some(offset) = attributes.offset else {
+            return Err(syn::Error::new(
+                field.span(),
+                "fields of a RemoteStruct need `#[remote(offset = ...)]` or `#[remote(skip)]`",
+            ));
+        };
+        let writer = format_ident!("write_{}", name);
+        let doc = format!("Write only the `{name}` field of the struct at `address`.");
+        if attributes.ptr {
+            sizes.push(quote! {
+                #offset + <#krate::RemotePtr<()> as #krate::RemoteField>::remote_size(arch)
+            });
+            reads.push(quote! { #name: #krate::read_remote_pointer::<#ty>(&bytes[#offset..], arch) });
+            writes.push(quote! { #krate::write_remote_pointer(&self.#name, &mut bytes[#offset..], arch); });
+            field_writers.push(quote! {
+                #[doc = #doc]
+                ///
+                /// # Errors
+                /// Returns an error if the field cannot be written.
+                #vis fn #writer<H: #krate::CopyAddress + #krate::PutAddress + ?Sized>(
+                    handle: &H,
+                    address: usize,
+                    value: &#ty,
+                ) -> ::std::io::Result<()> {
+                    #krate::write_remote_pointer_field(handle, address.wrapping_add(#offset), value)
+                }
+            });
+        } else {
+            sizes.push(quote! { #offset + <#ty as #krate::RemoteField>::remote_size(arch) });
+            reads.push(quote! {
+                #name: <#ty as #krate::RemoteField>::from_bytes(&bytes[#offset..], arch)
+            });
+            writes.push(quote! {
+                #krate::RemoteField::write_bytes(&self.#name, &mut bytes[#offset..], arch);
+            });
+            field_writers.push(quote! {
+                #[doc = #doc]
+                ///
+                /// # Errors
+                /// Returns an error if the field cannot be written.
+                #vis fn #writer<H: #krate::CopyAddress + #krate::PutAddress + ?Sized>(
+                    handle: &H,
+                    address: usize,
+                    value: &#ty,
+                ) -> ::std::io::Result<()> {
+                    #krate::write_remote_field(handle, address.wrapping_add(#offset), value)
+                }
+            });
+        }
+    }
+
+    // An explicit size can only make the span longer, so that a size that is too small cannot
+    // make `from_bytes` slice past the end of the bytes.
+    let size = match size {
+        Some(size) => quote! { #size },
+        None => quote! { 0 },
+    };
+    let size = quote! {{
+        let size: usize = #size;
+        size #(.max(#sizes))*
+    }};
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::RemoteField for #name #ty_generics #where_clause {
+            fn remote_size(arch: #krate::Architecture) -> usize {
+                #size
+            }
+
+            fn from_bytes(bytes: &[u8], arch: #krate::Architecture) -> Self {
+                Self { #(#reads,)* }
+            }
+
+            fn write_bytes(&self, bytes: &mut [u8], arch: #krate::Architecture) {
+                #(#writes)*
+            }
+        }
+
+        impl #impl_generics #krate::RemoteStruct for #name #ty_generics #where_clause {}
+
+        #[allow(dead_code)]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#field_writers)*
+        }
+    })
+}
+
+/// Derives `RemoteStruct` for a struct that mirrors one in another process, so that it can be
+/// read with a single copy of its whole span.
+///
+/// Every field needs `#[remote(offset = ...)]`, the offset of the field from the start of the
+/// struct. Fields that hold an address, such as `RemotePtr<T>` or `usize`, are marked with `ptr`
+/// to decode them as a pointer of the width of the target process, and fields that are not in the
+/// process are marked with `skip` and set to their `Default`. A `write_<field>` function is
+/// generated for every field, which writes just that field.
+///
+/// The span is the end of the last field, unless a larger one is set with `#[remote(size = ...)]`
+/// on the struct. The generated code refers to `titanium::desktop::memory`, which can be changed with
+/// `#[remote(crate = "...")]`, such as to use the memory crate directly.
+///
+/// ```rust,ignore
+/// #[derive(titanium::RemoteStruct)]
+/// struct Player {
+///     #[remote(offset = 0x30)]
+///     health: f32,
+///     #[remote(offset = 0x80, ptr)]
+///     weapon: RemotePtr<Weapon>,
+/// }
+/// ```
+#[proc_macro_derive(RemoteStruct, attributes(remote))]
+pub fn derive_remote_struct(item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::DeriveInput);
+    derive_remote_struct_impl(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}